@@ -1,22 +1,167 @@
 
 use rand::{Rng, rngs::StdRng};
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 pub trait Game : Clone {
-  type Action : Debug;
+  type Action : Debug + Clone + PartialEq;
 
   fn possible_actions(&self, actions : &mut Vec<Self::Action>);
   fn active_player(&self) -> i64;
   fn apply_action(&mut self, a : &Self::Action);
   fn player_score(&self, player : i64) -> f64;
   fn winner(&self) -> Option<i64>;
+
+  /// The number of players taking part
+  fn num_players(&self) -> i64 {
+    2
+  }
+
+  /// The result of the game for each player, in player order. By default
+  /// the winner gets 1 and everyone else gets 0, and a game without a
+  /// winner is shared equally.
+  fn payoffs(&self) -> Vec<f64> {
+    let n = self.num_players();
+    match self.winner() {
+      Some(w) => (0..n).map(|p| if p == w { 1.0 } else { 0.0 }).collect(),
+      None => vec![1.0 / n as f64 ; n as usize],
+    }
+  }
+
+  /// Returns true if the game ended in a draw. A game that stops
+  /// without a winner and without a draw is unfinished.
+  fn is_draw(&self) -> bool {
+    false
+  }
 }
 
-pub trait GameAgent<G : Game> : Clone {
+/// The companion to `Game` for games with random events, such as dice
+/// rolls or card deals, which agents can sample or average over.
+/// Deterministic games implement it with the defaults, and never
+/// reach a chance node.
+pub trait Stochastic : Game {
+  /// Returns true if the next event is random, rather than chosen by
+  /// the active player. At a chance node `possible_actions` is empty,
+  /// and the outcomes come from `chance_outcomes` instead.
+  fn is_chance_node(&self) -> bool {
+    false
+  }
+
+  /// Lists the outcomes of a chance node, each paired with its
+  /// probability. The probabilities should sum to one.
+  fn chance_outcomes(&self, _outcomes : &mut Vec<(Self::Action, f64)>) {}
+
+  /// Picks one outcome of a chance node, weighted by its probability
+  fn sample_chance(&self, rng : &mut StdRng) -> Option<Self::Action> {
+    let mut outcomes = vec![];
+    self.chance_outcomes(&mut outcomes);
+    let last = outcomes.len().checked_sub(1)?;
+    let mut r : f64 = rng.gen();
+    for (i, (a, p)) in outcomes.into_iter().enumerate() {
+      if r < p || i == last {
+        return Some(a);
+      }
+      r -= p;
+    }
+    None
+  }
+}
+
+/// A game where players can't see the whole state, such as a card game
+/// with hidden hands. Agents that only see their own observation can't cheat.
+pub trait HiddenInformation : Stochastic {
+  /// Everything that one player can see of the game
+  type Observation : Clone + Debug;
+
+  fn observe(&self, player : i64) -> Self::Observation;
+
+  /// Samples a complete state that is consistent with what the observing
+  /// player can see, by guessing at the parts that are hidden from them.
+  /// The state is drawn from the distribution that the game's chance
+  /// events would produce, ignoring anything the opponents' moves reveal.
+  fn determinize(observation : &Self::Observation, rng : &mut StdRng) -> Self;
+}
+
+pub trait GameAgent<G : Game> {
   fn choose_action(&mut self, game: &G, rng: &mut StdRng) -> Option<G::Action>;
+
+  /// A short description of the agent, for game records
+  fn name(&self) -> String {
+    "Agent".into()
+  }
+
+  /// Like `choose_action`, but thinks for as long as `limits` allow,
+  /// calling `report` whenever it has something new to say about its
+  /// search. By default the limits are ignored and nothing is reported.
+  fn choose_action_limited(
+    &mut self, game : &G, rng : &mut StdRng, _limits : &SearchLimits,
+    _report : &mut dyn FnMut(&SearchInfo<G::Action>)) -> Option<G::Action>
+  {
+    self.choose_action(game, rng)
+  }
+
+  /// The names and current values of the settings that can be
+  /// changed with `set_parameter`
+  fn parameters(&self) -> Vec<(&'static str, String)> {
+    vec![]
+  }
+
+  /// Changes a setting by name. Returns false if there's no such
+  /// setting, or the value can't be read.
+  fn set_parameter(&mut self, _name : &str, _value : &str) -> bool {
+    false
+  }
+}
+
+/// How long an agent may think for in `choose_action_limited`. Agents
+/// stop at whichever limit they reach first, and without any limits
+/// they search until they are stopped or have nothing left to search.
+#[derive(Clone, Default)]
+pub struct SearchLimits {
+  /// The number of player decisions to look ahead
+  pub depth : Option<i64>,
+  /// The number of positions to visit
+  pub nodes : Option<u64>,
+  pub deadline : Option<Instant>,
+  /// Can be set from another thread to stop the search
+  pub stop : Arc<AtomicBool>,
+}
+
+impl SearchLimits {
+  /// True if there's no limit on the nodes or the time, so an agent
+  /// that doesn't search by depth has no way to know when to stop
+  pub fn is_open_ended(&self) -> bool {
+    self.nodes.is_none() && self.deadline.is_none()
+  }
+
+  /// True if the search has visited `nodes` positions, or
+  /// run out of time, or been stopped
+  pub fn is_reached(&self, nodes : u64) -> bool {
+    self.nodes.is_some_and(|n| nodes >= n)
+      || self.deadline.is_some_and(|d| Instant::now() >= d)
+      || self.stop.load(Ordering::Relaxed)
+  }
+}
+
+/// A progress report from an agent's search
+#[derive(Clone, Debug)]
+pub struct SearchInfo<A> {
+  /// How far ahead the agent has looked
+  pub depth : i64,
+  /// The number of positions visited so far
+  pub nodes : u64,
+  /// The agent's estimate of the active player's `player_score`
+  pub score : f64,
+  /// The line of play that the agent expects, starting with its choice
+  pub line : Vec<A>,
 }
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RandomAgent {}
 
 impl <G : Game> GameAgent<G> for RandomAgent {
@@ -31,19 +176,16 @@ impl <G : Game> GameAgent<G> for RandomAgent {
       None
     }
   }
+
+  fn name(&self) -> String {
+    "Random".into()
+  }
 }
 
-/// Takes one action for whichever player has the next turn.
-/// Returns true if an action was taken.
-pub fn agent_action<A, B, G>(a : &mut A, b : &mut B, g : &mut G, rng: &mut StdRng) -> bool
-  where A : GameAgent<G>, B : GameAgent<G>, G : Game
-{
-  let a = match g.active_player() {
-    0 => a.choose_action(g, rng),
-    1 => b.choose_action(g, rng),
-    _ => panic!("no agent found for player"),
-  };
-  if let Some(a) = a {
+/// Samples and applies the outcome of a chance node.
+/// Returns true if an outcome was applied.
+pub fn chance_action<G : Stochastic>(g : &mut G, rng : &mut StdRng) -> bool {
+  if let Some(a) = g.sample_chance(rng) {
     g.apply_action(&a);
     true
   }
@@ -52,15 +194,101 @@ pub fn agent_action<A, B, G>(a : &mut A, b : &mut B, g : &mut G, rng: &mut StdRn
   }
 }
 
+/// Takes one action for whichever player has the next turn, or
+/// resolves the chance node if the next event is random. The agent
+/// for player `i` is `agents[i]`.
+/// Returns true if an action was taken.
+pub fn agent_action<G : Stochastic>(agents : &mut [&mut dyn GameAgent<G>], g : &mut G, rng: &mut StdRng) -> bool {
+  take_action(agents, g, rng).is_some()
+}
+
+/// Like `agent_action`, but returns the action that was taken,
+/// so that it can be recorded
+pub fn take_action<G : Stochastic>(agents : &mut [&mut dyn GameAgent<G>], g : &mut G, rng: &mut StdRng) -> Option<G::Action> {
+  let a = if g.is_chance_node() {
+    g.sample_chance(rng)
+  }
+  else {
+    match agents.get_mut(g.active_player() as usize) {
+      Some(agent) => agent.choose_action(g, rng),
+      None => panic!("no agent found for player"),
+    }
+  };
+  if let Some(a) = &a {
+    g.apply_action(a);
+  }
+  a
+}
+
+/// The actions available at this point of the game: the possible
+/// actions, or the outcomes of a chance node
+pub fn available_actions<G : Stochastic>(game : &G) -> Vec<G::Action> {
+  let mut actions = vec![];
+  if game.is_chance_node() {
+    let mut outcomes = vec![];
+    game.chance_outcomes(&mut outcomes);
+    actions.extend(outcomes.into_iter().map(|(a, _)| a));
+  }
+  else {
+    game.possible_actions(&mut actions);
+  }
+  actions
+}
+
+/// Counts the sequences of `depth` actions from this position, which is
+/// useful for checking move generation against known numbers. Chance
+/// outcomes each count as an action. Sequences that reach the end of
+/// the game early aren't counted.
+pub fn perft<G : Stochastic>(game : &G, depth : i64) -> u64 {
+  if depth == 0 {
+    return 1;
+  }
+  let actions = available_actions(game);
+  if depth == 1 {
+    return actions.len() as u64;
+  }
+  actions.iter().map(|a| {
+    let mut g = game.clone();
+    g.apply_action(a);
+    perft(&g, depth - 1)
+  }).sum()
+}
+
+/// An agent for hidden information games, which chooses its
+/// action based only on what its player can see
+pub trait ObservingAgent<G : HiddenInformation> {
+  fn choose_action(&mut self, observation : &G::Observation, rng : &mut StdRng) -> Option<G::Action>;
+}
+
+/// Wraps an `ObservingAgent` so that it can play like any other agent.
+/// It is only shown the active player's observation of the game.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Observer<A>(pub A);
+
+impl <G : HiddenInformation, A : ObservingAgent<G>> GameAgent<G> for Observer<A> {
+  fn choose_action(&mut self, game : &G, rng : &mut StdRng) -> Option<G::Action> {
+    let observation = game.observe(game.active_player());
+    self.0.choose_action(&observation, rng)
+  }
+}
+
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RolloutAgent {
   pub iterations : i64,
   pub depth : i64,
 }
 
-fn rollout<G : Game>(game : &mut G, rng : &mut StdRng, max_depth : i64) {
+fn rollout<G : Stochastic>(game : &mut G, rng : &mut StdRng, max_depth : i64) {
   let mut actions = vec![];
   for _ in 0..max_depth {
+    if game.is_chance_node() {
+      if chance_action(game, rng) {
+        continue;
+      }
+      break;
+    }
     actions.clear();
     game.possible_actions(&mut actions);
     if actions.len() > 0 {
@@ -73,7 +301,7 @@ fn rollout<G : Game>(game : &mut G, rng : &mut StdRng, max_depth : i64) {
   }
 }
 
-impl <G : Game> GameAgent<G> for RolloutAgent {
+impl <G : Stochastic> GameAgent<G> for RolloutAgent {
 
   fn choose_action(&mut self, game : &G, rng : &mut StdRng) -> Option<G::Action> {
     let player = game.active_player();
@@ -96,4 +324,412 @@ impl <G : Game> GameAgent<G> for RolloutAgent {
     }
     best_action
   }
+
+  /// Plays rounds of one rollout after each action until the limits
+  /// are reached, or `iterations` rounds if there's no limit on the
+  /// nodes or the time. Each rollout counts as a node.
+  fn choose_action_limited(
+    &mut self, game : &G, rng : &mut StdRng, limits : &SearchLimits,
+    report : &mut dyn FnMut(&SearchInfo<G::Action>)) -> Option<G::Action>
+  {
+    let player = game.active_player();
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    let mut scores = vec![0.0 ; actions.len()];
+    let mut counts = vec![0 ; actions.len()];
+    // The action with the best average score so far
+    let best = |scores : &[f64], counts : &[i64]| {
+      let mut best : Option<(usize, f64)> = None;
+      for (i, (&score, &count)) in scores.iter().zip(counts).enumerate() {
+        let average = score / count as f64;
+        if count > 0 && best.is_none_or(|(_, b)| average > b) {
+          best = Some((i, average));
+        }
+      }
+      best
+    };
+    let (mut nodes, mut rounds, mut next_report, mut reported_nodes) = (0, 0, 1, 0);
+    'rounds: while !actions.is_empty() && (rounds < self.iterations || !limits.is_open_ended()) {
+      for (i, a) in actions.iter().enumerate() {
+        if limits.is_reached(nodes) {
+          break 'rounds;
+        }
+        let mut game = game.clone();
+        game.apply_action(a);
+        rollout(&mut game, rng, self.depth);
+        scores[i] += game.player_score(player);
+        counts[i] += 1;
+        nodes += 1;
+      }
+      rounds += 1;
+      // Report less often as the averages settle down
+      if rounds == next_report {
+        if let Some((i, score)) = best(&scores, &counts) {
+          report(&SearchInfo { depth: self.depth, nodes, score, line: vec![actions[i].clone()] });
+          reported_nodes = nodes;
+        }
+        next_report *= 2;
+      }
+    }
+    match best(&scores, &counts) {
+      Some((i, score)) => {
+        // The best action may have changed since the last report
+        if nodes != reported_nodes {
+          report(&SearchInfo { depth: self.depth, nodes, score, line: vec![actions[i].clone()] });
+        }
+        Some(actions.swap_remove(i))
+      }
+      None => actions.into_iter().next(),
+    }
+  }
+
+  fn name(&self) -> String {
+    format!("Rollout ({} iterations, depth {})", self.iterations, self.depth)
+  }
+
+  fn parameters(&self) -> Vec<(&'static str, String)> {
+    vec![("iterations", self.iterations.to_string()), ("depth", self.depth.to_string())]
+  }
+
+  fn set_parameter(&mut self, name : &str, value : &str) -> bool {
+    match name {
+      "iterations" => value.parse().map(|v| self.iterations = v).is_ok(),
+      "depth" => value.parse().map(|v| self.depth = v).is_ok(),
+      _ => false,
+    }
+  }
+}
+
+/// Like `RolloutAgent`, but for hidden information games. It runs its
+/// rollouts on several determinizations of what it can see, and totals
+/// the score of each action across them. This assumes that the order
+/// of `possible_actions` is the same in every determinization.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeterminizedRolloutAgent {
+  pub determinizations : i64,
+  pub iterations : i64,
+  pub depth : i64,
+}
+
+impl <G : HiddenInformation> ObservingAgent<G> for DeterminizedRolloutAgent {
+
+  fn choose_action(&mut self, observation : &G::Observation, rng : &mut StdRng) -> Option<G::Action> {
+    let mut actions = vec![];
+    let mut scores = vec![];
+    let mut best_action = None;
+    for _ in 0..self.determinizations {
+      let game = G::determinize(observation, rng);
+      let player = game.active_player();
+      actions.clear();
+      game.possible_actions(&mut actions);
+      scores.resize(actions.len(), 0.0);
+      for (i, a) in actions.iter().enumerate() {
+        for _ in 0..self.iterations {
+          let mut game = game.clone();
+          game.apply_action(a);
+          rollout(&mut game, rng, self.depth);
+          scores[i] += game.player_score(player);
+        }
+      }
+    }
+    let mut best_score = -99999999999999.0;
+    for (a, score) in actions.into_iter().zip(scores) {
+      if score > best_score {
+        best_score = score;
+        best_action = Some(a);
+      }
+    }
+    best_action
+  }
+}
+
+/// Searches the game tree to a fixed depth, taking the probability-weighted
+/// average over the outcomes of each chance node (expectimax). Chance nodes
+/// are pruned with Ballard's Star1 algorithm, and optionally Star2, which
+/// rely on `player_score` lying between `min_score` and `max_score`.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExpectimaxAgent {
+  /// The number of player decisions to search. Chance events are free.
+  pub depth : i64,
+  pub min_score : f64,
+  pub max_score : f64,
+  /// Probe one successor of each chance outcome before searching them fully
+  pub star2 : bool,
+}
+
+/// The deepest that `choose_action_limited` will search
+const MAX_SEARCH_DEPTH : i64 = 64;
+
+/// Counts the nodes that a search visits, and notices when it has
+/// to stop. The values found after that point are meaningless.
+struct Budget<'a> {
+  limits : Option<&'a SearchLimits>,
+  nodes : u64,
+  exhausted : bool,
+  /// Set when the depth runs out before the end of the game, so that
+  /// searching deeper could find something new
+  horizon : bool,
+}
+
+impl <'a> Budget<'a> {
+  fn new(limits : Option<&'a SearchLimits>) -> Budget<'a> {
+    Budget { limits, nodes: 0, exhausted: false, horizon: false }
+  }
+
+  /// Counts a node, and returns true if the search has to stop
+  fn visit(&mut self) -> bool {
+    self.nodes += 1;
+    if let Some(limits) = self.limits {
+      self.exhausted = self.exhausted || limits.is_reached(self.nodes);
+    }
+    self.exhausted
+  }
+}
+
+/// A range of values. Searches use it as their alpha-beta window,
+/// and chance nodes use it for the bounds on each outcome's value.
+#[derive(Copy, Clone)]
+struct Window {
+  lower : f64,
+  upper : f64,
+}
+
+impl ExpectimaxAgent {
+
+  /// The widest window, which any value fits in
+  fn full_window(&self) -> Window {
+    Window { lower: self.min_score, upper: self.max_score }
+  }
+
+  fn evaluate<G : Game>(&self, game : &G, player : i64) -> f64 {
+    game.player_score(player).max(self.min_score).min(self.max_score)
+  }
+
+  /// Returns the value of `game` for `player`, and sets `line` to the
+  /// actions that lead to it, up to the next chance node. Like alpha-beta,
+  /// a value at or below `window.lower` is only an upper bound, and a value
+  /// at or above `window.upper` is only a lower bound.
+  fn search<G : Stochastic>(
+    &self, game : &G, player : i64, depth : i64, mut window : Window,
+    budget : &mut Budget, line : &mut Vec<G::Action>) -> f64
+  {
+    line.clear();
+    if budget.visit() {
+      return self.evaluate(game, player);
+    }
+    if game.is_chance_node() {
+      return self.chance_search(game, player, depth, window, budget);
+    }
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if depth <= 0 || actions.is_empty() {
+      budget.horizon = budget.horizon || !actions.is_empty();
+      return self.evaluate(game, player);
+    }
+    let maximising = game.active_player() == player;
+    let mut best = if maximising { self.min_score } else { self.max_score };
+    let mut child_line = vec![];
+    for a in actions.iter() {
+      let mut game = game.clone();
+      game.apply_action(a);
+      let v = self.search(&game, player, depth - 1, window, budget, &mut child_line);
+      if line.is_empty() || (maximising && v > best) || (!maximising && v < best) {
+        best = v;
+        line.clear();
+        line.push(a.clone());
+        line.extend(child_line.iter().cloned());
+      }
+      if maximising {
+        window.lower = window.lower.max(v);
+      }
+      else {
+        window.upper = window.upper.min(v);
+      }
+      if window.lower >= window.upper {
+        break;
+      }
+    }
+    best
+  }
+
+  fn chance_search<G : Stochastic>(&self, game : &G, player : i64, depth : i64, window : Window, budget : &mut Budget) -> f64 {
+    let mut outcomes = vec![];
+    game.chance_outcomes(&mut outcomes);
+    let mut children = vec![];
+    for (a, p) in outcomes {
+      let mut game = game.clone();
+      game.apply_action(&a);
+      children.push((game, p));
+    }
+    // Bounds on the value of each outcome
+    let mut bounds = vec![self.full_window() ; children.len()];
+    if self.star2 {
+      if let Some(v) = self.probe(&children, player, depth, window, &mut bounds, budget) {
+        return v;
+      }
+    }
+    // Star1: narrow the window of each outcome using the bounds of the
+    // outcomes that haven't been searched yet
+    let mut lower_rest : f64 = children.iter().zip(bounds.iter()).map(|((_, p), b)| p * b.lower).sum();
+    let mut upper_rest : f64 = children.iter().zip(bounds.iter()).map(|((_, p), b)| p * b.upper).sum();
+    let mut total = 0.0;
+    let mut line = vec![];
+    for (i, (child, p)) in children.iter().enumerate() {
+      lower_rest -= p * bounds[i].lower;
+      upper_rest -= p * bounds[i].upper;
+      let child_window = Window {
+        lower: ((window.lower - total - upper_rest) / p).max(self.min_score),
+        upper: ((window.upper - total - lower_rest) / p).min(self.max_score),
+      };
+      total += p * self.search(child, player, depth, child_window, budget, &mut line);
+      if total + upper_rest <= window.lower {
+        return total + upper_rest;
+      }
+      if total + lower_rest >= window.upper {
+        return total + lower_rest;
+      }
+    }
+    total
+  }
+
+  /// Star2: searches only the first move of each outcome. If that move
+  /// belongs to the searching player it gives a lower bound on the outcome,
+  /// and otherwise it gives an upper bound. Returns a value if the bounds
+  /// alone are enough to cut off the chance node.
+  fn probe<G : Stochastic>(
+    &self, children : &[(G, f64)], player : i64, depth : i64, window : Window,
+    bounds : &mut [Window], budget : &mut Budget) -> Option<f64>
+  {
+    let mut actions = vec![];
+    let mut line = vec![];
+    for (i, (child, _)) in children.iter().enumerate() {
+      if child.is_chance_node() {
+        continue;
+      }
+      actions.clear();
+      child.possible_actions(&mut actions);
+      if depth <= 0 || actions.is_empty() {
+        budget.horizon = budget.horizon || !actions.is_empty();
+        let v = self.evaluate(child, player);
+        bounds[i] = Window { lower: v, upper: v };
+        continue;
+      }
+      let mut child = child.clone();
+      child.apply_action(&actions[0]);
+      let v = self.search(&child, player, depth - 1, self.full_window(), budget, &mut line);
+      if children[i].0.active_player() == player {
+        bounds[i].lower = v;
+      }
+      else {
+        bounds[i].upper = v;
+      }
+    }
+    let lower_total : f64 = children.iter().zip(bounds.iter()).map(|((_, p), b)| p * b.lower).sum();
+    let upper_total : f64 = children.iter().zip(bounds.iter()).map(|((_, p), b)| p * b.upper).sum();
+    if lower_total >= window.upper {
+      Some(lower_total)
+    }
+    else if upper_total <= window.lower {
+      Some(upper_total)
+    }
+    else {
+      None
+    }
+  }
+
+  /// Searches each action to `depth`, starting with `first` if it's
+  /// available. Returns the best value, and the line that leads to it,
+  /// which is empty if the budget ran out before any action was searched.
+  fn search_root<G : Stochastic>(&self, game : &G, depth : i64, first : Option<&G::Action>, budget : &mut Budget)
+    -> (f64, Vec<G::Action>)
+  {
+    let player = game.active_player();
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if let Some(i) = first.and_then(|f| actions.iter().position(|a| a == f)) {
+      let a = actions.remove(i);
+      actions.insert(0, a);
+    }
+    let mut alpha = self.min_score;
+    let mut line = vec![];
+    let mut child_line = vec![];
+    for a in actions {
+      let mut game = game.clone();
+      game.apply_action(&a);
+      let window = Window { lower: alpha, upper: self.max_score };
+      let v = self.search(&game, player, depth - 1, window, budget, &mut child_line);
+      if budget.exhausted {
+        break;
+      }
+      if line.is_empty() || v > alpha {
+        alpha = v;
+        line.clear();
+        line.push(a);
+        line.append(&mut child_line);
+      }
+    }
+    (alpha, line)
+  }
+}
+
+impl <G : Stochastic> GameAgent<G> for ExpectimaxAgent {
+
+  fn choose_action(&mut self, game : &G, _rng : &mut StdRng) -> Option<G::Action> {
+    let (_, line) = self.search_root(game, self.depth, None, &mut Budget::new(None));
+    line.into_iter().next()
+  }
+
+  /// Searches one level deeper at a time, starting with the best action
+  /// from the last level, until the limits are reached, the whole game
+  /// tree has been searched, or an action reaches `max_score`. A level
+  /// that is cut short is ignored, unless it's the first.
+  fn choose_action_limited(
+    &mut self, game : &G, _rng : &mut StdRng, limits : &SearchLimits,
+    report : &mut dyn FnMut(&SearchInfo<G::Action>)) -> Option<G::Action>
+  {
+    let mut budget = Budget::new(Some(limits));
+    let mut best = None;
+    for depth in 1..=limits.depth.unwrap_or(MAX_SEARCH_DEPTH) {
+      budget.horizon = false;
+      let (score, line) = self.search_root(game, depth, best.as_ref(), &mut budget);
+      if budget.exhausted {
+        best = best.or_else(|| line.into_iter().next());
+        break;
+      }
+      best = line.first().cloned();
+      if best.is_none() {
+        break;
+      }
+      report(&SearchInfo { depth, nodes: budget.nodes, score, line });
+      if !budget.horizon || score >= self.max_score {
+        break;
+      }
+    }
+    best.or_else(|| {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      actions.into_iter().next()
+    })
+  }
+
+  fn name(&self) -> String {
+    format!("Expectimax (depth {})", self.depth)
+  }
+
+  fn parameters(&self) -> Vec<(&'static str, String)> {
+    vec![("depth", self.depth.to_string()), ("min_score", self.min_score.to_string()),
+      ("max_score", self.max_score.to_string()), ("star2", self.star2.to_string())]
+  }
+
+  fn set_parameter(&mut self, name : &str, value : &str) -> bool {
+    match name {
+      "depth" => value.parse().map(|v| self.depth = v).is_ok(),
+      "min_score" => value.parse().map(|v| self.min_score = v).is_ok(),
+      "max_score" => value.parse().map(|v| self.max_score = v).is_ok(),
+      "star2" => value.parse().map(|v| self.star2 = v).is_ok(),
+      _ => false,
+    }
+  }
 }
@@ -0,0 +1,280 @@
+use piston_window::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::utils::{Pos, Board};
+use crate::agents::{Game, Stochastic, GameAgent, agent_action};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tile {
+  Empty,
+  /// An amazon belonging to player 0 or 1
+  Amazon(i64),
+  /// A burnt tile that nothing can move onto or through
+  Arrow,
+}
+
+/// Move an amazon like a chess queen, and then shoot an arrow
+/// from where it lands, again like a queen
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Action {
+  pub from : Pos,
+  pub to : Pos,
+  pub arrow : Pos,
+}
+
+static DIRECTIONS : [Pos ; 8] = [
+  Pos {x: 1, y: 0}, Pos {x: -1, y: 0}, Pos {x: 0, y: 1}, Pos {x: 0, y: -1},
+  Pos {x: 1, y: 1}, Pos {x: 1, y: -1}, Pos {x: -1, y: 1}, Pos {x: -1, y: -1},
+];
+
+/// The game of the Amazons, on a 10x10 board with four amazons each.
+/// Every turn fills one more tile with an arrow, and the first player
+/// who can't move loses.
+#[derive(Clone)]
+pub struct Amazons {
+  pub tiles : Board<Tile>,
+  active_player : i64,
+  victory : Option<i64>,
+}
+
+impl Amazons {
+
+  pub fn new() -> Amazons {
+    let mut tiles = Board::new(Tile::Empty, 10);
+    for &(x, y) in &[(0, 3), (3, 0), (6, 0), (9, 3)] {
+      tiles.set(Pos {x, y}, Tile::Amazon(0));
+      tiles.set(Pos {x, y: 9 - y}, Tile::Amazon(1));
+    }
+    Amazons { tiles, active_player: 0, victory: None }
+  }
+
+  /// Returns true if `p` is on the board and can be moved through.
+  /// `vacated` is the tile that the moving amazon has left.
+  fn is_free(&self, p : Pos, vacated : Pos) -> bool {
+    p == vacated || self.tiles.try_get(p) == Some(Tile::Empty)
+  }
+
+  /// Calls `f` for each tile a queen move away from `from`
+  fn visit_queen_moves<F>(&self, from : Pos, vacated : Pos, mut f : F)
+    where F : FnMut(Pos)
+  {
+    for &d in DIRECTIONS.iter() {
+      let mut p = from + d;
+      while self.is_free(p, vacated) {
+        f(p);
+        p += d;
+      }
+    }
+  }
+
+  fn amazons(&self, player : i64) -> Vec<Pos> {
+    let mut amazons = vec![];
+    for y in 0..self.tiles.height {
+      for x in 0..self.tiles.width {
+        if self.tiles.get(Pos {x, y}) == Tile::Amazon(player) {
+          amazons.push(Pos {x, y});
+        }
+      }
+    }
+    amazons
+  }
+
+  /// An amazon that can step to a neighbouring tile can always shoot
+  /// back where it came from, so this is enough to have a move
+  fn can_move(&self, player : i64) -> bool {
+    self.amazons(player).into_iter().any(|a| {
+      DIRECTIONS.iter().any(|&d| self.tiles.try_get(a + d) == Some(Tile::Empty))
+    })
+  }
+
+  /// The number of queen moves open to the player's amazons
+  fn mobility(&self, player : i64) -> i32 {
+    let mut count = 0;
+    for a in self.amazons(player) {
+      self.visit_queen_moves(a, a, |_| count += 1);
+    }
+    count
+  }
+}
+
+impl Game for Amazons {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.victory.is_some() {
+      return;
+    }
+    for from in self.amazons(self.active_player) {
+      self.visit_queen_moves(from, from, |to| {
+        // The arrow can fly through or land on the tile the amazon left
+        self.visit_queen_moves(to, from, |arrow| {
+          actions.push(Action { from, to, arrow });
+        });
+      });
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    let player = self.active_player;
+    self.tiles.set(a.from, Tile::Empty);
+    self.tiles.set(a.to, Tile::Amazon(player));
+    self.tiles.set(a.arrow, Tile::Arrow);
+    self.active_player = 1 - player;
+    if !self.can_move(self.active_player) {
+      self.victory = Some(player);
+    }
+  }
+
+  /// 1000 for a win and -1000 for a loss, and otherwise
+  /// the difference in mobility
+  fn player_score(&self, player : i64) -> f64 {
+    match self.victory {
+      Some(w) if w == player => 1000.0,
+      Some(_) => -1000.0,
+      None => (self.mobility(player) - self.mobility(1 - player)) as f64,
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    self.victory
+  }
+}
+
+impl Stochastic for Amazons {}
+
+impl Recordable for Amazons {
+  const GAME_TYPE : &'static str = "amazons";
+
+  fn config(&self) -> String {
+    String::new()
+  }
+
+  fn from_config(_config : &str) -> Option<Amazons> {
+    Some(Amazons::new())
+  }
+}
+
+/// Moves are written as the amazon's move and then the arrow, e.g.
+/// "d1-d7/g7", with the rows numbered from player 0's side
+impl Notation for Amazons {
+  fn format_action(&self, action : &Action) -> String {
+    format!("{}-{}/{}", action.from.algebraic(), action.to.algebraic(), action.arrow.algebraic())
+  }
+}
+
+static TILE_SIZE : f64 = 60.0;
+
+fn draw_amazons(game : &Amazons, highlighted : &[Pos], context : &Context, graphics : &mut G2d) {
+  clear([1.0; 4], graphics);
+  for y in 0..game.tiles.height {
+    for x in 0..game.tiles.width {
+      let (left, top) = (x as f64 * TILE_SIZE, y as f64 * TILE_SIZE);
+      if (x + (y % 2)) % 2 == 1 {
+        rectangle(
+          [0.0, 0.0, 0.0, 1.0], // black
+          [left, top, TILE_SIZE, TILE_SIZE],
+          context.transform,
+          graphics);
+      }
+      match game.tiles.get(Pos {x, y}) {
+        Tile::Amazon(p) => {
+          let colour = if p == 0 { [0.0, 1.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0, 1.0] };
+          ellipse(
+            colour, [left + 5.0, top + 5.0, TILE_SIZE - 10.0, TILE_SIZE - 10.0],
+            context.transform, graphics);
+        }
+        Tile::Arrow => {
+          rectangle(
+            [0.5, 0.5, 0.5, 1.0], [left + 10.0, top + 10.0, TILE_SIZE - 20.0, TILE_SIZE - 20.0],
+            context.transform, graphics);
+        }
+        Tile::Empty => (),
+      }
+    }
+  }
+  for p in highlighted {
+    Rectangle::new_border([0.0, 0.0, 1.0, 1.0], 2.0)
+      .draw([p.x as f64 * TILE_SIZE, p.y as f64 * TILE_SIZE, TILE_SIZE, TILE_SIZE],
+        &DrawState::default(), context.transform, graphics);
+  }
+}
+
+/// Load a graphical, interactive game of the Amazons. Click an amazon,
+/// then the tile to move it to, and then the tile to shoot the arrow at.
+pub fn play_amazons<A, B>(mut agent_a : A, mut agent_b : B)
+  where A : GameAgent<Amazons>, B : GameAgent<Amazons>
+{
+  println!("Amazons!");
+  let mut game = Amazons::new();
+  let board_pixels = game.tiles.width as f64 * TILE_SIZE;
+  let mut rng = StdRng::from_entropy();
+
+  let agents : &mut [&mut dyn GameAgent<Amazons>] = &mut [&mut agent_a, &mut agent_b];
+
+  let mut window: PistonWindow =
+    WindowSettings::new("Amazons", [board_pixels, board_pixels])
+    .exit_on_esc(true).build().unwrap();
+
+  let mut mouse_pos = [0.0, 0.0];
+  // The actions matching the tiles clicked so far
+  let mut player_actions : Vec<Action> = vec![];
+  // Where the selected amazon is moving to, once chosen
+  let mut moved_to = None;
+
+  while let Some(event) = window.next() {
+    if let Some(Button::Keyboard(key)) = event.press_args() {
+      if key == Key::Space {
+        player_actions.clear();
+        moved_to = None;
+        agent_action(agents, &mut game, &mut rng);
+      }
+      if key == Key::Return {
+        player_actions.clear();
+        moved_to = None;
+        game = Amazons::new();
+      }
+    }
+    if let Some(p) = event.mouse_cursor_args() {
+      mouse_pos = p;
+    }
+    // Handle mouse clicks
+    if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+      let pos = Pos { x: (mouse_pos[0] / TILE_SIZE) as i32, y: (mouse_pos[1] / TILE_SIZE) as i32 };
+      if let Some(to) = moved_to {
+        // Shoot the arrow
+        if let Some(a) = player_actions.iter().find(|a| a.to == to && a.arrow == pos).cloned() {
+          game.apply_action(&a);
+          // AI response
+          agent_action(agents, &mut game, &mut rng);
+        }
+        player_actions.clear();
+        moved_to = None;
+      }
+      else if game.tiles.try_get(pos) == Some(Tile::Amazon(game.active_player)) {
+        // Select an amazon
+        player_actions.clear();
+        game.possible_actions(&mut player_actions);
+        player_actions.retain(|a| a.from == pos);
+      }
+      else if player_actions.iter().any(|a| a.to == pos) {
+        player_actions.retain(|a| a.to == pos);
+        moved_to = Some(pos);
+      }
+    }
+    // Highlight where the amazon can move, or where it can shoot
+    let mut highlighted : Vec<Pos> = player_actions.iter()
+      .map(|a| if moved_to.is_some() { a.arrow } else { a.to })
+      .collect();
+    highlighted.dedup();
+    // Handle draw events
+    window.draw_2d(&event, |context, graphics, _device| {
+      draw_amazons(&game, &highlighted, &context, graphics)
+    });
+  }
+}
@@ -0,0 +1,244 @@
+use piston_window::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::utils::{Pos, Board};
+use crate::agents::{Game, Stochastic, GameAgent, agent_action};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tile {
+  Empty,
+  /// A pawn belonging to player 0 or 1
+  Pawn(i64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Action {
+  pub from : Pos,
+  pub to : Pos,
+}
+
+/// Breakthrough: each player starts with two rows of pawns. A pawn moves
+/// one tile straight or diagonally forwards, and can only capture
+/// diagonally. The first player to reach the far row, or to capture
+/// every enemy pawn, wins. Player 0 starts at the top and moves down.
+#[derive(Clone)]
+pub struct Breakthrough {
+  pub tiles : Board<Tile>,
+  active_player : i64,
+  victory : Option<i64>,
+}
+
+impl Breakthrough {
+
+  pub fn new() -> Breakthrough {
+    let size = 8;
+    let mut tiles = Board::new(Tile::Empty, size);
+    for x in 0..size {
+      for y in 0..2 {
+        tiles.set(Pos {x, y}, Tile::Pawn(0));
+        tiles.set(Pos {x, y: size - 1 - y}, Tile::Pawn(1));
+      }
+    }
+    Breakthrough { tiles, active_player: 0, victory: None }
+  }
+
+  fn forwards(player : i64) -> i32 {
+    if player == 0 { 1 } else { -1 }
+  }
+
+  fn far_row(&self, player : i64) -> i32 {
+    if player == 0 { self.tiles.height - 1 } else { 0 }
+  }
+
+  fn count_pawns(&self, player : i64) -> i32 {
+    self.tiles.iter().filter(|&&t| t == Tile::Pawn(player)).count() as i32
+  }
+
+  /// How many rows the player's most advanced pawn has moved
+  fn furthest_advance(&self, player : i64) -> i32 {
+    let mut furthest = 0;
+    for y in 0..self.tiles.height {
+      for x in 0..self.tiles.width {
+        if self.tiles.get(Pos {x, y}) == Tile::Pawn(player) {
+          let advance = if player == 0 { y } else { self.tiles.height - 1 - y };
+          furthest = furthest.max(advance);
+        }
+      }
+    }
+    furthest
+  }
+}
+
+impl Game for Breakthrough {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.victory.is_some() {
+      return;
+    }
+    let player = self.active_player;
+    let dy = Breakthrough::forwards(player);
+    for y in 0..self.tiles.height {
+      for x in 0..self.tiles.width {
+        let from = Pos {x, y};
+        if self.tiles.get(from) != Tile::Pawn(player) {
+          continue;
+        }
+        for dx in -1..=1 {
+          let to = from + Pos {x: dx, y: dy};
+          match self.tiles.try_get(to) {
+            Some(Tile::Empty) => actions.push(Action { from, to }),
+            // Captures are only diagonal
+            Some(Tile::Pawn(p)) if p != player && dx != 0 => actions.push(Action { from, to }),
+            _ => (),
+          }
+        }
+      }
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    let player = self.active_player;
+    self.tiles.set(a.from, Tile::Empty);
+    self.tiles.set(a.to, Tile::Pawn(player));
+    if a.to.y == self.far_row(player) || self.count_pawns(1 - player) == 0 {
+      self.victory = Some(player);
+    }
+    self.active_player = 1 - player;
+  }
+
+  /// 1000 for a win and -1000 for a loss, and otherwise the difference
+  /// in pawns, plus a little for the most advanced pawn
+  fn player_score(&self, player : i64) -> f64 {
+    match self.victory {
+      Some(w) if w == player => 1000.0,
+      Some(_) => -1000.0,
+      None => {
+        let material = self.count_pawns(player) - self.count_pawns(1 - player);
+        let advance = self.furthest_advance(player) - self.furthest_advance(1 - player);
+        material as f64 + advance as f64 * 0.5
+      }
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    self.victory
+  }
+}
+
+impl Stochastic for Breakthrough {}
+
+impl Recordable for Breakthrough {
+  const GAME_TYPE : &'static str = "breakthrough";
+
+  fn config(&self) -> String {
+    String::new()
+  }
+
+  fn from_config(_config : &str) -> Option<Breakthrough> {
+    Some(Breakthrough::new())
+  }
+}
+
+/// Moves are written like "a2-a3", or "a2xb3" for a capture, with the
+/// rows numbered from player 0's side
+impl Notation for Breakthrough {
+  fn format_action(&self, action : &Action) -> String {
+    let separator = if self.tiles.get(action.to) == Tile::Empty { '-' } else { 'x' };
+    format!("{}{}{}", action.from.algebraic(), separator, action.to.algebraic())
+  }
+}
+
+fn draw_breakthrough(game : &Breakthrough, player_actions : &[Action], context : &Context, graphics : &mut G2d) {
+  clear([1.0; 4], graphics);
+  for y in 0..game.tiles.height {
+    for x in 0..game.tiles.width {
+      if (x + (y % 2)) % 2 == 1 {
+        rectangle(
+          [0.0, 0.0, 0.0, 1.0], // black
+          [x as f64 * 60.0, y as f64 * 60.0, 60.0, 60.0],
+          context.transform,
+          graphics);
+      }
+      let colour = match game.tiles.get(Pos {x, y}) {
+        Tile::Pawn(0) => Some([0.0, 1.0, 0.0, 1.0]),
+        Tile::Pawn(_) => Some([1.0, 0.0, 0.0, 1.0]),
+        Tile::Empty => None,
+      };
+      if let Some(c) = colour {
+        ellipse(
+          c, [x as f64 * 60.0 + 5.0, y as f64 * 60.0 + 5.0, 50.0, 50.0],
+          context.transform,
+          graphics);
+      }
+    }
+  }
+  for a in player_actions {
+    for &p in &[a.from, a.to] {
+      Rectangle::new_border([0.0, 0.0, 1.0, 1.0], 2.0)
+        .draw([p.x as f64 * 60.0, p.y as f64 * 60.0, 60.0, 60.0],
+          &DrawState::default(), context.transform, graphics);
+    }
+  }
+}
+
+/// Load a graphical, interactive breakthrough game
+pub fn play_breakthrough<A, B>(mut agent_a : A, mut agent_b : B)
+  where A : GameAgent<Breakthrough>, B : GameAgent<Breakthrough>
+{
+  println!("Breakthrough!");
+  let mut game = Breakthrough::new();
+  let board_pixels = game.tiles.width as f64 * 60.0;
+  let mut rng = StdRng::from_entropy();
+
+  let agents : &mut [&mut dyn GameAgent<Breakthrough>] = &mut [&mut agent_a, &mut agent_b];
+
+  let mut window: PistonWindow =
+    WindowSettings::new("Breakthrough", [board_pixels, board_pixels])
+    .exit_on_esc(true).build().unwrap();
+
+  let mut mouse_pos = [0.0, 0.0];
+  let mut player_actions = vec![];
+
+  while let Some(event) = window.next() {
+    if let Some(Button::Keyboard(key)) = event.press_args() {
+      if key == Key::Space {
+        player_actions.clear();
+        agent_action(agents, &mut game, &mut rng);
+      }
+      if key == Key::Return {
+        player_actions.clear();
+        game = Breakthrough::new();
+      }
+    }
+    if let Some(p) = event.mouse_cursor_args() {
+      mouse_pos = p;
+    }
+    // Handle mouse clicks
+    if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+      let pos = Pos { x: (mouse_pos[0] / 60.0) as i32, y: (mouse_pos[1] / 60.0) as i32 };
+      if game.tiles.try_get(pos) == Some(Tile::Pawn(game.active_player)) {
+        // Select a pawn, and show its moves
+        player_actions.clear();
+        game.possible_actions(&mut player_actions);
+        player_actions.retain(|a| a.from == pos);
+      }
+      else if let Some(a) = player_actions.iter().find(|a| a.to == pos).cloned() {
+        game.apply_action(&a);
+        player_actions.clear();
+        // AI response
+        agent_action(agents, &mut game, &mut rng);
+      }
+    }
+    // Handle draw events
+    window.draw_2d(&event, |context, graphics, _device| {
+      draw_breakthrough(&game, &player_actions, &context, graphics)
+    });
+  }
+}
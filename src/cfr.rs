@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use rand::{Rng, rngs::StdRng};
+
+use crate::agents::*;
+
+// Counterfactual regret minimisation (CFR) finds approximate Nash equilibria
+// of two-player zero-sum games with hidden information. It works on any
+// `HiddenInformation` game, treating each distinct observation of the active
+// player as an information set. The game's chance events are enumerated
+// rather than sampled, so this is only practical for small games.
+
+/// The regrets and strategy totals for one information set
+struct InfoSet {
+  regrets : Vec<f64>,
+  strategy_sum : Vec<f64>,
+  /// The strategy for the current pass over the tree. It mustn't change
+  /// during a pass, even though the set may be visited many times.
+  current : Vec<f64>,
+}
+
+impl InfoSet {
+  fn new(num_actions : usize) -> InfoSet {
+    InfoSet {
+      regrets: vec![0.0 ; num_actions],
+      strategy_sum: vec![0.0 ; num_actions],
+      current: vec![1.0 / num_actions as f64 ; num_actions],
+    }
+  }
+
+  /// Finds the strategy for the next pass by regret matching
+  fn update_strategy(&mut self, plus : bool) {
+    if plus {
+      for r in self.regrets.iter_mut() {
+        *r = r.max(0.0);
+      }
+    }
+    self.current = normalise(self.regrets.iter().map(|&r| r.max(0.0)).collect());
+  }
+
+  fn average_strategy(&self) -> Vec<f64> {
+    normalise(self.strategy_sum.clone())
+  }
+}
+
+/// Scales the weights to sum to one, or returns the
+/// uniform distribution if they are all zero
+fn normalise(mut weights : Vec<f64>) -> Vec<f64> {
+  let total : f64 = weights.iter().sum();
+  let n = weights.len() as f64;
+  for w in weights.iter_mut() {
+    *w = if total > 0.0 { *w / total } else { 1.0 / n };
+  }
+  weights
+}
+
+fn is_terminal<G : Stochastic>(game : &G, actions : &[G::Action]) -> bool {
+  actions.is_empty() && !game.is_chance_node()
+}
+
+pub struct CfrSolver<G : HiddenInformation> {
+  root : G,
+  /// Use CFR+ (clamped regrets and linearly weighted averaging)
+  /// rather than vanilla CFR
+  plus : bool,
+  info_sets : HashMap<G::Observation, InfoSet>,
+  iterations : i64,
+}
+
+impl <G : HiddenInformation> CfrSolver<G> where G::Observation : Hash + Eq {
+
+  pub fn new(root : G, plus : bool) -> CfrSolver<G> {
+    CfrSolver { root, plus, info_sets: HashMap::new(), iterations: 0 }
+  }
+
+  pub fn iterations(&self) -> i64 {
+    self.iterations
+  }
+
+  pub fn train(&mut self, iterations : i64) {
+    let root = self.root.clone();
+    for _ in 0..iterations {
+      self.iterations += 1;
+      // Alternating updates, which CFR+ relies on
+      for player in 0..2 {
+        self.cfr(&root, player, 1.0, 1.0);
+        let plus = self.plus;
+        for info_set in self.info_sets.values_mut() {
+          info_set.update_strategy(plus);
+        }
+      }
+    }
+  }
+
+  /// Returns the value of `game` for `player`, and updates the regrets of
+  /// that player's information sets. `own_reach` is the probability that
+  /// `player` plays to reach this state, and `other_reach` is the
+  /// probability that the opponent and chance do.
+  fn cfr(&mut self, game : &G, player : i64, own_reach : f64, other_reach : f64) -> f64 {
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if is_terminal(game, &actions) {
+      return game.player_score(player);
+    }
+    if game.is_chance_node() {
+      let mut outcomes = vec![];
+      game.chance_outcomes(&mut outcomes);
+      let mut value = 0.0;
+      for (a, p) in outcomes {
+        let mut game = game.clone();
+        game.apply_action(&a);
+        value += p * self.cfr(&game, player, own_reach, other_reach * p);
+      }
+      return value;
+    }
+    let active = game.active_player();
+    let key = game.observe(active);
+    let strategy = self.info_sets.entry(key.clone())
+      .or_insert_with(|| InfoSet::new(actions.len()))
+      .current.clone();
+    let mut values = vec![0.0 ; actions.len()];
+    let mut value = 0.0;
+    for (i, a) in actions.iter().enumerate() {
+      let mut game = game.clone();
+      game.apply_action(a);
+      values[i] = if active == player {
+        self.cfr(&game, player, own_reach * strategy[i], other_reach)
+      }
+      else {
+        self.cfr(&game, player, own_reach, other_reach * strategy[i])
+      };
+      value += strategy[i] * values[i];
+    }
+    if active == player {
+      let weight = if self.plus { self.iterations as f64 } else { 1.0 };
+      let info_set = self.info_sets.get_mut(&key).unwrap();
+      for i in 0..actions.len() {
+        info_set.regrets[i] += other_reach * (values[i] - value);
+        info_set.strategy_sum[i] += weight * own_reach * strategy[i];
+      }
+    }
+    value
+  }
+
+  /// A snapshot of the average strategy, for an agent to play
+  pub fn strategy(&self) -> Strategy<G> {
+    let table = self.info_sets.iter()
+      .map(|(k, s)| (k.clone(), s.average_strategy()))
+      .collect();
+    Strategy { table: Arc::new(table) }
+  }
+
+  /// How much a best-responding opponent could win against the average
+  /// strategy, averaged over both seats. This is zero at an equilibrium.
+  pub fn exploitability(&self) -> f64 {
+    let strategy = self.strategy();
+    let br_0 = BestResponse::new(&self.root, 0, &strategy).value();
+    let br_1 = BestResponse::new(&self.root, 1, &strategy).value();
+    (br_0 + br_1) / 2.0
+  }
+}
+
+/// A fixed strategy for every information set
+pub struct Strategy<G : HiddenInformation> {
+  table : Arc<HashMap<G::Observation, Vec<f64>>>,
+}
+
+impl <G : HiddenInformation> Clone for Strategy<G> {
+  fn clone(&self) -> Strategy<G> {
+    Strategy { table: self.table.clone() }
+  }
+}
+
+impl <G : HiddenInformation> Strategy<G> where G::Observation : Hash + Eq {
+  /// The action probabilities at an information set,
+  /// or the uniform distribution if it was never visited
+  fn probabilities(&self, observation : &G::Observation, num_actions : usize) -> Vec<f64> {
+    match self.table.get(observation) {
+      Some(p) => p.clone(),
+      None => vec![1.0 / num_actions as f64 ; num_actions],
+    }
+  }
+}
+
+/// Computes the value that `player` gets by best responding to a fixed
+/// strategy. The best response must pick the same action in every state of
+/// an information set, so each one is chosen by weighing its states by how
+/// likely the opponent and chance are to reach them.
+struct BestResponse<'l, G : HiddenInformation> {
+  root : &'l G,
+  player : i64,
+  strategy : &'l Strategy<G>,
+  /// The states in each of the player's information sets,
+  /// with the probability of the opponent and chance reaching them
+  states : HashMap<G::Observation, Vec<(G, f64)>>,
+  /// The best action index for each information set, once known
+  choices : HashMap<G::Observation, usize>,
+}
+
+impl <'l, G : HiddenInformation> BestResponse<'l, G> where G::Observation : Hash + Eq {
+
+  fn new(root : &'l G, player : i64, strategy : &'l Strategy<G>) -> BestResponse<'l, G> {
+    let mut br = BestResponse {
+      root, player, strategy, states: HashMap::new(), choices: HashMap::new(),
+    };
+    br.collect_states(root, 1.0);
+    br
+  }
+
+  fn collect_states(&mut self, game : &G, reach : f64) {
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if is_terminal(game, &actions) {
+      return;
+    }
+    if game.is_chance_node() {
+      let mut outcomes = vec![];
+      game.chance_outcomes(&mut outcomes);
+      for (a, p) in outcomes {
+        let mut game = game.clone();
+        game.apply_action(&a);
+        self.collect_states(&game, reach * p);
+      }
+      return;
+    }
+    let active = game.active_player();
+    let key = game.observe(active);
+    let probabilities = self.strategy.probabilities(&key, actions.len());
+    if active == self.player {
+      self.states.entry(key).or_default().push((game.clone(), reach));
+    }
+    for (i, a) in actions.iter().enumerate() {
+      let mut game = game.clone();
+      game.apply_action(a);
+      let p = if active == self.player { 1.0 } else { probabilities[i] };
+      self.collect_states(&game, reach * p);
+    }
+  }
+
+  fn value(&mut self) -> f64 {
+    let root = self.root.clone();
+    self.evaluate(&root)
+  }
+
+  fn evaluate(&mut self, game : &G) -> f64 {
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if is_terminal(game, &actions) {
+      return game.player_score(self.player);
+    }
+    if game.is_chance_node() {
+      let mut outcomes = vec![];
+      game.chance_outcomes(&mut outcomes);
+      return outcomes.iter().map(|(a, p)| {
+        let mut game = game.clone();
+        game.apply_action(a);
+        p * self.evaluate(&game)
+      }).sum();
+    }
+    let active = game.active_player();
+    let key = game.observe(active);
+    if active == self.player {
+      let choice = self.choose(&key, actions.len());
+      let mut game = game.clone();
+      game.apply_action(&actions[choice]);
+      self.evaluate(&game)
+    }
+    else {
+      let probabilities = self.strategy.probabilities(&key, actions.len());
+      let mut value = 0.0;
+      for (i, a) in actions.iter().enumerate() {
+        if probabilities[i] > 0.0 {
+          let mut game = game.clone();
+          game.apply_action(a);
+          value += probabilities[i] * self.evaluate(&game);
+        }
+      }
+      value
+    }
+  }
+
+  fn choose(&mut self, key : &G::Observation, num_actions : usize) -> usize {
+    if let Some(&c) = self.choices.get(key) {
+      return c;
+    }
+    let states = self.states.get(key).cloned().unwrap_or_default();
+    let mut best = (0, f64::NEG_INFINITY);
+    let mut actions = vec![];
+    for i in 0..num_actions {
+      let mut value = 0.0;
+      for (game, reach) in states.iter() {
+        actions.clear();
+        game.possible_actions(&mut actions);
+        let mut game = game.clone();
+        game.apply_action(&actions[i]);
+        value += reach * self.evaluate(&game);
+      }
+      if value > best.1 {
+        best = (i, value);
+      }
+    }
+    self.choices.insert(key.clone(), best.0);
+    best.0
+  }
+}
+
+/// Plays by sampling from a solved strategy. It only looks at
+/// the active player's observation and their legal actions.
+pub struct CfrAgent<G : HiddenInformation> {
+  pub strategy : Strategy<G>,
+}
+
+impl <G : HiddenInformation> Clone for CfrAgent<G> {
+  fn clone(&self) -> CfrAgent<G> {
+    CfrAgent { strategy: self.strategy.clone() }
+  }
+}
+
+impl <G : HiddenInformation> GameAgent<G> for CfrAgent<G> where G::Observation : Hash + Eq {
+  fn choose_action(&mut self, game : &G, rng : &mut StdRng) -> Option<G::Action> {
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    if actions.is_empty() {
+      return None;
+    }
+    let observation = game.observe(game.active_player());
+    let probabilities = self.strategy.probabilities(&observation, actions.len());
+    let mut r : f64 = rng.gen();
+    let last = actions.len() - 1;
+    for (i, a) in actions.into_iter().enumerate() {
+      if r < probabilities[i] || i == last {
+        return Some(a);
+      }
+      r -= probabilities[i];
+    }
+    None
+  }
+}
@@ -1,36 +1,58 @@
 
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use piston_window::*;
 use rand::{SeedableRng, rngs::StdRng};
 
-use crate::utils::Pos;
-use crate::agents::{Game, GameAgent, agent_action};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::utils::{Pos, Board, AsciiError, draw_ascii, read_ascii};
+use crate::agents::{Game, Stochastic, GameAgent, take_action};
+use crate::record::Recordable;
+use crate::notation::{Notation, NotationError, find_action};
 
 // --------- Define the checkers data structure ----------
 
 /// Represents the state of a checkers game
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Checkers {
-  pub tiles : [Tile ; 64],
+  pub tiles : Board<Tile>,
   pub active_player : Player,
   pub mode : Mode,
+  pub rules : CheckersRules,
+  /// The number of turns since a man moved or a piece was captured
+  pub quiet_turns : i32,
+  /// Hashes of the positions since a man moved or a piece was
+  /// captured, as no earlier position can be repeated
+  pub positions : Vec<u64>,
 }
 
 /// Holds the contents of a tile
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Tile {
   Occupied(Player, Piece),
+  /// A piece that was jumped earlier in the current capture chain.
+  /// It stays on the board until the chain is over, and it can't
+  /// be jumped a second time.
+  Captured(Player, Piece),
   Empty,
 }
 
 /// Describes the type of a piece
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Piece {
   Pawn,
   King,
 }
 
 /// The two competing players
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Player {
   White,
   Black,
@@ -38,6 +60,7 @@ pub enum Player {
 
 /// The mode that the game is in.
 #[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Mode {
   /// The active player can choose any move
   StartOfTurn,
@@ -49,6 +72,186 @@ pub enum Mode {
 
   /// One of the players won
   Victory(Player),
+
+  /// The game was drawn by repetition or a lack of progress
+  Draw,
+}
+
+/// What happens when a man reaches the back row part way through
+/// a chain of captures
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MidCaptureCrowning {
+  /// It is crowned, and its move ends there
+  EndsMove,
+  /// It is crowned, and carries on capturing as a king
+  ContinuesAsKing,
+  /// It carries on capturing as a man, and is only crowned
+  /// if the chain finishes on the back row
+  PassesThrough,
+}
+
+/// Which captures a player must choose between
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CapturePriority {
+  /// Any capture will do
+  Free,
+  /// The capture must take as many pieces as possible
+  Majority,
+  /// The Italian rules: take as many pieces as possible, then capture with
+  /// a king if possible, then take as many kings as possible, and then
+  /// take kings as early in the chain as possible
+  Quality,
+}
+
+/// The rules that differ between the regional variants of checkers
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CheckersRules {
+  /// The board is square, with this many tiles along each side
+  pub board_size : i32,
+  pub men_capture_backwards : bool,
+  pub men_capture_kings : bool,
+  /// Kings move and capture any distance along a diagonal
+  pub flying_kings : bool,
+  pub mid_capture_crowning : MidCaptureCrowning,
+  pub capture_priority : CapturePriority,
+  pub first_player : Player,
+  /// Draw once each player has made this many moves without
+  /// moving a man or capturing a piece
+  pub no_progress_limit : Option<i32>,
+  /// Draw when the same position occurs for the third time
+  pub threefold_repetition : bool,
+  /// Antidraughts, where the aim is to lose all your pieces or be blocked
+  pub giveaway : bool,
+}
+
+impl CheckersRules {
+  /// American checkers, or English draughts
+  pub fn american() -> CheckersRules {
+    CheckersRules {
+      board_size: 8,
+      men_capture_backwards: false,
+      men_capture_kings: true,
+      flying_kings: false,
+      mid_capture_crowning: MidCaptureCrowning::EndsMove,
+      capture_priority: CapturePriority::Free,
+      first_player: Black,
+      no_progress_limit: Some(40),
+      threefold_repetition: true,
+      giveaway: false,
+    }
+  }
+
+  /// International draughts, on a 10x10 board
+  pub fn international() -> CheckersRules {
+    CheckersRules {
+      board_size: 10,
+      men_capture_backwards: true,
+      men_capture_kings: true,
+      flying_kings: true,
+      mid_capture_crowning: MidCaptureCrowning::PassesThrough,
+      capture_priority: CapturePriority::Majority,
+      first_player: White,
+      no_progress_limit: Some(40),
+      threefold_repetition: true,
+      giveaway: false,
+    }
+  }
+
+  /// Brazilian draughts: the international rules on an 8x8 board
+  pub fn brazilian() -> CheckersRules {
+    CheckersRules { board_size: 8, ..CheckersRules::international() }
+  }
+
+  pub fn russian() -> CheckersRules {
+    CheckersRules {
+      board_size: 8,
+      men_capture_backwards: true,
+      men_capture_kings: true,
+      flying_kings: true,
+      mid_capture_crowning: MidCaptureCrowning::ContinuesAsKing,
+      capture_priority: CapturePriority::Free,
+      first_player: White,
+      no_progress_limit: Some(40),
+      threefold_repetition: true,
+      giveaway: false,
+    }
+  }
+
+  /// American pool checkers
+  pub fn pool() -> CheckersRules {
+    CheckersRules {
+      board_size: 8,
+      men_capture_backwards: true,
+      men_capture_kings: true,
+      flying_kings: true,
+      mid_capture_crowning: MidCaptureCrowning::PassesThrough,
+      capture_priority: CapturePriority::Free,
+      first_player: Black,
+      no_progress_limit: Some(40),
+      threefold_repetition: true,
+      giveaway: false,
+    }
+  }
+
+  pub fn italian() -> CheckersRules {
+    CheckersRules {
+      board_size: 8,
+      men_capture_backwards: false,
+      men_capture_kings: false,
+      flying_kings: false,
+      mid_capture_crowning: MidCaptureCrowning::EndsMove,
+      capture_priority: CapturePriority::Quality,
+      first_player: White,
+      no_progress_limit: Some(40),
+      threefold_repetition: true,
+      giveaway: false,
+    }
+  }
+
+  /// Looks up a preset by name, e.g. "russian"
+  pub fn from_name(name : &str) -> Option<CheckersRules> {
+    match name {
+      "american" | "english" => Some(CheckersRules::american()),
+      "international" => Some(CheckersRules::international()),
+      "brazilian" => Some(CheckersRules::brazilian()),
+      "russian" => Some(CheckersRules::russian()),
+      "pool" => Some(CheckersRules::pool()),
+      "italian" => Some(CheckersRules::italian()),
+      _ => None,
+    }
+  }
+
+  /// The name of the preset that these rules match, ignoring giveaway
+  pub fn name(&self) -> Option<&'static str> {
+    let rules = CheckersRules { giveaway: false, ..*self };
+    ["american", "international", "brazilian", "russian", "pool", "italian"].iter()
+      .find(|&&name| CheckersRules::from_name(name) == Some(rules))
+      .cloned()
+  }
+
+  /// The same rules, but played as antidraughts
+  pub fn giveaway(self) -> CheckersRules {
+    CheckersRules { giveaway: true, ..self }
+  }
+
+  /// How many rows of men each player starts with
+  fn starting_rows(&self) -> i32 {
+    (self.board_size - 2) / 2
+  }
+}
+
+/// How good a chain of captures is under the capture priority rules.
+/// Larger is better, comparing the fields in order.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct CaptureValue {
+  pieces : i32,
+  by_king : bool,
+  kings : i32,
+  /// Whether each captured piece was a king, in the order they were taken
+  king_order : Vec<bool>,
 }
 
 // --------- Implement game logic ----------
@@ -67,81 +270,478 @@ impl Tile {
       None
     }
   }
-}
 
-/// The size of the board (which is assumed to be square)
-const BOARD_SIZE : i32 = 8;
+  fn piece(self) -> Option<Piece> {
+    if let Occupied(_, p) = self {
+      Some(p)
+    }
+    else {
+      None
+    }
+  }
+}
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Action {
   Step { from : Pos, to : Pos },
   Jump { from : Pos, capture : Pos, to : Pos },
 }
 
-fn possible_moves(tile : Tile) -> &'static [Pos] {
-  match tile {
-    Occupied(White, Pawn) => &[Pos {x: -1, y: 1}, Pos {x: 1, y: 1}],
-    Occupied(Black, Pawn) => &[Pos {x: -1, y: -1}, Pos {x: 1, y: -1}],
-    Occupied(_, King) =>
-      &[Pos {x: -1, y: 1}, Pos {x: 1, y: 1},
-        Pos {x: -1, y: -1}, Pos {x: 1, y: -1}],
-    Empty => &[],
+/// The reasons that `Checkers::from_fen` can reject a position
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+  /// The FEN must start with W or B for the player to move
+  BadSide(String),
+  /// A list of pieces that doesn't start with W or B
+  BadField(String),
+  /// A square number that isn't on the board
+  BadSquare(String),
+  /// The same square is given more than once
+  RepeatedSquare(i32),
+}
+
+impl fmt::Display for FenError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FenError::BadSide(s) => write!(f, "the player to move should be 'W' or 'B', not '{}'", s),
+      FenError::BadField(s) => write!(f, "the pieces '{}' should start with 'W' or 'B'", s),
+      FenError::BadSquare(s) => write!(f, "'{}' isn't a square on the board", s),
+      FenError::RepeatedSquare(n) => write!(f, "square {} is given more than once", n),
+    }
   }
 }
 
-fn coord_index(x : i32, y : i32) -> usize {
-  (y * BOARD_SIZE + x) as usize
+impl std::error::Error for FenError {}
+
+/// The reasons that `Checkers::parse_move` can reject a move
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveError {
+  /// The text isn't square numbers separated by '-' or 'x'
+  Malformed(String),
+  /// No legal move matches the text
+  Illegal(String),
+  /// More than one legal move matches the text
+  Ambiguous(String),
 }
 
-fn pos_index(p : Pos) -> usize {
-  coord_index(p.x, p.y)
+impl fmt::Display for MoveError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MoveError::Malformed(m) => write!(f, "'{}' isn't a move in draughts notation", m),
+      MoveError::Illegal(m) => write!(f, "'{}' isn't a legal move", m),
+      MoveError::Ambiguous(m) => write!(f, "'{}' could be more than one move", m),
+    }
+  }
+}
+
+impl std::error::Error for MoveError {}
+
+const FORWARDS_WHITE : &[Pos] = &[Pos {x: -1, y: 1}, Pos {x: 1, y: 1}];
+const FORWARDS_BLACK : &[Pos] = &[Pos {x: -1, y: -1}, Pos {x: 1, y: -1}];
+const ALL_DIRECTIONS : &[Pos] =
+  &[Pos {x: -1, y: 1}, Pos {x: 1, y: 1},
+    Pos {x: -1, y: -1}, Pos {x: 1, y: -1}];
+
+/// The directions that a tile's piece can move in
+fn possible_moves(tile : Tile) -> &'static [Pos] {
+  match tile {
+    Occupied(White, Pawn) => FORWARDS_WHITE,
+    Occupied(Black, Pawn) => FORWARDS_BLACK,
+    Occupied(_, King) => ALL_DIRECTIONS,
+    _ => &[],
+  }
 }
 
 impl Checkers {
 
-  pub fn new() -> Checkers {
-    let mut board = [Tile::Empty ; 64];
-    for y in 0..3 {
-      for i in (0..BOARD_SIZE).step_by(2) {
+  pub fn new(rules : CheckersRules) -> Checkers {
+    let size = rules.board_size;
+    let rows = rules.starting_rows();
+    let mut board = Board::new(Tile::Empty, size);
+    for y in 0..rows {
+      for i in (0..size).step_by(2) {
         let x = i + (y % 2);
-        board[coord_index(x, y)] = Occupied(White, Pawn);
+        board.set(Pos {x, y}, Occupied(White, Pawn));
       }
     }
-    for y in (BOARD_SIZE-3)..BOARD_SIZE {
-      for i in (0..BOARD_SIZE).step_by(2) {
+    for y in (size-rows)..size {
+      for i in (0..size).step_by(2) {
         let x = i + (y % 2);
-        board[coord_index(x, y)] = Occupied(Black, Pawn);
+        board.set(Pos {x, y}, Occupied(Black, Pawn));
       }
     }
-    Checkers { tiles: board, active_player : White, mode: Mode::StartOfTurn }
+    let mut game = Checkers {
+      tiles: board, active_player : rules.first_player, mode: Mode::StartOfTurn, rules,
+      quiet_turns: 0, positions: vec![],
+    };
+    game.positions.push(game.position_hash());
+    game
+  }
+
+  /// The size of the board (which is assumed to be square)
+  pub fn size(&self) -> i32 {
+    self.tiles.width
+  }
+
+  /// The number of a dark tile in Portable Draughts Notation. Tile 1 is
+  /// on the left of black's back row, and the numbers go along each row
+  /// and then towards white's back row.
+  pub fn square_number(&self, p : Pos) -> i32 {
+    (self.size() - 1 - p.y) * (self.size() / 2) + p.x / 2 + 1
+  }
+
+  /// The tile with this number in Portable Draughts Notation
+  pub fn square_at(&self, number : i32) -> Option<Pos> {
+    let half = self.size() / 2;
+    if number < 1 || number > half * self.size() {
+      return None;
+    }
+    let y = self.size() - 1 - (number - 1) / half;
+    Some(Pos {x: (number - 1) % half * 2 + y % 2, y})
+  }
+
+  /// Sets up a position from a PDN FEN, e.g. "B:W21-32:B1-12,K14".
+  /// Kings are marked with a K, and ranges of squares are allowed.
+  pub fn from_fen(rules : CheckersRules, fen : &str) -> Result<Checkers, FenError> {
+    let mut game = Checkers::new(rules);
+    game.tiles = Board::new(Tile::Empty, rules.board_size);
+    let fen = fen.trim().trim_end_matches('.');
+    let mut fields = fen.split(':');
+    let side = fields.next().unwrap_or("").trim();
+    game.active_player = match side {
+      "W" => White,
+      "B" => Black,
+      _ => return Err(FenError::BadSide(side.into())),
+    };
+    for field in fields {
+      let field = field.trim();
+      let player = match field.chars().next() {
+        Some('W') => White,
+        Some('B') => Black,
+        _ => return Err(FenError::BadField(field.into())),
+      };
+      for item in field[1..].split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+        let (piece, squares) = match item.strip_prefix('K') {
+          Some(squares) => (King, squares),
+          None => (Pawn, item),
+        };
+        let bad_square = || FenError::BadSquare(item.into());
+        let mut ends = squares.splitn(2, '-').map(|n| n.trim().parse::<i32>());
+        let first = ends.next().unwrap().map_err(|_| bad_square())?;
+        let last = match ends.next() {
+          Some(n) => n.map_err(|_| bad_square())?,
+          None => first,
+        };
+        for n in first..=last {
+          let p = game.square_at(n).ok_or_else(bad_square)?;
+          if game.get_tile(p) != Tile::Empty {
+            return Err(FenError::RepeatedSquare(n));
+          }
+          game.set_tile(p, Occupied(player, piece));
+        }
+      }
+    }
+    game.positions = vec![game.position_hash()];
+    game.check_start_of_turn();
+    Ok(game)
+  }
+
+  /// Ends the game if either player is out of pieces, or the active player
+  /// can't move, in a position that was set up rather than played to. As in
+  /// `end_move`, that player loses, or wins at giveaway.
+  fn check_start_of_turn(&mut self) {
+    let (white, black) = self.piece_count();
+    let other = match self.active_player { White => Black, Black => White };
+    let (own_pieces, other_pieces) = if self.active_player == White { (white, black) } else { (black, white) };
+    let stuck = if other_pieces == 0 {
+      other
+    }
+    else if own_pieces == 0 || !self.has_legal_move() {
+      self.active_player
+    }
+    else {
+      return;
+    };
+    let unstuck = if stuck == other { self.active_player } else { other };
+    self.mode = Mode::Victory(if self.rules.giveaway { stuck } else { unstuck });
+  }
+
+  /// Writes the position as a PDN FEN. Part way through a chain of
+  /// captures, the captured pieces are still listed.
+  pub fn to_fen(&self) -> String {
+    let mut fen = String::from(if self.active_player == White { "W" } else { "B" });
+    for &player in [White, Black].iter() {
+      fen.push_str(if player == White { ":W" } else { ":B" });
+      let mut squares = vec![];
+      for (i, t) in self.tiles.iter().enumerate() {
+        let p = Pos {x: i as i32 % self.size(), y: i as i32 / self.size()};
+        match *t {
+          Occupied(owner, piece) | Captured(owner, piece) if owner == player => {
+            squares.push((self.square_number(p), piece == King));
+          }
+          _ => (),
+        }
+      }
+      squares.sort();
+      let squares : Vec<String> = squares.into_iter()
+        .map(|(n, king)| if king { format!("K{}", n) } else { n.to_string() })
+        .collect();
+      fen.push_str(&squares.join(","));
+    }
+    fen
+  }
+
+  /// Reads a position drawn by `Display`. Only the board and the line
+  /// saying whose turn it is are needed.
+  pub fn from_ascii(rules : CheckersRules, text : &str) -> Result<Checkers, AsciiError> {
+    let mut game = Checkers::new(rules);
+    let (board, lines) = read_ascii(text, rules.board_size, rules.board_size)?;
+    for y in 0..game.size() {
+      for x in 0..game.size() {
+        let tile = match board.get(Pos {x, y}) {
+          '.' | ' ' => Tile::Empty,
+          'w' => Occupied(White, Pawn),
+          'W' => Occupied(White, King),
+          'b' => Occupied(Black, Pawn),
+          'B' => Occupied(Black, King),
+          c => return Err(AsciiError::UnknownTile(c)),
+        };
+        if tile != Tile::Empty && (x + y) % 2 == 1 {
+          return Err(AsciiError::Invalid(format!("a piece on the light tile {}", Pos {x, y}.algebraic())));
+        }
+        game.set_tile(Pos {x, y}, tile);
+      }
+    }
+    let mut side = None;
+    let mut result = None;
+    for line in lines.iter() {
+      let bad_line = || AsciiError::BadLine(line.clone());
+      let size = game.size();
+      let tile = |name : &str| {
+        let p = Pos {x: name.bytes().next()? as i32 - b'a' as i32, y: name.get(1..)?.parse::<i32>().ok()? - 1};
+        if p.x >= 0 && p.x < size && p.y >= 0 && p.y < size { Some(p) } else { None }
+      };
+      match line.as_str() {
+        "White won" => result = Some(Mode::Victory(White)),
+        "Black won" => result = Some(Mode::Victory(Black)),
+        "Drawn" => result = Some(Mode::Draw),
+        _ => {
+          let mut parts = line.split(", ");
+          side = match parts.next() {
+            Some("White to move") => Some(White),
+            Some("Black to move") => Some(Black),
+            _ => return Err(bad_line()),
+          };
+          for part in parts {
+            let mut words = part.split(' ');
+            match (words.next(), words.next()) {
+              (Some("capturing"), Some("with")) => {
+                let p = words.next().and_then(tile).ok_or_else(bad_line)?;
+                game.mode = Mode::ChainCapture(p);
+              }
+              (Some("captured"), Some(name)) => {
+                for name in Some(name).into_iter().chain(words) {
+                  let p = tile(name).ok_or_else(bad_line)?;
+                  match game.get_tile(p) {
+                    Occupied(player, piece) => game.set_tile(p, Captured(player, piece)),
+                    _ => return Err(bad_line()),
+                  }
+                }
+              }
+              (Some("quiet"), Some("turns")) => {
+                game.quiet_turns = words.next().and_then(|n| n.parse().ok()).ok_or_else(bad_line)?;
+              }
+              _ => return Err(bad_line()),
+            }
+          }
+        }
+      }
+    }
+    game.active_player = side.ok_or(AsciiError::NoSideToMove)?;
+    game.positions = vec![game.position_hash()];
+    match result {
+      Some(mode) => game.mode = mode,
+      None if game.mode == Mode::StartOfTurn => game.check_start_of_turn(),
+      None => (),
+    }
+    Ok(game)
+  }
+
+  /// Every complete move for the active player: either a step, or a
+  /// whole chain of jumps that the `ChainCapture` mode would split up
+  pub fn legal_moves(&self) -> Vec<Vec<Action>> {
+    let mut moves = vec![];
+    self.visit_moves(&mut vec![], &mut moves);
+    moves
+  }
+
+  fn visit_moves(&self, prefix : &mut Vec<Action>, moves : &mut Vec<Vec<Action>>) {
+    let mut actions = vec![];
+    self.possible_actions(&mut actions);
+    for a in actions {
+      prefix.push(a);
+      let mut game = self.clone();
+      game.apply_action(&a);
+      if let Mode::ChainCapture(_) = game.mode {
+        game.visit_moves(prefix, moves);
+      }
+      else {
+        moves.push(prefix.clone());
+      }
+      prefix.pop();
+    }
+  }
+
+  /// Writes a complete move in PDN, e.g. "11-15", or "22x15x8" for a
+  /// chain of jumps, listing every tile that the piece lands on
+  pub fn move_notation(&self, actions : &[Action]) -> String {
+    let mut notation = String::new();
+    for (i, a) in actions.iter().enumerate() {
+      let (from, to, separator) = match *a {
+        Action::Step { from, to } => (from, to, '-'),
+        Action::Jump { from, to, .. } => (from, to, 'x'),
+      };
+      if i == 0 {
+        notation.push_str(&self.square_number(from).to_string());
+      }
+      notation.push(separator);
+      notation.push_str(&self.square_number(to).to_string());
+    }
+    notation
+  }
+
+  /// Finds the complete move written in PDN. A chain of jumps can list every
+  /// tile it lands on, or just the first and last, e.g. "22x8".
+  pub fn parse_move(&self, text : &str) -> Result<Vec<Action>, MoveError> {
+    let notation = text.trim_end_matches(['!', '?']);
+    let jump = notation.contains('x');
+    let squares = notation.split(if jump { 'x' } else { '-' })
+      .map(|n| n.parse::<i32>())
+      .collect::<Result<Vec<i32>, _>>()
+      .map_err(|_| MoveError::Malformed(text.into()))?;
+    if squares.len() < 2 {
+      return Err(MoveError::Malformed(text.into()));
+    }
+    let matches : Vec<Vec<Action>> = self.legal_moves().into_iter().filter(|m| {
+      let mut landings = vec![];
+      for a in m {
+        match *a {
+          Action::Step { from, to } => landings.extend(&[from, to]),
+          Action::Jump { from, to, .. } => {
+            if landings.is_empty() {
+              landings.push(from);
+            }
+            landings.push(to);
+          }
+        }
+      }
+      let landings : Vec<i32> = landings.into_iter().map(|p| self.square_number(p)).collect();
+      let is_jump = match m[0] {
+        Action::Jump { .. } => true,
+        Action::Step { .. } => false,
+      };
+      is_jump == jump && (landings == squares
+        || (squares.len() == 2 && squares[0] == landings[0] && squares[1] == *landings.last().unwrap()))
+    }).collect();
+    match matches.len() {
+      0 => Err(MoveError::Illegal(text.into())),
+      1 => Ok(matches.into_iter().next().unwrap()),
+      _ => Err(MoveError::Ambiguous(text.into())),
+    }
   }
 
   fn set_tile(&mut self, p : Pos, tile : Tile) {
-    self.tiles[pos_index(p)] = tile;
+    self.tiles.set(p, tile);
   }
 
   fn get_tile(&self, p : Pos) -> Tile {
-    self.tiles[pos_index(p)]
+    self.tiles.get(p)
   }
 
   fn try_get_tile(&self, p : Pos) -> Option<Tile> {
-    if p.x >= 0 && p.x < BOARD_SIZE && p.y >= 0 && p.y < BOARD_SIZE {
-      Some(self.get_tile(p))
+    self.tiles.try_get(p)
+  }
+
+  /// The directions that the piece on a tile can capture in
+  fn capture_directions(&self, tile : Tile) -> &'static [Pos] {
+    match tile {
+      Occupied(_, Pawn) if self.rules.men_capture_backwards => ALL_DIRECTIONS,
+      t => possible_moves(t),
     }
-    else { None }
   }
 
+  /// The furthest a piece can travel in one direction
+  fn range(&self, tile : Tile) -> i32 {
+    match tile {
+      Occupied(_, King) if self.rules.flying_kings => self.size(),
+      _ => 1,
+    }
+  }
+
+  /// Visits the jumps that the piece on `start` can make. A flying king
+  /// that can land on several tiles beyond a piece must choose one where
+  /// it can carry on capturing, if there is one, under every rule set.
   fn visit_jumps_from_pos(&self, start : Pos, mut f : impl FnMut(Action)) {
+    let mut jumps = vec![];
+    self.visit_landings_from_pos(start, |a| jumps.push(a));
+    let captured = |a : &Action| match *a {
+      Action::Jump { capture, .. } => capture,
+      Action::Step { .. } => panic!("a step doesn't capture anything"),
+    };
+    // Each direction captures a different piece
+    let mut i = 0;
+    while i < jumps.len() {
+      let same_piece = jumps[i..].iter().take_while(|a| captured(a) == captured(&jumps[i])).count();
+      let landings = &jumps[i..i + same_piece];
+      let continuing : Vec<Action> = if landings.len() > 1 {
+        landings.iter().cloned().filter(|&a| self.clone().jump_piece(a)).collect()
+      }
+      else {
+        vec![]
+      };
+      if continuing.is_empty() {
+        landings.iter().cloned().for_each(&mut f);
+      }
+      else {
+        continuing.into_iter().for_each(&mut f);
+      }
+      i += same_piece;
+    }
+  }
+
+  /// Visits every jump that the piece on `start` could make, landing
+  /// on any empty tile beyond the piece that it captures
+  fn visit_landings_from_pos(&self, start : Pos, mut f : impl FnMut(Action)) {
     let start_tile = self.get_tile(start);
     let player = start_tile.player().unwrap();
-    for m in possible_moves(start_tile) {
-      let pos = start + *m;
-      if let Some(p) = self.try_get_tile(pos).and_then(|t| t.player()) {
-        if p != player {
-          let jump = pos + *m;
-          if let Some(Empty) = self.try_get_tile(jump) {
-            let a = Action::Jump{ from: start, capture: pos, to: jump};
-            f(a);
+    let range = self.range(start_tile);
+    for m in self.capture_directions(start_tile) {
+      // Flying kings can cross any number of empty tiles to reach the piece
+      let mut pos = start + *m;
+      for _ in 1..range {
+        if self.try_get_tile(pos) != Some(Empty) {
+          break;
+        }
+        pos += *m;
+      }
+      if let Some(target) = self.try_get_tile(pos) {
+        let can_take = match (start_tile, target) {
+          (Occupied(_, Pawn), Occupied(_, King)) => self.rules.men_capture_kings,
+          _ => true,
+        };
+        if can_take && target.player().is_some_and(|p| p != player) {
+          // ...and land on any empty tile beyond it
+          let mut jump = pos + *m;
+          for _ in 0..range {
+            if let Some(Empty) = self.try_get_tile(jump) {
+              let a = Action::Jump{ from: start, capture: pos, to: jump};
+              f(a);
+              jump += *m;
+            }
+            else {
+              break;
+            }
           }
         }
       }
@@ -157,52 +757,215 @@ impl Checkers {
   fn find_steps_from_pos(&self, start : Pos, actions : &mut Vec<Action>) {
     let start_tile = self.get_tile(start);
     for m in possible_moves(start_tile) {
-      let pos = start + *m;
-      if let Some(Empty) = self.try_get_tile(pos) {
-        actions.push(Action::Step{ from: start, to: pos});
+      let mut pos = start + *m;
+      for _ in 0..self.range(start_tile) {
+        if let Some(Empty) = self.try_get_tile(pos) {
+          actions.push(Action::Step{ from: start, to: pos});
+          pos += *m;
+        }
+        else {
+          break;
+        }
       }
     }
   }
 
   fn visit_player_pieces(&self, player : Player, mut f : impl FnMut(Pos)) {
+    let size = self.size();
     for (i, t) in self.tiles.iter().enumerate() {
       if t.player() == Some(player) {
         let p = Pos {
-          x: (i as i32) % BOARD_SIZE,
-          y: (i as i32) / BOARD_SIZE,
+          x: (i as i32) % size,
+          y: (i as i32) / size,
         };
         f(p);
       }
     }
   }
 
+  /// The value of the best chain of captures that starts with this jump
+  fn capture_value(&self, jump : Action) -> CaptureValue {
+    let (from, capture, to) = match jump {
+      Action::Jump { from, capture, to } => (from, capture, to),
+      Action::Step { .. } => panic!("a step doesn't capture anything"),
+    };
+    let by_king = self.get_tile(from).piece() == Some(King);
+    let king_taken = self.get_tile(capture).piece() == Some(King);
+    let mut game = self.clone();
+    let mut best : Option<CaptureValue> = None;
+    if game.jump_piece(jump) {
+      game.visit_jumps_from_pos(to, |a| {
+        let v = game.capture_value(a);
+        if best.as_ref().is_none_or(|b| v > *b) {
+          best = Some(v);
+        }
+      });
+    }
+    let rest = best.unwrap_or(CaptureValue { pieces: 0, by_king, kings: 0, king_order: vec![] });
+    let mut king_order = vec![king_taken];
+    king_order.extend(rest.king_order);
+    CaptureValue {
+      pieces: 1 + rest.pieces,
+      by_king,
+      kings: king_taken as i32 + rest.kings,
+      king_order,
+    }
+  }
+
+  /// Keep only the jumps that the capture priority rules allow
+  fn filter_captures(&self, actions : &mut Vec<Action>) {
+    if self.rules.capture_priority == CapturePriority::Free || actions.len() < 2 {
+      return;
+    }
+    let mut values : Vec<CaptureValue> = actions.iter().map(|&a| self.capture_value(a)).collect();
+    if self.rules.capture_priority == CapturePriority::Majority {
+      for v in values.iter_mut() {
+        *v = CaptureValue { pieces: v.pieces, by_king: false, kings: 0, king_order: vec![] };
+      }
+    }
+    let best = values.iter().max().cloned().unwrap();
+    let mut i = 0;
+    actions.retain(|_| {
+      i += 1;
+      values[i - 1] == best
+    });
+  }
+
+  /// Moves the jumping piece and marks the captured piece. If the
+  /// chain can't continue, this ends the move.
+  fn make_jump(&mut self, jump : Action) {
+    if let Action::Jump { to, .. } = jump {
+      if self.jump_piece(jump) {
+        self.mode = Mode::ChainCapture(to);
+      }
+      else {
+        self.end_move(to);
+      }
+    }
+  }
+
+  /// Moves the jumping piece and marks the captured piece, without
+  /// ending the move. Returns true if the chain of captures can continue.
+  fn jump_piece(&mut self, jump : Action) -> bool {
+    let (from, capture, to) = match jump {
+      Action::Jump { from, capture, to } => (from, capture, to),
+      Action::Step { .. } => panic!("a step doesn't capture anything"),
+    };
+    let tile_value = self.get_tile(from);
+    self.set_tile(from, Tile::Empty);
+    if let Occupied(player, piece) = self.get_tile(capture) {
+      self.set_tile(capture, Captured(player, piece));
+    }
+    self.set_tile(to, tile_value);
+    let crowned = self.is_crowning_tile(tile_value, to);
+    let crowning = self.rules.mid_capture_crowning;
+    if crowned && crowning == MidCaptureCrowning::EndsMove {
+      return false;
+    }
+    if crowned && crowning == MidCaptureCrowning::ContinuesAsKing {
+      self.king_check(to);
+    }
+    self.can_capture_a_piece(to)
+  }
+
+  /// Removes the captured pieces, crowns the piece that moved
+  /// if it is on the back row, and passes the turn
+  fn end_move(&mut self, to : Pos) {
+    let mut progress = self.get_tile(to).piece() == Some(Pawn);
+    let size = self.size();
+    for y in 0..size {
+      for x in 0..size {
+        if let Captured(_, _) = self.get_tile(Pos {x, y}) {
+          self.set_tile(Pos {x, y}, Tile::Empty);
+          progress = true;
+        }
+      }
+    }
+    self.king_check(to);
+    let player = self.active_player;
+    self.active_player_swap();
+    self.mode = Mode::StartOfTurn;
+    if progress {
+      self.quiet_turns = 0;
+      self.positions.clear();
+    }
+    else {
+      self.quiet_turns += 1;
+    }
+    let position = self.position_hash();
+    self.positions.push(position);
+    // A player who can't move loses, or wins at giveaway
+    if self.victory_check() || !self.has_legal_move() {
+      let winner = if self.rules.giveaway { self.active_player } else { player };
+      self.mode = Mode::Victory(winner);
+    }
+    else if self.draw_check(position) {
+      self.mode = Mode::Draw;
+    }
+  }
+
+  /// Identifies the position and the player to move, for detecting repetitions
+  fn position_hash(&self) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for tile in self.tiles.iter() {
+      tile.hash(&mut hasher);
+    }
+    self.active_player.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  /// Any landing means that there is a jump, so this doesn't need
+  /// to work out which landings the rules allow
+  fn has_legal_move(&self) -> bool {
+    let mut found = false;
+    let mut steps = vec![];
+    self.visit_player_pieces(self.active_player, |pos| {
+      self.visit_landings_from_pos(pos, |_| found = true);
+      self.find_steps_from_pos(pos, &mut steps);
+    });
+    found || !steps.is_empty()
+  }
+
+  fn draw_check(&self, position : u64) -> bool {
+    let no_progress = match self.rules.no_progress_limit {
+      // Each player has made this many moves
+      Some(limit) => self.quiet_turns >= 2 * limit,
+      None => false,
+    };
+    let repetition = self.rules.threefold_repetition
+      && self.positions.iter().filter(|&&p| p == position).count() >= 3;
+    no_progress || repetition
+  }
+
   /// Return true if the piece at pos can capture a piece
   /// in its next move
   fn can_capture_a_piece(&mut self, p : Pos) -> bool {
     let mut can_capture = false;
-    self.visit_jumps_from_pos(p, |_a| {
+    self.visit_landings_from_pos(p, |_a| {
       can_capture = true;
     });
     can_capture
   }
 
+  /// Return true if `tile` holds a normal piece, and `p` is
+  /// the final row at the other end of the board for it
+  fn is_crowning_tile(&self, tile : Tile, p : Pos) -> bool {
+    match tile {
+      Occupied(Black, Pawn) => p.y == 0,
+      Occupied(White, Pawn) => p.y == self.size()-1,
+      _ => false,
+    }
+  }
+
   /// Turn the tile at `pos` into a king if it is
   /// currently a normal piece, and just reached the
   /// final row at the other end of the board
   fn king_check(&mut self, p : Pos) {
     let tile_value = self.get_tile(p);
-    match tile_value {
-      Occupied(Black, Pawn) => {
-        if p.y == 0 {
-          self.set_tile(p, Occupied(Black, King));
-        }
+    if self.is_crowning_tile(tile_value, p) {
+      if let Occupied(player, _) = tile_value {
+        self.set_tile(p, Occupied(player, King));
       }
-      Occupied(White, Pawn) => {
-        if p.y == BOARD_SIZE-1 {
-          self.set_tile(p, Occupied(White, King));
-        }
-      }
-      _ => (),
     }
   }
 
@@ -220,7 +983,7 @@ impl Checkers {
         Occupied(Black, King) => black += 2,
         Occupied(White, Pawn) => white += 1,
         Occupied(White, King) => white += 2,
-        Tile::Empty => (),
+        Captured(_, _) | Tile::Empty => (),
       }
     }
     (white, black)
@@ -248,11 +1011,15 @@ impl Game for Checkers {
             self.find_steps_from_pos(pos, actions);
           });
         }
+        else {
+          self.filter_captures(actions);
+        }
       }
       Mode::ChainCapture(p) => {
         self.find_jumps_from_pos(p, actions);
+        self.filter_captures(actions);
       }
-      Mode::Victory(_) => (),
+      Mode::Victory(_) | Mode::Draw => (),
     }
   }
 
@@ -268,35 +1035,29 @@ impl Game for Checkers {
         let tile_value = self.get_tile(from);
         self.set_tile(from, Tile::Empty);
         self.set_tile(to, tile_value);
-        self.king_check(to);
-        self.active_player_swap();
-        self.mode = Mode::StartOfTurn;
+        self.end_move(to);
       }
-      Action::Jump { from, capture, to } => {
-        let tile_value = self.get_tile(from);
-        self.set_tile(from, Tile::Empty);
-        self.set_tile(capture, Tile::Empty);
-        self.set_tile(to, tile_value);
-        self.king_check(to);
-        if self.can_capture_a_piece(to) {
-          self.mode = Mode::ChainCapture(to);
-        }
-        else {
-          self.active_player_swap();
-          self.mode = Mode::StartOfTurn;
-        }
-        if self.victory_check() {
-          self.mode = Mode::Victory(tile_value.player().unwrap());
-        }
+      Action::Jump { .. } => {
+        self.make_jump(*a);
       }
     }
   }
 
+  /// The material advantage, or a larger value once the game is won, or 0
+  /// once it is drawn. At giveaway the advantage is inverted, as having
+  /// fewer pieces is better.
   fn player_score(&self, player : i64) -> f64 {
     let (white, black) = self.piece_count();
+    let white_advantage = match self.mode {
+      Mode::Victory(White) => 1000,
+      Mode::Victory(Black) => -1000,
+      Mode::Draw => 0,
+      _ if self.rules.giveaway => black - white,
+      _ => white - black,
+    };
     match player {
-      0 => (white - black) as f64,
-      1 => (black - white) as f64,
+      0 => white_advantage as f64,
+      1 => -white_advantage as f64,
       _ => panic!("checkers is a two-player game"),
     }
   }
@@ -308,12 +1069,122 @@ impl Game for Checkers {
       _ => None,
     }
   }
+
+  fn is_draw(&self) -> bool {
+    self.mode == Mode::Draw
+  }
+}
+
+impl Stochastic for Checkers {}
+
+impl Recordable for Checkers {
+  const GAME_TYPE : &'static str = "checkers";
+
+  /// The rules and the position, e.g. "russian giveaway W:W21,22:B1,2".
+  /// Rules that don't match a preset can't be set up again.
+  fn config(&self) -> String {
+    let mut config = self.rules.name().unwrap_or("custom").to_string();
+    if self.rules.giveaway {
+      config.push_str(" giveaway");
+    }
+    format!("{} {}", config, self.to_fen())
+  }
+
+  fn from_config(config : &str) -> Option<Checkers> {
+    let words : Vec<&str> = config.split_whitespace().collect();
+    let (fen, names) = words.split_last()?;
+    let mut rules = CheckersRules::from_name(names.first()?)?;
+    match names.get(1) {
+      Some(&"giveaway") if names.len() == 2 => rules = rules.giveaway(),
+      None => (),
+      Some(_) => return None,
+    }
+    Checkers::from_fen(rules, fen).ok()
+  }
+}
+
+/// Each action is one step or jump, e.g. "11-15" or "22x15"
+impl Notation for Checkers {
+  fn format_action(&self, action : &Action) -> String {
+    self.move_notation(&[*action])
+  }
+
+  /// Reads a single step or jump, or a whole move in PDN, which gives
+  /// its first jump
+  fn parse_action(&self, text : &str) -> Result<Action, NotationError> {
+    let text = text.trim();
+    match self.parse_move(text) {
+      Ok(chain) => Ok(chain[0]),
+      Err(MoveError::Malformed(t)) => Err(NotationError::Malformed(t)),
+      Err(MoveError::Ambiguous(t)) => Err(NotationError::Ambiguous(t)),
+      // The text might be part of a chain
+      Err(MoveError::Illegal(_)) => find_action(self, text, |written| written == text),
+    }
+  }
+}
+
+/// Draws the board with white at the bottom, using w and b for men and
+/// W and B for kings, e.g.
+///
+/// ```text
+///  8   b   b   b   b
+///  7 b   .   b   .
+///  6   .   .   .   .
+///  5 w   w   .   b
+///  4   .   .   w   .
+///  3 .   w   .   b
+///  2   w   .   .   .
+///  1 w   w   .   w
+///    a b c d e f g h
+/// White to move, capturing with f4, captured g3
+/// ```
+///
+/// Pieces captured earlier in a chain are drawn until the chain is over.
+/// The result follows on its own line.
+impl fmt::Display for Checkers {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    let size = self.size();
+    let mut captured = vec![];
+    write!(f, "{}", draw_ascii(size, size, true, |p| match self.get_tile(p) {
+      Occupied(player, piece) | Captured(player, piece) => {
+        let letter = if player == White { 'w' } else { 'b' };
+        if piece == King { letter.to_ascii_uppercase() } else { letter }
+      }
+      Tile::Empty if (p.x + p.y) % 2 == 0 => '.',
+      Tile::Empty => ' ',
+    }))?;
+    for y in 0..size {
+      for x in 0..size {
+        if let Captured(_, _) = self.get_tile(Pos {x, y}) {
+          captured.push(Pos {x, y}.algebraic());
+        }
+      }
+    }
+    write!(f, "{} to move", if self.active_player == White { "White" } else { "Black" })?;
+    if let Mode::ChainCapture(p) = self.mode {
+      write!(f, ", capturing with {}", p.algebraic())?;
+    }
+    if !captured.is_empty() {
+      write!(f, ", captured {}", captured.join(" "))?;
+    }
+    if self.quiet_turns > 0 {
+      write!(f, ", quiet turns {}", self.quiet_turns)?;
+    }
+    writeln!(f)?;
+    match self.mode {
+      Mode::Victory(White) => writeln!(f, "White won"),
+      Mode::Victory(Black) => writeln!(f, "Black won"),
+      Mode::Draw => writeln!(f, "Drawn"),
+      Mode::StartOfTurn | Mode::ChainCapture(_) => Ok(()),
+    }
+  }
 }
 
 fn draw_checkers(game : &Checkers, player_actions : &[Action], context : &Context, graphics : &mut G2d) {
   clear([1.0; 4], graphics);
-  for y in 0..BOARD_SIZE {
-    for i in (0..BOARD_SIZE).step_by(2) {
+  let size = game.size();
+  for y in 0..size {
+    for i in (0..size).step_by(2) {
       let x = i + (y % 2);
       rectangle(
         [0.0, 0.0, 0.0, 1.0], // black
@@ -327,6 +1198,7 @@ fn draw_checkers(game : &Checkers, player_actions : &[Action], context : &Contex
         Occupied(White, Pawn) => Some([0.0, 1.0, 0.0, 1.0]),
         Occupied(Black, King) => Some([0.5, 0.0, 0.0, 1.0]),
         Occupied(White, King) => Some([0.0, 0.5, 0.0, 1.0]),
+        Captured(_, _) => Some([0.5, 0.5, 0.5, 1.0]),
         Empty => None,
       };
       if let Some(c) = colour {
@@ -357,29 +1229,73 @@ fn draw_checkers(game : &Checkers, player_actions : &[Action], context : &Contex
   }
 }
 
+fn window_title(rules : CheckersRules) -> String {
+  if rules.giveaway { "Checkers (giveaway)".into() } else { "Checkers".into() }
+}
+
+/// Adds an action to the game record, noting who played it. A human
+/// and an agent can both play for the same side.
+fn record_action(history : &mut Vec<Action>, movers : &mut Vec<String>, name : String, a : Action) {
+  history.push(a);
+  if !movers.contains(&name) {
+    movers.push(name);
+  }
+}
+
 /// Load a graphical, interactive checkers game
-pub fn play_checkers<A, B>(mut agent_a : A, mut agent_b : B)
+pub fn play_checkers<A, B>(mut rules : CheckersRules, mut agent_a : A, mut agent_b : B)
   where A : GameAgent<Checkers>, B : GameAgent<Checkers>
 {
   println!("Checkers!");
-  let mut game = Checkers::new();
+  let mut game = Checkers::new(rules);
+  let board_pixels = game.size() as f64 * 60.0;
   let mut rng = StdRng::from_entropy();
 
+  let agents : &mut [&mut dyn GameAgent<Checkers>] = &mut [&mut agent_a, &mut agent_b];
+
   let mut window: PistonWindow =
-    WindowSettings::new("Checkers", [480, 480])
+    WindowSettings::new(window_title(rules), [board_pixels, board_pixels])
     .exit_on_esc(true).build().unwrap();
 
   let mut mouse_pos = [0.0, 0.0];
   let mut player_actions = vec![];
+  // The actions so far, and who played each side, for saving the game
+  let mut history = vec![];
+  let mut movers : [Vec<String> ; 2] = [vec![], vec![]];
   
   while let Some(event) = window.next() {
     if let Some(Button::Keyboard(key)) = event.press_args() {
       if key == Key::Space {
         player_actions.clear();
-        agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng);
+        let side = game.active_player() as usize;
+        if let Some(a) = take_action(agents, &mut game, &mut rng) {
+          record_action(&mut history, &mut movers[side], agents[side].name(), a);
+        }
       }
       if key == Key::Return {
-        game = Checkers::new();
+        game = Checkers::new(rules);
+        history.clear();
+        movers = [vec![], vec![]];
+      }
+      // Toggle antidraughts, and start a new game
+      if key == Key::G {
+        rules.giveaway = !rules.giveaway;
+        game = Checkers::new(rules);
+        player_actions.clear();
+        history.clear();
+        movers = [vec![], vec![]];
+        window.set_title(window_title(rules));
+      }
+      // Save the game so far to checkers.pdn
+      if key == Key::S {
+        let names = |side : &Vec<String>| if side.is_empty() { "?".into() } else { side.join(" and ") };
+        let tags = [("Event", "Workshop checkers".to_string()),
+          ("White", names(&movers[0])), ("Black", names(&movers[1]))];
+        let pdn = crate::pdn::write_pdn(&tags, &Checkers::new(rules), &history);
+        match crate::pgn::append_pgn("checkers.pdn", &pdn) {
+          Ok(()) => println!("Saved the game to checkers.pdn"),
+          Err(e) => println!("Couldn't save the game: {}", e),
+        }
       }
     }
     if let Some(p) = event.mouse_cursor_args() {
@@ -399,12 +1315,16 @@ pub fn play_checkers<A, B>(mut agent_a : A, mut agent_b : B)
               Action::Jump { to, ..} => to,
             };
             if to == pos {
+              let side = game.active_player() as usize;
+              record_action(&mut history, &mut movers[side], "Human".into(), a);
               game.apply_action(&a);
               player_actions.clear();
               // AI response
               if game.mode == Mode::StartOfTurn {
                 loop {
-                  if agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng) {
+                  let side = game.active_player() as usize;
+                  if let Some(a) = take_action(agents, &mut game, &mut rng) {
+                    record_action(&mut history, &mut movers[side], agents[side].name(), a);
                     // loop to complete chains, if needed
                     if let Mode::ChainCapture(_) = game.mode {
                       continue;
@@ -437,3 +1357,115 @@ pub fn play_checkers<A, B>(mut agent_a : A, mut agent_b : B)
   }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The lines of a drawn board, without the indentation or
+  /// the padding after the last tile
+  fn lines(text : &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+  }
+
+  /// The available moves, in draughts notation
+  fn moves(game : &Checkers) -> Vec<String> {
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    actions.iter().map(|a| game.format_action(a)).collect()
+  }
+
+  #[test]
+  fn majority_capture_must_take_the_most_pieces() {
+    // 28x17 takes one piece, but 28x19x10 takes two
+    let game = Checkers::from_fen(CheckersRules::international(), "W:W28:B22,23,14").unwrap();
+    assert_eq!(moves(&game), ["28x19"]);
+    // American checkers lets the player choose
+    let game = Checkers::from_fen(CheckersRules::american(), "B:B10:W14,15,23").unwrap();
+    assert_eq!(moves(&game), ["10x17", "10x19"]);
+  }
+
+  #[test]
+  fn men_capture_backwards_in_international_draughts() {
+    let game = Checkers::from_fen(CheckersRules::international(), "W:W28:B32").unwrap();
+    assert_eq!(moves(&game), ["28x37"]);
+    let game = Checkers::from_fen(CheckersRules::american(), "B:B15:W10").unwrap();
+    assert_eq!(moves(&game), ["15-18", "15-19"]);
+  }
+
+  #[test]
+  fn flying_king_lands_where_it_can_keep_capturing() {
+    // The king could land on 32, 28, 23, 19, 14, 10 or 5 after taking 37,
+    // but only from 28 can it go on to take 33
+    let game = Checkers::from_fen(CheckersRules::international(), "W:WK46:B37,33").unwrap();
+    assert_eq!(moves(&game), ["46x28"]);
+    // With nothing more to take, it can land anywhere
+    let game = Checkers::from_fen(CheckersRules::international(), "W:WK46:B37").unwrap();
+    assert_eq!(moves(&game), ["46x32", "46x28", "46x23", "46x19", "46x14", "46x10", "46x5"]);
+  }
+
+  #[test]
+  fn men_passing_the_back_row_mid_capture_are_not_crowned() {
+    let mut game = Checkers::from_fen(CheckersRules::international(), "W:W12:B8,9,45").unwrap();
+    while game.active_player() == 0 {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      assert_eq!(actions.len(), 1);
+      game.apply_action(&actions[0]);
+    }
+    assert!(game.get_tile(game.square_at(14).unwrap()) == Occupied(White, Pawn));
+  }
+
+  #[test]
+  fn losing_the_last_piece_wins_at_giveaway() {
+    let mut game = Checkers::from_fen(CheckersRules::american().giveaway(), "B:B10:W14").unwrap();
+    game.apply_action(&game.parse_action("10x17").unwrap());
+    assert_eq!(game.winner(), Some(0));
+    assert!(game.player_score(0) > 0.0 && game.player_score(1) < 0.0);
+  }
+
+  #[test]
+  fn set_up_position_with_a_side_out_of_pieces_is_over() {
+    let rules = CheckersRules::american();
+    // Black has no pieces, so white wins whoever is to move
+    assert_eq!(Checkers::from_fen(rules, "W:W21:B").unwrap().winner(), Some(0));
+    assert_eq!(Checkers::from_fen(rules, "B:W21:B").unwrap().winner(), Some(0));
+    // ...and at giveaway, black wins
+    assert_eq!(Checkers::from_fen(rules.giveaway(), "W:W21:B").unwrap().winner(), Some(1));
+    assert_eq!(Checkers::from_fen(rules.giveaway(), "B:W21:B").unwrap().winner(), Some(1));
+    // White can't move its man on 4
+    assert_eq!(Checkers::from_fen(rules, "W:W4:B1").unwrap().winner(), Some(1));
+    assert_eq!(Checkers::from_fen(rules.giveaway(), "W:W4:B1").unwrap().winner(), Some(0));
+  }
+
+  #[test]
+  fn chain_capture_reads_back_from_ascii() {
+    let text = "
+       8   .   b   .   b
+       7 b   .   .   .
+       6   b   .   .   .
+       5 b   .   .   b
+       4   w   .   w   .
+       3 .   w   .   b
+       2   .   w   .   .
+       1 w   w   w   .
+         a b c d e f g h
+      White to move, capturing with f4, captured g3
+    ";
+    let game = Checkers::from_ascii(CheckersRules::american(), text).unwrap();
+    assert!(game.mode == Mode::ChainCapture(Pos {x: 5, y: 3}));
+    assert!(game.get_tile(Pos {x: 6, y: 2}) == Captured(Black, Pawn));
+    assert_eq!(lines(&game.to_string()), lines(text));
+  }
+
+  #[test]
+  fn starting_position_reads_back_from_ascii() {
+    for &name in ["american", "international", "russian"].iter() {
+      let rules = CheckersRules::from_name(name).unwrap();
+      let game = Checkers::new(rules);
+      let read = Checkers::from_ascii(rules, &game.to_string()).unwrap();
+      assert_eq!(read.to_string(), game.to_string());
+      assert_eq!(read.to_fen(), game.to_fen());
+    }
+  }
+}
@@ -1,20 +1,28 @@
 
+use std::fmt;
 use piston_window::*;
-use rand::{SeedableRng, rngs::StdRng};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 use crate::utils::*;
 use crate::agents::*;
+use crate::record::Recordable;
+use crate::notation::{Notation, NotationError};
 
 /// Holds the contents of a tile
 #[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 enum Tile {
   Occupied(Player, Piece),
   Empty,
 }
 
 /// The type of piece
-#[derive(Copy, Clone, PartialEq)]
-enum Piece {
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Piece {
   Pawn, // normal pawn
   Bishop,
   Knight,
@@ -25,10 +33,15 @@ enum Piece {
   HoppedPawn, // just moved two spaces
   StartingKing, // can castle
   StartingRook, // can castle
+  PromotedQueen, // returns to the reserve as a pawn in crazyhouse
+  PromotedRook,
+  PromotedBishop,
+  PromotedKnight,
 }
 
 /// The two competing players
 #[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 enum Player {
   White,
   Black,
@@ -36,12 +49,16 @@ enum Player {
 
 /// The mode that the game is in.
 #[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 enum Mode {
   /// The active player can choose any move
   Turn,
 
-  /// One of the players won
+  /// One of the players won, by checkmate or a variant's own rule
   Victory(Player),
+
+  /// Stalemate
+  Draw,
 }
 
 use Tile::*;
@@ -56,6 +73,82 @@ impl Tile {
       Empty => None,
     }
   }
+
+  /// The piece on the tile, ignoring whether it has moved yet
+  fn kind(self) -> Option<Piece> {
+    match self {
+      Occupied(_, StartingPawn) | Occupied(_, HoppedPawn) => Some(Pawn),
+      Occupied(_, StartingKing) => Some(King),
+      Occupied(_, StartingRook) => Some(Rook),
+      Occupied(_, PromotedQueen) => Some(Queen),
+      Occupied(_, PromotedRook) => Some(Rook),
+      Occupied(_, PromotedBishop) => Some(Bishop),
+      Occupied(_, PromotedKnight) => Some(Knight),
+      Occupied(_, p) => Some(p),
+      Empty => None,
+    }
+  }
+}
+
+impl Player {
+  fn other(self) -> Player {
+    match self { White => Black, Black => White }
+  }
+
+  /// The direction that the player's pawns move in
+  fn forwards(self) -> i32 {
+    match self { White => 1, Black => -1 }
+  }
+
+  fn back_row(self) -> i32 {
+    match self { White => 0, Black => BOARD_SIZE - 1 }
+  }
+
+  fn index(self) -> usize {
+    match self { White => 0, Black => 1 }
+  }
+}
+
+/// Rule changes that turn chess into one of its variants
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+  Standard,
+  /// Captured pieces join the capturer's reserve, and
+  /// can be dropped back onto the board as a move
+  Crazyhouse,
+  /// A capture explodes, removing the capturing piece and every piece
+  /// other than a pawn next to it. Exploding the enemy king wins.
+  Atomic,
+  /// Checking the enemy king for the third time wins
+  ThreeCheck,
+  /// Moving your king to one of the four central tiles wins
+  KingOfTheHill,
+}
+
+impl Variant {
+  /// Looks up a variant by name, e.g. "atomic"
+  pub fn from_name(name : &str) -> Option<Variant> {
+    match name {
+      "standard" => Some(Variant::Standard),
+      "crazyhouse" => Some(Variant::Crazyhouse),
+      "atomic" => Some(Variant::Atomic),
+      "threecheck" => Some(Variant::ThreeCheck),
+      "koth" | "kingofthehill" => Some(Variant::KingOfTheHill),
+      _ => None,
+    }
+  }
+
+  /// The name that `from_name` reads
+  pub fn name(self) -> &'static str {
+    match self {
+      Variant::Standard => "standard",
+      Variant::Crazyhouse => "crazyhouse",
+      Variant::Atomic => "atomic",
+      Variant::ThreeCheck => "threecheck",
+      Variant::KingOfTheHill => "koth",
+    }
+  }
 }
 
 /// The size of the board (which is assumed to be square)
@@ -63,36 +156,740 @@ const BOARD_SIZE : i32 = 8;
 
 type Board = crate::utils::Board<Tile>;
 
-#[derive(Debug, Copy, Clone)]
+const KNIGHT_MOVES : &[Pos] =
+  &[Pos {x: 1, y: 2}, Pos {x: 2, y: 1}, Pos {x: 2, y: -1}, Pos {x: 1, y: -2},
+    Pos {x: -1, y: -2}, Pos {x: -2, y: -1}, Pos {x: -2, y: 1}, Pos {x: -1, y: 2}];
+const STRAIGHT : &[Pos] =
+  &[Pos {x: 1, y: 0}, Pos {x: -1, y: 0}, Pos {x: 0, y: 1}, Pos {x: 0, y: -1}];
+const DIAGONAL : &[Pos] =
+  &[Pos {x: 1, y: 1}, Pos {x: 1, y: -1}, Pos {x: -1, y: 1}, Pos {x: -1, y: -1}];
+const ALL_DIRECTIONS : &[Pos] =
+  &[Pos {x: 1, y: 0}, Pos {x: -1, y: 0}, Pos {x: 0, y: 1}, Pos {x: 0, y: -1},
+    Pos {x: 1, y: 1}, Pos {x: 1, y: -1}, Pos {x: -1, y: 1}, Pos {x: -1, y: -1}];
+
+/// The knight placements for each value of the last digit of a
+/// Chess960 position number, as indices into the five tiles left
+/// once the bishops and queen are placed
+const KNIGHT_TABLE : [(usize, usize) ; 10] =
+  [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+/// The pieces that can be held in a crazyhouse reserve
+const RESERVE_PIECES : [Piece ; 5] = [Pawn, Knight, Bishop, Rook, Queen];
+
+/// The pieces that a pawn can promote to, queen first
+const PROMOTION_PIECES : [Piece ; 4] = [Queen, Rook, Bishop, Knight];
+
+/// The Chess960 number of the standard starting position
+pub const STANDARD_POSITION : u32 = 518;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Action {
-  Move { from : Pos, to : Pos },
+  /// `promotion` is the piece that a pawn reaching the far row becomes
+  Move { from : Pos, to : Pos, promotion : Option<Piece> },
+  /// The king and the rook swap sides as in Chess960. The king always
+  /// ends on the c or g file, and the rook next to it on the d or f file.
   Castle { king : Pos, king_to : Pos, rook : Pos, rook_to : Pos },
+  /// Place a piece from the reserve (crazyhouse only)
+  Drop { piece : Piece, to : Pos },
+}
+
+/// Reads a tile name in algebraic notation, e.g. "e4"
+pub fn parse_square(name : &str) -> Option<Pos> {
+  let bytes = name.as_bytes();
+  if bytes.len() != 2 {
+    return None;
+  }
+  let p = Pos { x: bytes[0] as i32 - b'a' as i32, y: bytes[1] as i32 - b'1' as i32 };
+  if p.x >= 0 && p.x < BOARD_SIZE && p.y >= 0 && p.y < BOARD_SIZE { Some(p) } else { None }
+}
+
+/// The letter for a piece in FEN and algebraic notation,
+/// in upper case
+fn piece_letter(piece : Piece) -> char {
+  match piece {
+    Pawn | StartingPawn | HoppedPawn => 'P',
+    Knight | PromotedKnight => 'N',
+    Bishop | PromotedBishop => 'B',
+    Rook | StartingRook | PromotedRook => 'R',
+    Queen | PromotedQueen => 'Q',
+    King | StartingKing => 'K',
+  }
 }
 
+/// The reasons that `Chess::from_fen` can reject a position
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+  /// The FEN needs at least the pieces, side to move, castling
+  /// and en passant fields. The two clocks can be left out.
+  MissingField(&'static str),
+  TooManyFields,
+  /// The piece placement must have eight rows separated by '/'
+  WrongRowCount(usize),
+  /// A row (numbered from 8 down to 1) doesn't cover eight tiles
+  WrongRowLength(i32),
+  UnknownPiece(char),
+  /// Each side must have exactly one king, unless it was exploded in atomic
+  WrongKingCount(&'static str),
+  /// A pawn is on the first or last row
+  PawnOnEndRow(String),
+  BadSideToMove(String),
+  BadCastling(char),
+  /// The castling field names a rook or king that isn't in place
+  NoPieceToCastle(char),
+  BadEnPassant(String),
+  BadClock(String),
+}
+
+impl fmt::Display for FenError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FenError::MissingField(field) => write!(f, "the {} field is missing", field),
+      FenError::TooManyFields => write!(f, "there are more than six fields"),
+      FenError::WrongRowCount(n) => write!(f, "expected 8 rows of pieces, found {}", n),
+      FenError::WrongRowLength(row) => write!(f, "row {} doesn't have 8 tiles", row),
+      FenError::UnknownPiece(c) => write!(f, "unknown piece '{}'", c),
+      FenError::WrongKingCount(side) => write!(f, "{} must have exactly one king", side),
+      FenError::PawnOnEndRow(square) => write!(f, "there is a pawn on {}, on an end row", square),
+      FenError::BadSideToMove(s) => write!(f, "the side to move should be 'w' or 'b', not '{}'", s),
+      FenError::BadCastling(c) => write!(f, "unknown castling right '{}'", c),
+      FenError::NoPieceToCastle(c) => write!(f, "castling right '{}' has no matching king and rook", c),
+      FenError::BadEnPassant(s) => write!(f, "'{}' isn't a possible en passant square", s),
+      FenError::BadClock(s) => write!(f, "'{}' isn't a valid move count", s),
+    }
+  }
+}
+
+impl std::error::Error for FenError {}
+
+/// The reasons that `Chess::parse_san` or `Chess::parse_long_algebraic`
+/// can reject a move
+#[derive(Debug, Clone, PartialEq)]
+pub enum SanError {
+  /// The text isn't written in the expected notation
+  Malformed(String),
+  /// No legal move matches the text
+  Illegal(String),
+  /// More than one legal move matches the text
+  Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SanError::Malformed(san) => write!(f, "'{}' isn't a move in algebraic notation", san),
+      SanError::Illegal(san) => write!(f, "'{}' isn't a legal move", san),
+      SanError::Ambiguous(san) => write!(f, "'{}' could be more than one move", san),
+    }
+  }
+}
+
+impl std::error::Error for SanError {}
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Chess {
   board : Board,
   active_player : Player,
   mode : Mode,
+  variant : Variant,
+  /// The number of each of `RESERVE_PIECES` held by each player
+  reserves : [[i32 ; 5] ; 2],
+  /// The number of times each player has given check
+  checks : [i32 ; 2],
+  /// The number of moves since a pawn moved or a piece was captured
+  halfmove_clock : i32,
+  /// Starts at 1, and goes up after each of black's moves
+  fullmove_number : i32,
 }
 
 impl Chess {
 
+  /// The standard starting position
   pub fn new() -> Chess {
+    Chess::chess960(STANDARD_POSITION)
+  }
+
+  /// One of the 960 Fischer Random starting positions, by its Scharnagl
+  /// number. Position 518 is the standard one.
+  pub fn chess960(index : u32) -> Chess {
+    assert!(index < 960, "there are only 960 starting positions");
+    let mut rank = [None ; 8];
+    let mut n = index as usize;
+    // Bishops on a light tile and then a dark one, then the queen,
+    // then the knights, and the remaining three tiles are R K R
+    rank[(n % 4) * 2 + 1] = Some(Bishop);
+    n /= 4;
+    rank[(n % 4) * 2] = Some(Bishop);
+    n /= 4;
+    let place = |rank : &mut [Option<Piece> ; 8], i : usize, piece : Piece| {
+      let x = (0..8).filter(|&x| rank[x].is_none()).nth(i).unwrap();
+      rank[x] = Some(piece);
+    };
+    place(&mut rank, n % 6, Queen);
+    n /= 6;
+    let (a, b) = KNIGHT_TABLE[n];
+    // Place the second knight first, so the first one's index still holds
+    place(&mut rank, b, Knight);
+    place(&mut rank, a, Knight);
+    for &piece in [StartingRook, StartingKing, StartingRook].iter() {
+      place(&mut rank, 0, piece);
+    }
+    let mut back_row = [Pawn ; 8];
+    for x in 0..8 {
+      back_row[x] = rank[x].unwrap();
+    }
+    Chess::with_back_row(back_row)
+  }
+
+  /// A random Chess960 starting position, chosen by a seed
+  pub fn chess960_from_seed(seed : u64) -> Chess {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Chess::chess960(rng.gen_range(0, 960))
+  }
+
+  /// A starting position with the pieces on the back rows given by
+  /// letters from the a file to the h file, e.g. "RNBQKBNR". It must have
+  /// the usual pieces, with the bishops on opposite colours and the king
+  /// between the rooks.
+  pub fn from_back_row(pieces : &str) -> Option<Chess> {
+    let mut back_row = [Pawn ; 8];
+    let mut count = 0;
+    for (i, c) in pieces.chars().enumerate() {
+      if i >= 8 {
+        return None;
+      }
+      back_row[i] = match c.to_ascii_uppercase() {
+        'K' => StartingKing,
+        'Q' => Queen,
+        'R' => StartingRook,
+        'B' => Bishop,
+        'N' => Knight,
+        _ => return None,
+      };
+      count += 1;
+    }
+    let find = |piece| (0..8).filter(|&x| back_row[x] == piece).collect::<Vec<_>>();
+    let (kings, queens, rooks, bishops, knights) =
+      (find(StartingKing), find(Queen), find(StartingRook), find(Bishop), find(Knight));
+    let valid = count == 8
+      && kings.len() == 1 && queens.len() == 1 && rooks.len() == 2 && knights.len() == 2
+      && bishops.len() == 2 && bishops[0] % 2 != bishops[1] % 2
+      && rooks[0] < kings[0] && kings[0] < rooks[1];
+    if valid { Some(Chess::with_back_row(back_row)) } else { None }
+  }
+
+  fn with_back_row(back_row : [Piece ; 8]) -> Chess {
     let mut board = Board::new(Tile::Empty, BOARD_SIZE);
-    for y in 0..3 {
-      for i in (0..BOARD_SIZE).step_by(2) {
-        let x = i + (y % 2);
-        board.set(Pos {x, y}, Occupied(White, Pawn));
+    for x in 0..BOARD_SIZE {
+      let piece = back_row[x as usize];
+      board.set(Pos {x, y: 0}, Occupied(White, piece));
+      board.set(Pos {x, y: 1}, Occupied(White, StartingPawn));
+      board.set(Pos {x, y: BOARD_SIZE-2}, Occupied(Black, StartingPawn));
+      board.set(Pos {x, y: BOARD_SIZE-1}, Occupied(Black, piece));
+    }
+    Chess {
+      board, active_player : White, mode: Mode::Turn, variant: Variant::Standard,
+      reserves: [[0 ; 5] ; 2], checks: [0 ; 2], halfmove_clock: 0, fullmove_number: 1,
+    }
+  }
+
+  /// The same position, played as a variant
+  pub fn with_variant(self, variant : Variant) -> Chess {
+    // The variant's rules decide whether the position is already over
+    let mut game = Chess { variant, mode: Mode::Turn, ..self };
+    game.update_mode();
+    game
+  }
+
+  pub fn variant(&self) -> Variant {
+    self.variant
+  }
+
+  /// Starts at 1, and goes up after each of black's moves
+  pub fn fullmove_number(&self) -> i32 {
+    self.fullmove_number
+  }
+
+  /// Sets up a position from Forsyth-Edwards Notation, played as a variant.
+  /// Castling rights can be given as KQkq, or as the files of the rooks for
+  /// Chess960 (Shredder-FEN). Variant state, like crazyhouse reserves, isn't
+  /// included, but an atomic position can have a side whose king has exploded.
+  pub fn from_fen(variant : Variant, fen : &str) -> Result<Chess, FenError> {
+    Ok(Chess::read_fen(fen, variant == Variant::Atomic)?.with_variant(variant))
+  }
+
+  /// Reads FEN, allowing a side with no king when `exploded` is set,
+  /// for the end of an atomic game
+  fn read_fen(fen : &str, exploded : bool) -> Result<Chess, FenError> {
+    let mut fields = fen.split_whitespace();
+    let mut next_field = |name| fields.next().ok_or(FenError::MissingField(name));
+    let (placement, side, castling, en_passant) =
+      (next_field("piece placement")?, next_field("side to move")?, next_field("castling")?,
+        next_field("en passant")?);
+    let halfmove = fields.next().unwrap_or("0");
+    let fullmove = fields.next().unwrap_or("1");
+    if fields.next().is_some() {
+      return Err(FenError::TooManyFields);
+    }
+
+    let mut game = Chess {
+      board: Board::new(Empty, BOARD_SIZE), active_player: White, mode: Mode::Turn,
+      variant: Variant::Standard, reserves: [[0 ; 5] ; 2], checks: [0 ; 2],
+      halfmove_clock: 0, fullmove_number: 1,
+    };
+    let rows : Vec<&str> = placement.split('/').collect();
+    if rows.len() != BOARD_SIZE as usize {
+      return Err(FenError::WrongRowCount(rows.len()));
+    }
+    // The first row is black's back row
+    for (i, row) in rows.iter().enumerate() {
+      let y = BOARD_SIZE - 1 - i as i32;
+      let mut x = 0;
+      for c in row.chars() {
+        if let Some(n) = c.to_digit(10) {
+          x += n as i32;
+          continue;
+        }
+        let player = if c.is_ascii_uppercase() { White } else { Black };
+        let piece = match c.to_ascii_lowercase() {
+          'p' if y == 0 || y == BOARD_SIZE - 1 => {
+            return Err(FenError::PawnOnEndRow(Pos {x, y}.algebraic()));
+          }
+          'p' if y == player.back_row() + player.forwards() => StartingPawn,
+          'p' => Pawn,
+          'n' => Knight,
+          'b' => Bishop,
+          'r' => Rook,
+          'q' => Queen,
+          'k' => King,
+          _ => return Err(FenError::UnknownPiece(c)),
+        };
+        if x >= BOARD_SIZE {
+          return Err(FenError::WrongRowLength(y + 1));
+        }
+        game.board.set(Pos {x, y}, Occupied(player, piece));
+        x += 1;
+      }
+      if x != BOARD_SIZE {
+        return Err(FenError::WrongRowLength(y + 1));
       }
     }
-    for y in (BOARD_SIZE-3)..BOARD_SIZE {
-      for i in (0..BOARD_SIZE).step_by(2) {
-        let x = i + (y % 2);
-        board.set(Pos {x, y}, Occupied(Black, Pawn));
+    for &(player, name) in [(White, "white"), (Black, "black")].iter() {
+      let kings = game.board.iter().filter(|&&t| t == Occupied(player, King)).count();
+      if kings > 1 || kings == 0 && !exploded {
+        return Err(FenError::WrongKingCount(name));
       }
     }
-    Chess { board, active_player : White, mode: Mode::Turn }
+
+    game.active_player = match side {
+      "w" => White,
+      "b" => Black,
+      _ => return Err(FenError::BadSideToMove(side.into())),
+    };
+
+    if castling != "-" {
+      for c in castling.chars() {
+        let player = if c.is_ascii_uppercase() { White } else { Black };
+        let y = player.back_row();
+        let king = match game.find_king(player) {
+          Some(k) if k.y == y => k,
+          _ => return Err(FenError::NoPieceToCastle(c)),
+        };
+        let is_rook = |x : i32| game.board.get(Pos {x, y}).kind() == Some(Rook)
+          && game.board.get(Pos {x, y}).player() == Some(player);
+        // K and Q mean the outermost rook on that side of the king
+        let rook_x = match c.to_ascii_lowercase() {
+          'k' => (king.x + 1..BOARD_SIZE).rev().find(|&x| is_rook(x)),
+          'q' => (0..king.x).find(|&x| is_rook(x)),
+          f @ 'a' ..= 'h' => Some(f as i32 - 'a' as i32).filter(|&x| x != king.x && is_rook(x)),
+          _ => return Err(FenError::BadCastling(c)),
+        };
+        match rook_x {
+          Some(x) => {
+            game.board.set(Pos {x, y}, Occupied(player, StartingRook));
+            game.board.set(king, Occupied(player, StartingKing));
+          }
+          None => return Err(FenError::NoPieceToCastle(c)),
+        }
+      }
+    }
+
+    if en_passant != "-" {
+      // The tile that the pawn hopped over, behind the pawn
+      let mover = game.active_player.other();
+      let hopped = parse_square(en_passant)
+        .map(|p| Pos {x: p.x, y: p.y + mover.forwards()})
+        .filter(|&p| p.y == mover.back_row() + 3 * mover.forwards()
+          && game.board.get(p) == Occupied(mover, Pawn));
+      match hopped {
+        Some(p) => game.board.set(p, Occupied(mover, HoppedPawn)),
+        None => return Err(FenError::BadEnPassant(en_passant.into())),
+      }
+    }
+
+    let parse_clock = |s : &str, min| match s.parse::<i32>() {
+      Ok(n) if n >= min => Ok(n),
+      _ => Err(FenError::BadClock(s.into())),
+    };
+    game.halfmove_clock = parse_clock(halfmove, 0)?;
+    game.fullmove_number = parse_clock(fullmove, 1)?;
+
+    // The position might already be checkmate or stalemate
+    game.check_no_moves();
+    Ok(game)
+  }
+
+  /// Writes the position in Forsyth-Edwards Notation. Castling rights use
+  /// KQkq, unless another rook is further out on the same side, in which
+  /// case the rook's file is given instead.
+  pub fn to_fen(&self) -> String {
+    let mut fen = String::new();
+    for y in (0..BOARD_SIZE).rev() {
+      let mut empty = 0;
+      for x in 0..BOARD_SIZE {
+        let tile = self.board.get(Pos {x, y});
+        let letter = match tile.kind() {
+          Some(piece) => piece_letter(piece).to_ascii_lowercase(),
+          None => {
+            empty += 1;
+            continue;
+          }
+        };
+        if empty > 0 {
+          fen.push_str(&empty.to_string());
+          empty = 0;
+        }
+        fen.push(if tile.player() == Some(White) { letter.to_ascii_uppercase() } else { letter });
+      }
+      if empty > 0 {
+        fen.push_str(&empty.to_string());
+      }
+      if y > 0 {
+        fen.push('/');
+      }
+    }
+
+    fen.push_str(if self.active_player == White { " w " } else { " b " });
+
+    let mut castling = String::new();
+    for &player in [White, Black].iter() {
+      let y = player.back_row();
+      let king = match self.find_king(player) {
+        Some(k) if self.board.get(k) == Occupied(player, StartingKing) => k,
+        _ => continue,
+      };
+      let is_rook = |x : i32| self.board.get(Pos {x, y}).kind() == Some(Rook)
+        && self.board.get(Pos {x, y}).player() == Some(player);
+      let kingside = (king.x + 1..BOARD_SIZE).rev().collect::<Vec<_>>();
+      let queenside = (0..king.x).collect::<Vec<_>>();
+      for (side, letter) in [(kingside, 'k'), (queenside, 'q')].iter() {
+        let outermost = side.iter().cloned().find(|&x| is_rook(x));
+        for &x in side {
+          if self.board.get(Pos {x, y}) == Occupied(player, StartingRook) {
+            let c = if Some(x) == outermost { *letter } else { (b'a' + x as u8) as char };
+            castling.push(if player == White { c.to_ascii_uppercase() } else { c });
+          }
+        }
+      }
+    }
+    fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+    // A pawn that just hopped belongs to the player who moved last
+    let mover = self.active_player.other();
+    let mut en_passant = "-".to_string();
+    self.visit_player_pieces(mover, |p| {
+      if self.board.get(p) == Occupied(mover, HoppedPawn) {
+        en_passant = Pos {x: p.x, y: p.y - mover.forwards()}.algebraic();
+      }
+    });
+    fen.push_str(&format!(" {} {} {}", en_passant, self.halfmove_clock, self.fullmove_number));
+    fen
+  }
+
+  /// Reads a position drawn by `Display`. Only the board and the line
+  /// saying whose turn it is are needed, and anything missing from that
+  /// line is taken to be "-", or 0 for the halfmove clock and 1 for the move.
+  pub fn from_ascii(text : &str) -> Result<Chess, AsciiError> {
+    let (board, lines) = read_ascii(text, BOARD_SIZE, BOARD_SIZE)?;
+    let mut placement = String::new();
+    for y in (0..BOARD_SIZE).rev() {
+      let mut empty = 0;
+      for x in 0..BOARD_SIZE {
+        match board.get(Pos {x, y}) {
+          '.' | ' ' => empty += 1,
+          c if "pnbrqk".contains(c.to_ascii_lowercase()) => {
+            if empty > 0 {
+              placement.push_str(&empty.to_string());
+              empty = 0;
+            }
+            placement.push(c);
+          }
+          c => return Err(AsciiError::UnknownTile(c)),
+        }
+      }
+      if empty > 0 {
+        placement.push_str(&empty.to_string());
+      }
+      if y > 0 {
+        placement.push('/');
+      }
+    }
+
+    let mut fen = None;
+    let (mut variant, mut reserves, mut checks, mut mode) = (Variant::Standard, [[0 ; 5] ; 2], [0 ; 2], None);
+    for line in lines.iter() {
+      let bad_line = || AsciiError::BadLine(line.clone());
+      if let Some(name) = line.strip_prefix("Variant: ") {
+        variant = Variant::from_name(name).ok_or_else(bad_line)?;
+      }
+      else if let Some(pieces) = line.strip_prefix("Reserves: ") {
+        for c in pieces.chars().filter(|&c| c != '-') {
+          let player = if c.is_ascii_uppercase() { White } else { Black };
+          let i = RESERVE_PIECES.iter().position(|&p| piece_letter(p) == c.to_ascii_uppercase())
+            .ok_or_else(bad_line)?;
+          reserves[player.index()][i] += 1;
+        }
+      }
+      else if let Some(counts) = line.strip_prefix("Checks: ") {
+        // e.g. "Checks: white 1, black 2"
+        for (i, part) in counts.split(", ").enumerate() {
+          let name = if i == 0 { "white " } else { "black " };
+          match part.strip_prefix(name) {
+            Some(count) if i < 2 => checks[i] = count.parse().map_err(|_| bad_line())?,
+            _ => return Err(bad_line()),
+          }
+        }
+      }
+      else if line == "White won" {
+        mode = Some(Mode::Victory(White));
+      }
+      else if line == "Black won" {
+        mode = Some(Mode::Victory(Black));
+      }
+      else if line == "Drawn" {
+        mode = Some(Mode::Draw);
+      }
+      else {
+        let mut parts = line.split(", ");
+        let side = match parts.next() {
+          Some("White to move") => "w",
+          Some("Black to move") => "b",
+          _ => return Err(bad_line()),
+        };
+        let (mut castling, mut en_passant, mut halfmove, mut fullmove) = ("-", "-", "0", "1");
+        for part in parts {
+          let split = part.rfind(' ').ok_or_else(bad_line)?;
+          let value = &part[split + 1..];
+          match &part[..split] {
+            "castling" => castling = value,
+            "en passant" => en_passant = value,
+            "halfmove clock" => halfmove = value,
+            "move" => fullmove = value,
+            _ => return Err(bad_line()),
+          }
+        }
+        fen = Some(format!("{} {} {} {} {} {}", placement, side, castling, en_passant, halfmove, fullmove));
+      }
+    }
+    let fen = fen.ok_or(AsciiError::NoSideToMove)?;
+    let exploded = variant == Variant::Atomic && mode.is_some_and(|m| m != Mode::Draw);
+    let mut game = Chess::read_fen(&fen, exploded).map_err(|e| AsciiError::Invalid(e.to_string()))?;
+    game.variant = variant;
+    game.reserves = reserves;
+    game.checks = checks;
+    // Pieces in reserve can get out of what looked like checkmate
+    game.mode = Mode::Turn;
+    match mode {
+      Some(mode) => game.mode = mode,
+      None => game.check_no_moves(),
+    }
+    Ok(game)
+  }
+
+  /// Writes a legal action in Standard Algebraic Notation, e.g. "Nbd7",
+  /// "exd6", "e8=Q+", "O-O-O" or "N@f3"
+  pub fn san(&self, a : &Action) -> String {
+    let mut san = match *a {
+      Action::Castle { king_to, .. } => {
+        if king_to.x == BOARD_SIZE - 2 { "O-O".into() } else { "O-O-O".into() }
+      }
+      Action::Drop { piece, to } => format!("{}@{}", piece_letter(piece), to.algebraic()),
+      Action::Move { from, to, promotion } => {
+        let piece = self.board.get(from).kind().unwrap();
+        let capture = self.board.get(to) != Empty || (piece == Pawn && from.x != to.x);
+        let mut san = String::new();
+        if piece == Pawn {
+          if capture {
+            san.push(from.algebraic().chars().next().unwrap());
+          }
+        }
+        else {
+          san.push(piece_letter(piece));
+          // Tell apart other pieces of the same kind that can move there
+          let mut actions = vec![];
+          self.possible_actions(&mut actions);
+          let others : Vec<Pos> = actions.iter().filter_map(|b| match *b {
+            Action::Move { from: f, to: t, .. } if t == to && f != from
+              && self.board.get(f).kind() == Some(piece) => Some(f),
+            _ => None,
+          }).collect();
+          let name = from.algebraic();
+          if !others.is_empty() {
+            if others.iter().all(|o| o.x != from.x) {
+              san.push_str(&name[..1]);
+            }
+            else if others.iter().all(|o| o.y != from.y) {
+              san.push_str(&name[1..]);
+            }
+            else {
+              san.push_str(&name);
+            }
+          }
+        }
+        if capture {
+          san.push('x');
+        }
+        san.push_str(&to.algebraic());
+        if let Some(promotion) = promotion {
+          san.push('=');
+          san.push(piece_letter(promotion));
+        }
+        san
+      }
+    };
+    let mut game = self.clone();
+    game.apply_action(a);
+    if game.in_check(game.active_player) {
+      san.push(if game.mode == Mode::Victory(self.active_player) { '#' } else { '+' });
+    }
+    san
+  }
+
+  /// Finds the legal action written in Standard Algebraic Notation.
+  /// Check and mate markers and annotations like "!?" are optional.
+  pub fn parse_san(&self, text : &str) -> Result<Action, SanError> {
+    let malformed = || SanError::Malformed(text.into());
+    let san = text.trim_end_matches(|c| "+#!?".contains(c));
+    let mut actions = vec![];
+    self.possible_actions(&mut actions);
+    let matches : Vec<Action> = match san {
+      "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+        let kingside = san.len() == 3;
+        actions.into_iter().filter(|a| match *a {
+          Action::Castle { king_to, .. } => (king_to.x == BOARD_SIZE - 2) == kingside,
+          _ => false,
+        }).collect()
+      }
+      _ => {
+        let mut chars : Vec<char> = san.chars().collect();
+        let piece = match chars.first() {
+          Some('P') => Pawn,
+          Some('N') => Knight,
+          Some('B') => Bishop,
+          Some('R') => Rook,
+          Some('Q') => Queen,
+          Some('K') => King,
+          Some(c) if c.is_ascii_uppercase() => return Err(malformed()),
+          Some(_) => Pawn,
+          None => return Err(malformed()),
+        };
+        if chars[0].is_ascii_uppercase() {
+          chars.remove(0);
+        }
+        // Promotions are written as "e8=Q", or sometimes "e8Q"
+        let promotion = match chars.last() {
+          Some('Q') => Some(Queen),
+          Some('R') => Some(Rook),
+          Some('B') => Some(Bishop),
+          Some('N') => Some(Knight),
+          _ => None,
+        };
+        if promotion.is_some() {
+          chars.pop();
+          if chars.last() == Some(&'=') {
+            chars.pop();
+          }
+        }
+        if chars.len() < 2 {
+          return Err(malformed());
+        }
+        let to_name : String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = parse_square(&to_name).ok_or_else(malformed)?;
+        let drop = chars.last() == Some(&'@');
+        if drop || chars.last() == Some(&'x') {
+          chars.pop();
+        }
+        // What's left tells apart pieces that could move to the same tile
+        let mut file = None;
+        let mut rank = None;
+        for c in chars {
+          match c {
+            'a' ..= 'h' if file.is_none() => file = Some(c as i32 - 'a' as i32),
+            '1' ..= '8' if rank.is_none() => rank = Some(c as i32 - '1' as i32),
+            _ => return Err(malformed()),
+          }
+        }
+        actions.into_iter().filter(|a| match *a {
+          Action::Move { from, to: t, promotion: p } => !drop && t == to && p == promotion
+            && self.board.get(from).kind() == Some(piece)
+            && file.is_none_or(|x| x == from.x) && rank.is_none_or(|y| y == from.y),
+          Action::Drop { piece: p, to: t } => drop && p == piece && t == to,
+          Action::Castle { .. } => false,
+        }).collect()
+      }
+    };
+    match matches.len() {
+      0 => Err(SanError::Illegal(text.into())),
+      1 => Ok(matches[0]),
+      _ => Err(SanError::Ambiguous(text.into())),
+    }
+  }
+
+  /// Writes a legal action in the long algebraic notation used by chess
+  /// engines, e.g. "e2e4", "e7e8n" or "N@f3". Castling is written as the
+  /// king's move, e.g. "e1g1", or as the king taking its own rook, e.g.
+  /// "e1h1", for Chess960.
+  pub fn long_algebraic(&self, a : &Action, chess960 : bool) -> String {
+    match *a {
+      Action::Move { from, to, promotion } => {
+        let promotion = promotion.map_or(String::new(), |p| piece_letter(p).to_ascii_lowercase().to_string());
+        format!("{}{}{}", from.algebraic(), to.algebraic(), promotion)
+      }
+      Action::Castle { king, king_to, rook, .. } => {
+        format!("{}{}", king.algebraic(), (if chess960 { rook } else { king_to }).algebraic())
+      }
+      Action::Drop { piece, to } => format!("{}@{}", piece_letter(piece), to.algebraic()),
+    }
+  }
+
+  /// Finds the legal action written in long algebraic notation. Outside
+  /// of Chess960, castling can be written either way.
+  pub fn parse_long_algebraic(&self, text : &str, chess960 : bool) -> Result<Action, SanError> {
+    let malformed = || SanError::Malformed(text.into());
+    // Slicing the text below assumes that every character is one byte
+    if !text.is_ascii() {
+      return Err(malformed());
+    }
+    let bytes = text.as_bytes();
+    let well_formed = match bytes.len() {
+      4 => parse_square(&text[..2]).and(parse_square(&text[2..])).is_some(),
+      5 if bytes[1] == b'@' => "PNBRQ".contains(bytes[0] as char) && parse_square(&text[3..]).is_some(),
+      5 => parse_square(&text[..2]).and(parse_square(&text[2..4])).is_some() && "qnbr".contains(bytes[4] as char),
+      _ => false,
+    };
+    if !well_formed {
+      return Err(malformed());
+    }
+    let mut actions = vec![];
+    self.possible_actions(&mut actions);
+    let find = |chess960| actions.iter().find(|a| self.long_algebraic(a, chess960) == text).cloned();
+    find(chess960).or_else(|| if chess960 { None } else { find(true) })
+      .ok_or_else(|| SanError::Illegal(text.into()))
+  }
+
+  fn try_get(&self, p : Pos) -> Option<Tile> {
+    self.board.try_get(p)
   }
 
   fn visit_player_pieces(&self, player : Player, mut f : impl FnMut(Pos)) {
@@ -107,46 +904,392 @@ impl Chess {
     }
   }
 
-  fn find_moves_at_position(&self, pos : Pos, actions : &mut Vec<Action>) {
-    panic!()
+  fn find_king(&self, player : Player) -> Option<Pos> {
+    let mut king = None;
+    self.visit_player_pieces(player, |p| {
+      if self.board.get(p).kind() == Some(King) {
+        king = Some(p);
+      }
+    });
+    king
+  }
+
+  /// Returns true if any of `player`'s pieces could capture on `pos`
+  fn is_attacked(&self, pos : Pos, player : Player) -> bool {
+    let is = |p : Pos, kinds : &[Piece]| match self.try_get(p) {
+      Some(t) => t.player() == Some(player) && kinds.contains(&t.kind().unwrap()),
+      None => false,
+    };
+    let pawn_row = pos.y - player.forwards();
+    if is(Pos {x: pos.x - 1, y: pawn_row}, &[Pawn]) || is(Pos {x: pos.x + 1, y: pawn_row}, &[Pawn]) {
+      return true;
+    }
+    if KNIGHT_MOVES.iter().any(|&m| is(pos + m, &[Knight])) {
+      return true;
+    }
+    // Kings can't capture in atomic, as they would explode
+    if self.variant != Variant::Atomic && ALL_DIRECTIONS.iter().any(|&m| is(pos + m, &[King])) {
+      return true;
+    }
+    let slides_to = |directions : &[Pos], kinds : &[Piece]| directions.iter().any(|&m| {
+      let mut p = pos + m;
+      while self.try_get(p) == Some(Empty) {
+        p += m;
+      }
+      is(p, kinds)
+    });
+    slides_to(STRAIGHT, &[Rook, Queen]) || slides_to(DIAGONAL, &[Bishop, Queen])
+  }
+
+  fn in_check(&self, player : Player) -> bool {
+    let king = match self.find_king(player) {
+      Some(k) => k,
+      None => return false,
+    };
+    // Touching kings are safe in atomic, as neither can be captured
+    // without exploding the other
+    if self.variant == Variant::Atomic {
+      if let Some(other) = self.find_king(player.other()) {
+        if (king.x - other.x).abs() <= 1 && (king.y - other.y).abs() <= 1 {
+          return false;
+        }
+      }
+    }
+    self.is_attacked(king, player.other())
+  }
+
+  /// Returns true if the active player's action doesn't expose their own
+  /// king. In atomic, exploding the enemy king is legal even when in check,
+  /// but exploding your own king isn't.
+  fn is_legal(&self, a : &Action) -> bool {
+    let player = self.active_player;
+    let mut game = self.clone();
+    game.make_move(a);
+    if self.variant == Variant::Atomic {
+      if game.find_king(player).is_none() {
+        return false;
+      }
+      if game.find_king(player.other()).is_none() {
+        return true;
+      }
+    }
+    !game.in_check(player)
   }
 
-  /// Turn the tile at `pos` into a queen if it is
-  /// currently a pawn, and just reached the
-  /// final row at the other end of the board
-  fn pawn_promotion(&mut self, p : Pos) {
-    let tile_value = self.board.get(p);
-    match tile_value {
-      Occupied(Black, Pawn) => {
-        if p.y == 0 {
-          self.board.set(p, Occupied(Black, Queen));
+  /// Finds the moves of the piece at `pos`, including those
+  /// that would leave its own king in check
+  fn find_moves_at_position(&self, pos : Pos, actions : &mut Vec<Action>) {
+    let tile = self.board.get(pos);
+    let player = tile.player().unwrap();
+    // Pawns reaching the far row can promote to any of `PROMOTION_PIECES`
+    let promotes = |to : Pos| tile.kind() == Some(Pawn) && (to.y == 0 || to.y == BOARD_SIZE - 1);
+    let mut add = |to : Pos| {
+      if promotes(to) {
+        for &piece in PROMOTION_PIECES.iter() {
+          actions.push(Action::Move { from: pos, to, promotion: Some(piece) });
+        }
+      }
+      else {
+        actions.push(Action::Move { from: pos, to, promotion: None });
+      }
+    };
+    let can_land = |p : Pos| match self.try_get(p) {
+      Some(t) => t.player() != Some(player),
+      None => false,
+    };
+    let slide = |directions : &[Pos], add : &mut dyn FnMut(Pos)| {
+      for &m in directions {
+        let mut p = pos + m;
+        while self.try_get(p) == Some(Empty) {
+          add(p);
+          p += m;
+        }
+        if can_land(p) {
+          add(p);
+        }
+      }
+    };
+    match tile.kind().unwrap() {
+      Pawn => {
+        let f = player.forwards();
+        let ahead = Pos {x: pos.x, y: pos.y + f};
+        if self.try_get(ahead) == Some(Empty) {
+          add(ahead);
+          let hop = Pos {x: pos.x, y: pos.y + 2 * f};
+          if let Occupied(_, StartingPawn) = tile {
+            if self.try_get(hop) == Some(Empty) {
+              add(hop);
+            }
+          }
+        }
+        for &dx in [-1, 1].iter() {
+          let to = Pos {x: pos.x + dx, y: pos.y + f};
+          match self.try_get(to) {
+            Some(Occupied(p, _)) if p != player => add(to),
+            // En passant, past a pawn that just moved two tiles
+            Some(Empty) => {
+              let passed = Pos {x: pos.x + dx, y: pos.y};
+              if self.try_get(passed) == Some(Occupied(player.other(), HoppedPawn)) {
+                add(to);
+              }
+            }
+            _ => (),
+          }
+        }
+      }
+      Knight => {
+        for &m in KNIGHT_MOVES {
+          if can_land(pos + m) {
+            add(pos + m);
+          }
         }
       }
-      Occupied(White, Pawn) => {
-        if p.y == BOARD_SIZE-1 {
-          self.board.set(p, Occupied(White, Queen));
+      King => {
+        let can_capture = self.variant != Variant::Atomic;
+        for &m in ALL_DIRECTIONS {
+          if can_land(pos + m) && (can_capture || self.try_get(pos + m) == Some(Empty)) {
+            add(pos + m);
+          }
         }
       }
+      Bishop => slide(DIAGONAL, &mut add),
+      Rook => slide(STRAIGHT, &mut add),
+      Queen => slide(ALL_DIRECTIONS, &mut add),
       _ => (),
     }
+    if tile == Occupied(player, StartingKing) {
+      self.find_castles(pos, actions);
+    }
+  }
+
+  /// Finds the ways to drop pieces from the active player's reserve.
+  /// Pawns can't be dropped on the first or last row.
+  fn find_drops(&self, actions : &mut Vec<Action>) {
+    let reserve = self.reserves[self.active_player.index()];
+    for (i, &piece) in RESERVE_PIECES.iter().enumerate() {
+      if reserve[i] == 0 {
+        continue;
+      }
+      for y in 0..BOARD_SIZE {
+        if piece == Pawn && (y == 0 || y == BOARD_SIZE - 1) {
+          continue;
+        }
+        for x in 0..BOARD_SIZE {
+          if self.board.get(Pos {x, y}) == Empty {
+            actions.push(Action::Drop { piece, to: Pos {x, y} });
+          }
+        }
+      }
+    }
+  }
+
+  /// Castling follows the Chess960 rules: the king and rook can't have
+  /// moved, every tile between their starting and final tiles must be
+  /// empty apart from the two of them, and the king can't be in check or
+  /// pass through an attacked tile.
+  fn find_castles(&self, king : Pos, actions : &mut Vec<Action>) {
+    let player = self.board.get(king).player().unwrap();
+    let y = player.back_row();
+    if king.y != y {
+      return;
+    }
+    for x in 0..BOARD_SIZE {
+      let rook = Pos {x, y};
+      if self.board.get(rook) != Occupied(player, StartingRook) {
+        continue;
+      }
+      let (king_x, rook_x) = if x < king.x { (2, 3) } else { (6, 5) };
+      let (king_to, rook_to) = (Pos {x: king_x, y}, Pos {x: rook_x, y});
+      let mut game = self.clone();
+      game.board.set(king, Empty);
+      game.board.set(rook, Empty);
+      let xs = [king.x, rook.x, king_to.x, rook_to.x];
+      let (lo, hi) = (*xs.iter().min().unwrap(), *xs.iter().max().unwrap());
+      if (lo..=hi).any(|x| game.board.get(Pos {x, y}) != Empty) {
+        continue;
+      }
+      let (lo, hi) = (king.x.min(king_to.x), king.x.max(king_to.x));
+      if (lo..=hi).any(|x| game.is_attacked(Pos {x, y}, player.other())) {
+        continue;
+      }
+      actions.push(Action::Castle { king, king_to, rook, rook_to });
+    }
+  }
+
+  /// Moves the pieces, without checking whether the action is legal
+  fn make_move(&mut self, a : &Action) {
+    let player = self.active_player;
+    self.halfmove_clock += 1;
+    if player == Black {
+      self.fullmove_number += 1;
+    }
+    match *a {
+      Action::Move { from, to, promotion } => {
+        let mut tile_value = self.board.get(from);
+        let mut captured = self.board.get(to);
+        // A pawn moving diagonally onto an empty tile is capturing en passant
+        if tile_value.kind() == Some(Pawn) && from.x != to.x && captured == Empty {
+          let passed = Pos {x: to.x, y: from.y};
+          captured = self.board.get(passed);
+          self.board.set(passed, Empty);
+        }
+        if tile_value.kind() == Some(Pawn) || captured != Empty {
+          self.halfmove_clock = 0;
+        }
+        tile_value = match tile_value {
+          Occupied(p, StartingPawn) if (to.y - from.y).abs() == 2 => Occupied(p, HoppedPawn),
+          Occupied(p, StartingPawn) => Occupied(p, Pawn),
+          Occupied(p, StartingKing) => Occupied(p, King),
+          Occupied(p, StartingRook) => Occupied(p, Rook),
+          t => t,
+        };
+        self.board.set(from, Tile::Empty);
+        self.board.set(to, tile_value);
+        if let Some(piece) = promotion {
+          self.pawn_promotion(to, piece);
+        }
+        if captured != Empty {
+          match self.variant {
+            Variant::Crazyhouse => self.add_to_reserve(player, captured),
+            Variant::Atomic => self.explode(to),
+            _ => (),
+          }
+        }
+      }
+      Action::Castle { king, king_to, rook, rook_to } => {
+        self.board.set(king, Empty);
+        self.board.set(rook, Empty);
+        self.board.set(king_to, Occupied(player, King));
+        self.board.set(rook_to, Occupied(player, Rook));
+      }
+      Action::Drop { piece, to } => {
+        let i = RESERVE_PIECES.iter().position(|&p| p == piece).unwrap();
+        self.reserves[player.index()][i] -= 1;
+        // Pawns dropped on their starting row can still move two tiles
+        let piece = if piece == Pawn && to.y == player.back_row() + player.forwards() {
+          StartingPawn
+        }
+        else {
+          piece
+        };
+        self.board.set(to, Occupied(player, piece));
+      }
+    }
+    // The opponent's pawns can only be taken en passant straight after they hop
+    let size = BOARD_SIZE;
+    for y in 0..size {
+      for x in 0..size {
+        if self.board.get(Pos {x, y}) == Occupied(player.other(), HoppedPawn) {
+          self.board.set(Pos {x, y}, Occupied(player.other(), Pawn));
+        }
+      }
+    }
+    self.active_player_swap();
+  }
+
+  /// Adds a captured piece to the capturer's reserve. Promoted
+  /// pieces go back to being pawns.
+  fn add_to_reserve(&mut self, player : Player, captured : Tile) {
+    let piece = match captured {
+      Occupied(_, PromotedQueen) | Occupied(_, PromotedRook)
+        | Occupied(_, PromotedBishop) | Occupied(_, PromotedKnight) => Pawn,
+      t => t.kind().unwrap(),
+    };
+    if let Some(i) = RESERVE_PIECES.iter().position(|&p| p == piece) {
+      self.reserves[player.index()][i] += 1;
+    }
+  }
+
+  /// Removes the capturing piece at `pos`, and every piece around it other than pawns
+  fn explode(&mut self, pos : Pos) {
+    self.board.set(pos, Empty);
+    for &m in ALL_DIRECTIONS {
+      if let Some(t) = self.try_get(pos + m) {
+        if t != Empty && t.kind() != Some(Pawn) {
+          self.board.set(pos + m, Empty);
+        }
+      }
+    }
+  }
+
+  /// Turns the pawn that just reached the final row at `p` into
+  /// `piece`, marked as promoted for the crazyhouse reserve
+  fn pawn_promotion(&mut self, p : Pos, piece : Piece) {
+    let promoted = match piece {
+      Rook => PromotedRook,
+      Bishop => PromotedBishop,
+      Knight => PromotedKnight,
+      _ => PromotedQueen,
+    };
+    if let Occupied(player, _) = self.board.get(p) {
+      self.board.set(p, Occupied(player, promoted));
+    }
+  }
+
+  /// Counts the check just given in three-check, and then ends the game if it is over
+  fn victory_check(&mut self) {
+    if self.variant == Variant::ThreeCheck && self.in_check(self.active_player) {
+      self.checks[self.active_player.other().index()] += 1;
+    }
+    self.update_mode();
   }
 
-  fn victory_check(&self) -> bool {
-    let (white, black) = self.piece_count();
-    white == 0 || black == 0
+  /// Ends the game if the player who just moved won by a variant's
+  /// rule, or if the player to move is checkmated or stalemated
+  fn update_mode(&mut self) {
+    let mover = self.active_player.other();
+    let won = match self.variant {
+      Variant::Atomic => self.find_king(self.active_player).is_none(),
+      Variant::ThreeCheck => self.checks[mover.index()] >= 3,
+      Variant::KingOfTheHill => match self.find_king(mover) {
+        Some(k) => (k.x == 3 || k.x == 4) && (k.y == 3 || k.y == 4),
+        None => false,
+      },
+      Variant::Standard | Variant::Crazyhouse => false,
+    };
+    if won {
+      self.mode = Mode::Victory(mover);
+      return;
+    }
+    self.check_no_moves();
   }
 
-  fn piece_count(&self) -> (i32, i32) {
-    let mut white = 0;
-    let mut black = 0;
+  /// Ends the game if the player to move has no moves: they lose
+  /// if they are in check, and otherwise it is stalemate
+  fn check_no_moves(&mut self) {
+    let mut actions = vec![];
+    self.possible_actions(&mut actions);
+    if actions.is_empty() {
+      self.mode = if self.in_check(self.active_player) {
+        Mode::Victory(self.active_player.other())
+      }
+      else {
+        Mode::Draw
+      };
+    }
+  }
+
+  /// The total value of each player's pieces, including their reserve
+  fn material(&self) -> (i32, i32) {
+    let value = |piece| match piece {
+      Pawn => 1,
+      Knight | Bishop => 3,
+      Rook => 5,
+      Queen => 9,
+      _ => 0,
+    };
+    let mut totals = [0, 0];
     for tile in self.board.iter() {
-      match tile {
-        Occupied(Black, _) => black += 1,
-        Occupied(White, _) => white += 1,
-        Empty => (),
+      if let Occupied(p, _) = tile {
+        totals[p.index()] += value(tile.kind().unwrap());
+      }
+    }
+    for (total, reserve) in totals.iter_mut().zip(self.reserves.iter()) {
+      for (i, &piece) in RESERVE_PIECES.iter().enumerate() {
+        *total += reserve[i] * value(piece);
       }
     }
-    (white, black)
+    (totals[0], totals[1])
   }
 
   fn active_player_swap(&mut self) {
@@ -167,8 +1310,13 @@ impl Game for Chess {
         self.visit_player_pieces(p, |pos| {
           self.find_moves_at_position(pos, actions);
         });
+        if self.variant == Variant::Crazyhouse {
+          self.find_drops(actions);
+        }
+        // Moves that would leave the king in check are illegal
+        actions.retain(|a| self.is_legal(a));
       }
-      Mode::Victory(_) => (),
+      Mode::Victory(_) | Mode::Draw => (),
     }
   }
 
@@ -179,31 +1327,22 @@ impl Game for Chess {
   }
 
   fn apply_action(&mut self, a : &Action) {
-    match *a {
-      Action::Move { from, to } => {
-        let tile_value = self.board.get(from);
-        self.board.set(from, Tile::Empty);
-        self.board.set(to, tile_value);
-        self.pawn_promotion(to);
-        self.active_player_swap();
-        if self.victory_check() {
-          self.mode = Mode::Victory(tile_value.player().unwrap());
-        }
-        else {
-          self.mode = Mode::Turn;
-        }
-      }
-      Action::Castle { king, king_to, rook, rook_to } => {
-        panic!()
-      }
-    }
+    self.make_move(a);
+    self.victory_check();
   }
 
+  /// The material advantage, or a larger value once the game is won
   fn player_score(&self, player : i64) -> f64 {
-    let (white, black) = self.piece_count();
+    let (white, black) = self.material();
+    let white_advantage = match self.mode {
+      Mode::Victory(White) => 1000,
+      Mode::Victory(Black) => -1000,
+      Mode::Draw => 0,
+      Mode::Turn => white - black,
+    };
     match player {
-      0 => (white - black) as f64,
-      1 => (black - white) as f64,
+      0 => white_advantage as f64,
+      1 => -white_advantage as f64,
       _ => panic!("chess is a two-player game"),
     }
   }
@@ -215,6 +1354,132 @@ impl Game for Chess {
       _ => None,
     }
   }
+
+  fn is_draw(&self) -> bool {
+    self.mode == Mode::Draw
+  }
+}
+
+impl Stochastic for Chess {}
+
+impl Recordable for Chess {
+  const GAME_TYPE : &'static str = "chess";
+
+  /// The variant and the position in FEN, e.g. "atomic 8/8/8/8/8/8/8/K6k w - - 0 1"
+  fn config(&self) -> String {
+    format!("{} {}", self.variant.name(), self.to_fen())
+  }
+
+  fn from_config(config : &str) -> Option<Chess> {
+    let mut parts = config.splitn(2, ' ');
+    let variant = Variant::from_name(parts.next()?)?;
+    Chess::from_fen(variant, parts.next()?).ok()
+  }
+}
+
+/// Actions are written in Standard Algebraic Notation, e.g. "Nf3"
+impl Notation for Chess {
+  fn format_action(&self, action : &Action) -> String {
+    self.san(action)
+  }
+
+  fn parse_action(&self, text : &str) -> Result<Action, NotationError> {
+    self.parse_san(text.trim()).map_err(|e| match e {
+      SanError::Malformed(san) => NotationError::Malformed(san),
+      SanError::Illegal(san) => NotationError::Illegal(san),
+      SanError::Ambiguous(san) => NotationError::Ambiguous(san),
+    })
+  }
+}
+
+/// Draws the board with white at the bottom, in upper case, and black
+/// in lower case, e.g.
+///
+/// ```text
+///  8 r n b q k b n r
+///  7 p p p p p p p p
+///  6 . . . . . . . .
+///  5 . . . . . . . .
+///  4 . . . . P . . .
+///  3 . . . . . . . .
+///  2 P P P P . P P P
+///  1 R N B Q K B N R
+///    a b c d e f g h
+/// Black to move, castling KQkq, en passant e3, halfmove clock 0, move 1
+/// ```
+///
+/// The variant, crazyhouse reserves, checks given in three-check, and
+/// the result follow on their own lines.
+impl fmt::Display for Chess {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", draw_ascii(BOARD_SIZE, BOARD_SIZE, true, |p| match self.board.get(p) {
+      Occupied(White, piece) => piece_letter(piece),
+      Occupied(Black, piece) => piece_letter(piece).to_ascii_lowercase(),
+      Empty => '.',
+    }))?;
+    let fen = self.to_fen();
+    let fields : Vec<&str> = fen.split(' ').collect();
+    writeln!(f, "{} to move, castling {}, en passant {}, halfmove clock {}, move {}",
+      if self.active_player == White { "White" } else { "Black" },
+      fields[2], fields[3], fields[4], fields[5])?;
+    if self.variant != Variant::Standard {
+      writeln!(f, "Variant: {}", self.variant.name())?;
+    }
+    if self.variant == Variant::Crazyhouse {
+      let mut reserves = String::new();
+      for &player in [White, Black].iter() {
+        for (i, &piece) in RESERVE_PIECES.iter().enumerate() {
+          for _ in 0..self.reserves[player.index()][i] {
+            let letter = piece_letter(piece);
+            reserves.push(if player == White { letter } else { letter.to_ascii_lowercase() });
+          }
+        }
+      }
+      writeln!(f, "Reserves: {}", if reserves.is_empty() { "-".into() } else { reserves })?;
+    }
+    if self.variant == Variant::ThreeCheck {
+      writeln!(f, "Checks: white {}, black {}", self.checks[0], self.checks[1])?;
+    }
+    match self.mode {
+      Mode::Victory(White) => writeln!(f, "White won"),
+      Mode::Victory(Black) => writeln!(f, "Black won"),
+      Mode::Draw => writeln!(f, "Drawn"),
+      Mode::Turn => Ok(()),
+    }
+  }
+}
+
+/// Draws a piece in the tile whose top left corner is at (`left`, `top`)
+fn draw_piece(player : Player, piece : Piece, left : f64, top : f64, context : &Context, graphics : &mut G2d) {
+  let colour = match player {
+    Black => [1.0, 0.0, 0.0, 1.0],
+    White => [0.0, 1.0, 0.0, 1.0],
+  };
+  // Pawns are small, and the other pieces are marked with lines
+  if piece == Pawn {
+    ellipse(colour, [left + 15.0, top + 15.0, 30.0, 30.0], context.transform, graphics);
+    return;
+  }
+  ellipse(colour, [left + 5.0, top + 5.0, 50.0, 50.0], context.transform, graphics);
+  let mark = [1.0, 1.0, 1.0, 1.0];
+  let cross = [[left + 30.0, top + 12.0, left + 30.0, top + 48.0],
+    [left + 12.0, top + 30.0, left + 48.0, top + 30.0]];
+  let saltire = [[left + 17.0, top + 17.0, left + 43.0, top + 43.0],
+    [left + 17.0, top + 43.0, left + 43.0, top + 17.0]];
+  let lines : Vec<[f64 ; 4]> = match piece {
+    Knight => vec![saltire[0]],
+    Bishop => saltire.to_vec(),
+    Rook => {
+      Rectangle::new_border(mark, 2.0).draw([left + 18.0, top + 18.0, 24.0, 24.0],
+        &DrawState::default(), context.transform, graphics);
+      vec![]
+    }
+    Queen => cross.iter().chain(saltire.iter()).cloned().collect(),
+    _ => cross.to_vec(),
+  };
+  for l in lines {
+    line(mark, 2.0, l, context.transform, graphics);
+  }
 }
 
 fn draw_chess(game : &Chess, player_actions : &[Action], context : &Context, graphics : &mut G2d) {
@@ -227,18 +1492,31 @@ fn draw_chess(game : &Chess, player_actions : &[Action], context : &Context, gra
         [x as f64 * 60.0, y as f64 * 60.0, 60.0, 60.0],
         context.transform,
         graphics);
-
-      let tile = game.board.get(Pos{x, y});
-      let colour = match tile {
-        Occupied(Black, _) => Some([1.0, 0.0, 0.0, 1.0]),
-        Occupied(White, _) => Some([0.0, 1.0, 0.0, 1.0]),
-        Empty => None,
-      };
-      if let Some(c) = colour {
-        ellipse(
-          c, [x as f64 * 60.0 + 5.0, y as f64 * 60.0 + 5.0, 50.0, 50.0],
-          context.transform,
-          graphics);
+    }
+  }
+  for y in 0..BOARD_SIZE {
+    for x in 0..BOARD_SIZE {
+      if let Occupied(player, _) = game.board.get(Pos{x, y}) {
+        let piece = game.board.get(Pos{x, y}).kind().unwrap();
+        draw_piece(player, piece, x as f64 * 60.0, y as f64 * 60.0, context, graphics);
+      }
+    }
+  }
+  // The crazyhouse reserves are in the columns to the right of the
+  // board, with a dot below each piece for every one held
+  if game.variant == Variant::Crazyhouse {
+    for &player in [White, Black].iter() {
+      let left = (BOARD_SIZE + player.index() as i32) as f64 * 60.0;
+      for (i, &piece) in RESERVE_PIECES.iter().enumerate() {
+        let count = game.reserves[player.index()][i];
+        let top = i as f64 * 60.0;
+        if count > 0 {
+          draw_piece(player, piece, left, top, context, graphics);
+        }
+        for n in 0..count {
+          ellipse([0.0, 0.0, 0.0, 1.0], [left + 5.0 + n as f64 * 7.0, top + 53.0, 5.0, 5.0],
+            context.transform, graphics);
+        }
       }
     }
   }
@@ -249,7 +1527,7 @@ fn draw_chess(game : &Chess, player_actions : &[Action], context : &Context, gra
   }
   for a in player_actions {
     match *a {
-      Action::Move { from, to } => {
+      Action::Move { from, to, .. } => {
         draw_border(from, [0.0, 0.0, 1.0, 1.0], &context, graphics);
         draw_border(to, [0.0, 0.0, 1.0, 1.0], &context, graphics);
       }
@@ -259,6 +1537,9 @@ fn draw_chess(game : &Chess, player_actions : &[Action], context : &Context, gra
         draw_border(rook, [0.0, 0.0, 1.0, 1.0], &context, graphics);
         draw_border(rook_to, [0.0, 0.0, 1.0, 1.0], &context, graphics);
       }
+      Action::Drop { to, .. } => {
+        draw_border(to, [0.0, 0.0, 1.0, 1.0], &context, graphics);
+      }
     }
   }
 }
@@ -269,50 +1550,96 @@ fn handle_click(
   pos : Pos,
 ) -> Option<Action>
 {
-  let tile = game.board.get(pos);
+  // Promotions are to a queen, as it comes first of the promotion pieces
   for a in player_actions.iter().cloned() {
     let to = match a {
       Action::Move { to, ..} => to,
-      Action::Castle { king_to, ..} => king_to,
+      // Click on the rook to castle, as the king might not move
+      Action::Castle { rook, ..} => rook,
+      Action::Drop { to, ..} => to,
     };
     if to == pos {
       return Some(a);
     }
   }
-  if let Occupied(player, _) = tile {
+  // Clicking a piece in the active player's reserve shows where it can be dropped
+  let reserve_column = BOARD_SIZE + game.active_player.index() as i32;
+  if pos.x == reserve_column && pos.y >= 0 && (pos.y as usize) < RESERVE_PIECES.len() {
+    let piece = RESERVE_PIECES[pos.y as usize];
+    player_actions.clear();
+    game.possible_actions(player_actions);
+    player_actions.retain(|a| match a {
+      Action::Drop { piece: p, .. } => *p == piece,
+      _ => false,
+    });
+    return None;
+  }
+  if let Some(Occupied(player, _)) = game.board.try_get(pos) {
     if game.active_player == player {
       player_actions.clear();
       game.possible_actions(player_actions);
       player_actions.retain(|a| match a {
         Action::Move{from, ..} => *from == pos,
         Action::Castle{king, ..} => *king == pos,
+        Action::Drop{..} => false,
       });
     }
   }
   None
 }
 
-pub fn play_chess<A, B>(mut agent_a : A, mut agent_b : B)
+/// Adds a move to the game record, noting who played it. A human
+/// and an agent can both play for the same side.
+fn record_move(history : &mut Vec<Action>, movers : &mut Vec<String>, name : String, a : Action) {
+  history.push(a);
+  if !movers.contains(&name) {
+    movers.push(name);
+  }
+}
+
+pub fn play_chess<A, B>(start : Chess, mut agent_a : A, mut agent_b : B)
   where A : GameAgent<Chess>, B : GameAgent<Chess>
 {
   println!("Chess!");
-  let mut game = Chess::new();
+  let mut game = start.clone();
   let mut rng = StdRng::from_entropy(); //StdRng::seed_from_u64(0);
+  // Leave room for the reserves in crazyhouse
+  let window_width = if start.variant == Variant::Crazyhouse { 600 } else { 480 };
+
+  let agents : &mut [&mut dyn GameAgent<Chess>] = &mut [&mut agent_a, &mut agent_b];
 
   let mut window: PistonWindow =
-    WindowSettings::new("Chess", [480, 480])
+    WindowSettings::new("Chess", [window_width, 480])
     .exit_on_esc(true).build().unwrap();
 
   let mut mouse_pos = [0.0, 0.0];
   let mut player_actions = vec![];
+  // The moves so far, and who played each side, for saving the game
+  let mut history = vec![];
+  let mut movers : [Vec<String> ; 2] = [vec![], vec![]];
   
   while let Some(event) = window.next() {
     if let Some(Button::Keyboard(key)) = event.press_args() {
       if key == Key::Space {
-        agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng);
+        let side = game.active_player.index();
+        if let Some(a) = take_action(agents, &mut game, &mut rng) {
+          record_move(&mut history, &mut movers[side], agents[side].name(), a);
+        }
       }
       if key == Key::Return {
-        game = Chess::new();
+        game = start.clone();
+        history.clear();
+        movers = [vec![], vec![]];
+      }
+      // Save the game so far to chess.pgn
+      if key == Key::S {
+        let names = |side : &Vec<String>| if side.is_empty() { "?".into() } else { side.join(" and ") };
+        let tags = [("Event", "Workshop chess".to_string()),
+          ("White", names(&movers[0])), ("Black", names(&movers[1]))];
+        match crate::pgn::append_pgn("chess.pgn", &crate::pgn::write_pgn(&tags, &start, &history)) {
+          Ok(()) => println!("Saved the game to chess.pgn"),
+          Err(e) => println!("Couldn't save the game: {}", e),
+        }
       }
     }
     if let Some(p) = event.mouse_cursor_args() {
@@ -324,11 +1651,16 @@ pub fn play_chess<A, B>(mut agent_a : A, mut agent_b : B)
       let y = (mouse_pos[1] / 60.0) as i32;
       let pos = Pos{x, y};
       if let Some(action) = handle_click(&mut game, &mut player_actions, pos) {
+        let side = game.active_player.index();
+        record_move(&mut history, &mut movers[side], "Human".into(), action);
         game.apply_action(&action);
         player_actions.clear();
         // AI response
         if game.mode == Mode::Turn {
-          agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng);
+          let side = game.active_player.index();
+          if let Some(a) = take_action(agents, &mut game, &mut rng) {
+            record_move(&mut history, &mut movers[side], agents[side].name(), a);
+          }
         }
       }
     }
@@ -339,3 +1671,73 @@ pub fn play_chess<A, B>(mut agent_a : A, mut agent_b : B)
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::agents::perft;
+
+  /// The lines of a drawn board, without the indentation
+  fn lines(text : &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+  }
+
+  /// The perft counts of a position, from depth 1
+  fn perft_counts(game : &Chess, depth : i64) -> Vec<u64> {
+    (1..=depth).map(|d| perft(game, d)).collect()
+  }
+
+  #[test]
+  fn perft_from_the_start() {
+    assert_eq!(perft_counts(&Chess::new(), 3), [20, 400, 8902]);
+  }
+
+  #[test]
+  fn perft_kiwipete() {
+    // Castling both ways, en passant, promotions and pins
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let game = Chess::from_fen(Variant::Standard, fen).unwrap();
+    assert_eq!(perft_counts(&game, 3), [48, 2039, 97862]);
+  }
+
+  #[test]
+  fn perft_chess960_castling() {
+    // Castling on the h side leaves each king where it is
+    let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+    let game = Chess::from_fen(Variant::Standard, fen).unwrap();
+    assert_eq!(perft_counts(&game, 3), [21, 528, 12189]);
+  }
+
+  #[test]
+  fn crazyhouse_reserves_read_back_from_ascii() {
+    let text = "
+       8 r . b . q . . r
+       7 . p p . k n . p
+       6 p . . . p . . n
+       5 P . P . . p . .
+       4 . . q P . . p N
+       3 b . B . P . P .
+       2 P . . . . . . P
+       1 R N . K R . . .
+         a b c d e f g h
+      White to move, castling -, en passant -, halfmove clock 0, move 25
+      Variant: crazyhouse
+      Reserves: Ppb
+    ";
+    let game = Chess::from_ascii(text).unwrap();
+    assert_eq!(game.variant(), Variant::Crazyhouse);
+    assert_eq!(game.reserves, [[1, 0, 0, 0, 0], [1, 0, 1, 0, 0]]);
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    assert!(actions.iter().any(|a| matches!(a, Action::Drop { piece: Pawn, .. })));
+    assert_eq!(lines(&game.to_string()), lines(text));
+  }
+
+  #[test]
+  fn exploded_king_reads_back_from_ascii() {
+    let game = Chess::from_fen(Variant::Atomic, "1b2k3/2p1r3/8/6rP/5P2/8/8/4R3 w - - 0 48").unwrap();
+    assert_eq!(game.winner(), Some(1));
+    let read = Chess::from_ascii(&game.to_string()).unwrap();
+    assert_eq!(read.to_string(), game.to_string());
+    assert_eq!(read.to_fen(), game.to_fen());
+  }
+}
@@ -0,0 +1,306 @@
+use crate::utils::Pos;
+use crate::agents::{Game, Stochastic};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+// Chinese checkers is played on a six-pointed star of 121 holes. Holes are
+// addressed with axial hex coordinates (q, r), stored in a `Pos` as
+// `x = q` and `y = r`, with the centre of the star at (0, 0).
+
+/// How far the star extends from the centre in each axis
+const RADIUS : i32 = 8;
+
+/// The width of the square grid that holds the star
+const GRID_SIZE : i32 = 2 * RADIUS + 1;
+
+/// The number of pieces each player starts with
+const PIECES : i32 = 10;
+
+/// The six neighbours of a hole
+const DIRECTIONS : [Pos ; 6] = [
+  Pos { x: 1, y: 0 }, Pos { x: -1, y: 0 },
+  Pos { x: 0, y: 1 }, Pos { x: 0, y: -1 },
+  Pos { x: 1, y: -1 }, Pos { x: -1, y: 1 },
+];
+
+/// The outermost hole of each corner triangle, going round the star
+/// so that corner `i` faces corner `(i + 3) % 6`
+const CORNER_TIPS : [Pos ; 6] = [
+  Pos { x: 4, y: -8 }, Pos { x: 8, y: -4 }, Pos { x: 4, y: 4 },
+  Pos { x: -4, y: 8 }, Pos { x: -8, y: 4 }, Pos { x: -4, y: -4 },
+];
+
+/// Holds the contents of a hole
+#[derive(Copy, Clone, PartialEq)]
+enum Tile {
+  /// Not part of the star
+  OffBoard,
+  Empty,
+  Occupied(usize),
+}
+
+type Board = crate::utils::Board<Tile>;
+
+/// Moves a piece one hole, or through a chain of hops over
+/// neighbouring pieces. Hops don't capture anything.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Action {
+  pub from : Pos,
+  pub to : Pos,
+}
+
+/// Chinese checkers for 2, 3, 4 or 6 players. Each player races their
+/// ten pieces from their home corner into the opposite corner.
+#[derive(Clone)]
+pub struct ChineseCheckers {
+  board : Board,
+  /// The home corner of each player
+  homes : Vec<usize>,
+  active_player : usize,
+  winner : Option<usize>,
+  /// The total distance of a player's pieces from their target at the start
+  start_distance : i32,
+}
+
+/// Which corner triangle a hole is in, if any
+fn corner(p : Pos) -> Option<usize> {
+  let (q, r, s) = (p.x, p.y, -p.x - p.y);
+  if r < -4 { Some(0) }
+  else if q > 4 { Some(1) }
+  else if s < -4 { Some(2) }
+  else if r > 4 { Some(3) }
+  else if q < -4 { Some(4) }
+  else if s > 4 { Some(5) }
+  else { None }
+}
+
+fn on_star(p : Pos) -> bool {
+  let (q, r, s) = (p.x, p.y, -p.x - p.y);
+  (q >= -4 && r >= -4 && s >= -4) || (q <= 4 && r <= 4 && s <= 4)
+}
+
+fn hex_distance(a : Pos, b : Pos) -> i32 {
+  let (dq, dr) = (a.x - b.x, a.y - b.y);
+  (dq.abs() + dr.abs() + (dq + dr).abs()) / 2
+}
+
+fn target_corner(home : usize) -> usize {
+  (home + 3) % 6
+}
+
+impl ChineseCheckers {
+
+  pub fn new(players : usize) -> ChineseCheckers {
+    let homes = match players {
+      2 => vec![0, 3],
+      3 => vec![0, 2, 4],
+      4 => vec![0, 1, 3, 4],
+      6 => vec![0, 1, 2, 3, 4, 5],
+      _ => panic!("chinese checkers is played by 2, 3, 4 or 6 players"),
+    };
+    let mut game = ChineseCheckers {
+      board: Board::new(Tile::OffBoard, GRID_SIZE),
+      homes, active_player: 0, winner: None, start_distance: 0,
+    };
+    for y in -RADIUS..=RADIUS {
+      for x in -RADIUS..=RADIUS {
+        let p = Pos { x, y };
+        if on_star(p) {
+          let owner = corner(p).and_then(|c| game.homes.iter().position(|&h| h == c));
+          game.set_tile(p, owner.map(Tile::Occupied).unwrap_or(Tile::Empty));
+        }
+      }
+    }
+    game.start_distance = game.target_distance(0);
+    game
+  }
+
+  fn get_tile(&self, p : Pos) -> Tile {
+    self.board.try_get(Pos { x: p.x + RADIUS, y: p.y + RADIUS }).unwrap_or(Tile::OffBoard)
+  }
+
+  fn set_tile(&mut self, p : Pos, t : Tile) {
+    self.board.set(Pos { x: p.x + RADIUS, y: p.y + RADIUS }, t);
+  }
+
+  fn visit_player_pieces(&self, player : usize, mut f : impl FnMut(Pos)) {
+    for y in -RADIUS..=RADIUS {
+      for x in -RADIUS..=RADIUS {
+        let p = Pos { x, y };
+        if self.get_tile(p) == Tile::Occupied(player) {
+          f(p);
+        }
+      }
+    }
+  }
+
+  /// A piece may only stop in its own home, its target, or
+  /// a corner that is nobody's home or target
+  fn can_stop_at(&self, player : usize, p : Pos) -> bool {
+    match corner(p) {
+      None => true,
+      Some(c) => {
+        let home = self.homes[player];
+        c == home || c == target_corner(home) ||
+          !self.homes.iter().any(|&h| h == c || target_corner(h) == c)
+      }
+    }
+  }
+
+  fn find_moves_from_pos(&self, start : Pos, actions : &mut Vec<Action>) {
+    let player = match self.get_tile(start) {
+      Tile::Occupied(p) => p,
+      _ => return,
+    };
+    let mut destinations = vec![];
+    for &d in DIRECTIONS.iter() {
+      let p = start + d;
+      if self.get_tile(p) == Tile::Empty {
+        destinations.push(p);
+      }
+    }
+    // Search every chain of hops. The moving piece has left
+    // its start hole, so that hole counts as empty.
+    let mut visited = vec![start];
+    let mut frontier = vec![start];
+    while let Some(pos) = frontier.pop() {
+      for &d in DIRECTIONS.iter() {
+        let over = pos + d;
+        let landing = over + d;
+        if let Tile::Occupied(_) = self.get_tile(over) {
+          if over != start && self.get_tile(landing) == Tile::Empty && !visited.contains(&landing) {
+            visited.push(landing);
+            frontier.push(landing);
+            if !destinations.contains(&landing) {
+              destinations.push(landing);
+            }
+          }
+        }
+      }
+    }
+    for to in destinations {
+      if self.can_stop_at(player, to) {
+        actions.push(Action { from: start, to });
+      }
+    }
+  }
+
+  /// The total distance of a player's pieces from the tip of their target
+  fn target_distance(&self, player : usize) -> i32 {
+    let tip = CORNER_TIPS[target_corner(self.homes[player])];
+    let mut distance = 0;
+    self.visit_player_pieces(player, |p| distance += hex_distance(p, tip));
+    distance
+  }
+
+  /// How far a player has got, from 0 at the start to 1 when
+  /// their pieces fill their target
+  fn progress(&self, player : usize) -> f64 {
+    // Ten pieces packed into a corner are 20 steps from its tip
+    let finished_distance = 20;
+    let remaining = self.target_distance(player) - finished_distance;
+    1.0 - remaining as f64 / (self.start_distance - finished_distance) as f64
+  }
+
+  /// A player wins once every hole of their target is filled, as long as
+  /// at least one of those pieces is theirs. This stops a player winning
+  /// by leaving pieces in their home to block an opponent.
+  fn victory_check(&self, player : usize) -> bool {
+    let target = target_corner(self.homes[player]);
+    let mut filled = 0;
+    let mut own = 0;
+    for y in -RADIUS..=RADIUS {
+      for x in -RADIUS..=RADIUS {
+        let p = Pos { x, y };
+        if corner(p) == Some(target) && on_star(p) {
+          match self.get_tile(p) {
+            Tile::Occupied(o) => {
+              filled += 1;
+              if o == player {
+                own += 1;
+              }
+            }
+            _ => return false,
+          }
+        }
+      }
+    }
+    filled == PIECES && own > 0
+  }
+}
+
+impl Game for ChineseCheckers {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.winner.is_some() {
+      return;
+    }
+    self.visit_player_pieces(self.active_player, |pos| {
+      self.find_moves_from_pos(pos, actions);
+    });
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player as i64
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    let tile = self.get_tile(a.from);
+    self.set_tile(a.from, Tile::Empty);
+    self.set_tile(a.to, tile);
+    if self.victory_check(self.active_player) {
+      self.winner = Some(self.active_player);
+    }
+    else {
+      self.active_player = (self.active_player + 1) % self.homes.len();
+    }
+  }
+
+  /// The player's progress towards their target,
+  /// relative to the average of their opponents
+  fn player_score(&self, player : i64) -> f64 {
+    let n = self.homes.len();
+    let own = self.progress(player as usize);
+    let others : f64 = (0..n).filter(|&p| p != player as usize).map(|p| self.progress(p)).sum();
+    own - others / (n - 1) as f64
+  }
+
+  fn winner(&self) -> Option<i64> {
+    self.winner.map(|p| p as i64)
+  }
+
+  fn num_players(&self) -> i64 {
+    self.homes.len() as i64
+  }
+
+  /// The winner gets 1 and everyone else 0. If nobody has won
+  /// yet, each player's payoff is their progress so far.
+  fn payoffs(&self) -> Vec<f64> {
+    let n = self.homes.len();
+    match self.winner {
+      Some(w) => (0..n).map(|p| if p == w { 1.0 } else { 0.0 }).collect(),
+      None => (0..n).map(|p| self.progress(p)).collect(),
+    }
+  }
+}
+
+impl Stochastic for ChineseCheckers {}
+
+impl Recordable for ChineseCheckers {
+  const GAME_TYPE : &'static str = "chinese-checkers";
+
+  /// The number of players, e.g. "3"
+  fn config(&self) -> String {
+    self.homes.len().to_string()
+  }
+
+  fn from_config(config : &str) -> Option<ChineseCheckers> {
+    match config.parse() {
+      Ok(players) if [2, 3, 4, 6].contains(&players) => Some(ChineseCheckers::new(players)),
+      _ => None,
+    }
+  }
+}
+
+impl Notation for ChineseCheckers {}
@@ -1,30 +1,130 @@
-
+use std::io;
 use rayon::prelude::*;
 use rand::{SeedableRng, rngs::StdRng};
 
 use crate::agents::*;
+use crate::record::{Record, Recordable, append_records};
+
+/// An agent that can be copied into each of the contest's parallel games
+pub trait ContestAgent<G : Game> : Send + Sync {
+  fn new_copy(&self) -> Box<dyn GameAgent<G>>;
+}
+
+impl <G : Game, A : GameAgent<G> + Clone + Send + Sync + 'static> ContestAgent<G> for A {
+  fn new_copy(&self) -> Box<dyn GameAgent<G>> {
+    Box::new(self.clone())
+  }
+}
 
-pub fn run_contest<A, B, G>(g : G, a : A, b : B)
-  where A : GameAgent<G> + Send + Sync,
-    B : GameAgent<G>  + Send + Sync,
-    G : Game + Send + Sync
+/// Plays 100 games, with `agents[i]` playing as player `i`, and
+/// prints the number of wins and draws and the ranking stats of each player.
+pub fn run_contest<G>(g : G, agents : &[&dyn ContestAgent<G>])
+  where G : Stochastic + Send + Sync, G::Action : Send
+{
+  run_contest_with(|_| g.clone(), agents)
+}
+
+/// Like `run_contest`, but game `i` starts from `new_game(i)`,
+/// so that the agents face a variety of starting positions
+pub fn run_contest_with<G>(new_game : impl Fn(usize) -> G, agents : &[&dyn ContestAgent<G>])
+  where G : Stochastic + Send + Sync, G::Action : Send
+{
+  run_contest_recorded(new_game, agents, |_| ());
+}
+
+/// Like `run_contest_with`, but adds a record of every game to the end
+/// of the file at `path`, one line each, so that they can be replayed
+pub fn run_contest_saved<G>(new_game : impl Fn(usize) -> G, agents : &[&dyn ContestAgent<G>], path : &str)
+  -> io::Result<()>
+  where G : Recordable + Send + Sync, G::Action : Send
+{
+  let mut records = vec![];
+  run_contest_recorded(new_game, agents, |r| records.push(Record::from_game(&r)));
+  append_records(path, &records)
+}
+
+/// The actions played in one game of a contest, so that it can be saved
+pub struct GameRecord<G : Game> {
+  /// The game's number, from 0
+  pub index : usize,
+  /// The seed of the random number generator that the agents shared.
+  /// Deterministic agents will play the same game again from it.
+  pub seed : u64,
+  pub start : G,
+  /// Includes the outcomes of chance events
+  pub actions : Vec<G::Action>,
+  pub end : G,
+  /// The agents' names, in player order
+  pub agents : Vec<String>,
+}
+
+/// Like `run_contest_with`, but passes the record of each game
+/// to `record`, in order, once the contest is over
+pub fn run_contest_recorded<G>(new_game : impl Fn(usize) -> G, agents : &[&dyn ContestAgent<G>],
+  mut record : impl FnMut(GameRecord<G>))
+  where G : Stochastic + Send + Sync, G::Action : Send
 {
   let total_games = 100;
-  let mut games = vec![ g.clone() ; total_games ];  
-  let winners = games.par_iter_mut()
-    .map(move |g| {
-      let mut rng = StdRng::from_entropy();
-      let (mut a, mut b) = (a.clone(), b.clone());
+  let games = (0..total_games).map(new_game).collect::<Vec<_>>();
+  let num_players = games[0].num_players() as usize;
+  assert_eq!(agents.len(), num_players, "need one agent per player");
+  let records = games.into_par_iter().enumerate()
+    .map(move |(index, mut g)| {
+      let seed = rand::random::<u64>();
+      let mut rng = StdRng::seed_from_u64(seed);
+      let start = g.clone();
+      let mut agents : Vec<Box<dyn GameAgent<G>>> =
+        agents.iter().map(|a| a.new_copy()).collect();
+      let names = agents.iter().map(|a| a.name()).collect();
+      let mut agents : Vec<&mut dyn GameAgent<G>> =
+        agents.iter_mut().map(|a| a.as_mut() as &mut dyn GameAgent<G>).collect();
+      let mut actions = vec![];
       for _ in 0..400 {
-        if !agent_action(&mut a, &mut b, g, &mut rng) {
-          return g.winner();
+        // Dice rolls and other chance events don't count as moves
+        while g.is_chance_node() {
+          match take_action(&mut agents, &mut g, &mut rng) {
+            Some(a) => actions.push(a),
+            None => break,
+          }
+        }
+        match take_action(&mut agents, &mut g, &mut rng) {
+          Some(a) => actions.push(a),
+          None => break,
         }
       }
-      g.winner()
+      GameRecord { index, seed, start, actions, end: g, agents: names }
     }).collect::<Vec<_>>();
-  let p1_wins = winners.iter().filter(|&&w| w == Some(0)).count();
-  let p2_wins = winners.iter().filter(|&&w| w == Some(1)).count();
-  println!("P1 wins: {}, P2 wins: {}, Draws: {}",
-    p1_wins, p2_wins, total_games - (p1_wins + p2_wins));
-}
 
+  let mut wins = vec![0 ; num_players];
+  let mut draws = 0;
+  let mut total_payoff = vec![0.0 ; num_players];
+  let mut total_rank = vec![0.0 ; num_players];
+  for r in records.iter() {
+    if let Some(w) = r.end.winner() {
+      wins[w as usize] += 1;
+    }
+    else if r.end.is_draw() {
+      draws += 1;
+    }
+    let payoffs = r.end.payoffs();
+    for p in 0..num_players {
+      total_payoff[p] += payoffs[p];
+      // Tied players share the better rank
+      let rank = 1 + payoffs.iter().filter(|&&v| v > payoffs[p]).count();
+      total_rank[p] += rank as f64;
+    }
+  }
+  let win_counts = wins.iter().enumerate()
+    .map(|(p, w)| format!("P{} wins: {}", p + 1, w))
+    .collect::<Vec<_>>();
+  // Games that hit the move limit without a result
+  let unfinished = total_games - draws - wins.iter().sum::<usize>();
+  println!("{}, Draws: {}, Unfinished: {}", win_counts.join(", "), draws, unfinished);
+  for p in 0..num_players {
+    println!("P{}: average payoff {:.3}, average rank {:.2}",
+      p + 1, total_payoff[p] / total_games as f64, total_rank[p] / total_games as f64);
+  }
+  for r in records {
+    record(r);
+  }
+}
@@ -0,0 +1,641 @@
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::agents::{Game, GameAgent, SearchLimits, SearchInfo};
+use crate::checkers::{Checkers, CheckersRules, Action, Mode, Tile, Piece, Player, MoveError};
+use crate::uci::{read_commands, think_time, option_type, OptionType, Commands};
+
+/// The rule presets that the "variant" parameter offers, with the
+/// international rules called "normal" as they are in Scan. Each can
+/// be played as antidraughts by adding "-losing", or just "losing"
+/// for the international rules.
+const VARIANTS : &[&str] = &["normal", "brazilian", "russian", "pool", "american", "italian"];
+
+/// What a Hub engine remembers between commands
+struct HubEngine<A> {
+  agent : A,
+  rng : StdRng,
+  rules : CheckersRules,
+  game : Checkers,
+  /// The moves the engine has made since "new-game", for time controls
+  moves_made : i64,
+  /// The limits from "level". Each "level" replaces all of them.
+  depth : Option<i64>,
+  nodes : Option<u64>,
+  move_time : Option<Duration>,
+  infinite : bool,
+  /// The moves in each time control, or 0 for the whole game, and the
+  /// time for each control and the increment, in milliseconds
+  moves_per_control : i64,
+  base : Option<i64>,
+  increment : i64,
+  /// The time on the engine's clock in milliseconds. It's kept up to date
+  /// after each move, and "time" can correct it.
+  clock : i64,
+}
+
+/// Plays draughts for Hub interfaces with `agent`, by speaking version 2
+/// of the Hub protocol from Scan over `input` and `output`, until the
+/// interface sends "quit" or closes `input`. The game starts out with
+/// `rules`, and the "variant" parameter can change them. "stop" and
+/// "ponder-hit" end a search early, and other commands that arrive
+/// during a search are answered once it's over.
+pub fn run_hub<A, R, W>(agent : A, rules : CheckersRules, input : R, mut output : W) -> io::Result<()>
+  where A : GameAgent<Checkers>, R : BufRead + Send + 'static, W : Write
+{
+  let lines = read_commands(input, &["stop", "ponder-hit", "quit"]);
+  let mut engine = HubEngine {
+    agent, rng: StdRng::from_entropy(), rules, game: Checkers::new(rules), moves_made: 0,
+    depth: None, nodes: None, move_time: None, infinite: false,
+    moves_per_control: 75, base: Some(5 * 60 * 1000), increment: 0, clock: 5 * 60 * 1000,
+  };
+  while let Ok((line, stop)) = lines.recv() {
+    let (command, args) = parse_command(&line);
+    let arg = |name : &str| args.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    match command {
+      "hub" => engine.identify(&mut output)?,
+      "init" => writeln!(output, "ready")?,
+      "set-param" => match (arg("name"), arg("value")) {
+        (Some("variant"), Some(value)) => match variant_rules(value) {
+          Some(rules) => {
+            engine.rules = rules;
+            engine.game = Checkers::new(rules);
+          }
+          None => writeln!(output, "error message=\"unknown variant {}\"", value)?,
+        },
+        (Some(name), Some(value)) => {
+          if !engine.agent.set_parameter(name, value) {
+            writeln!(output, "error message=\"can't set {} to {}\"", name, value)?;
+          }
+        }
+        _ => writeln!(output, "error message=\"set-param needs a name and a value\"")?,
+      },
+      "new-game" => {
+        engine.moves_made = 0;
+        engine.clock = engine.base.unwrap_or(0);
+        engine.set_position(arg("pos"), None, &mut output)?;
+      }
+      "pos" => engine.set_position(arg("pos"), arg("moves"), &mut output)?,
+      "level" => engine.set_level(&args, &mut output)?,
+      // Seconds left on the engine's clock
+      "time" => match arg("left").and_then(|t| t.parse::<f64>().ok()) {
+        Some(left) => engine.clock = (left * 1000.0) as i64,
+        None => writeln!(output, "error message=\"time needs left=<seconds>\"")?,
+      },
+      "go" => {
+        let open_ended = args.iter().any(|(n, _)| n == "ponder" || n == "analyze");
+        if !engine.go(open_ended, &stop, &lines, &mut output)? {
+          return Ok(());
+        }
+      }
+      "ping" => writeln!(output, "pong")?,
+      "quit" => return Ok(()),
+      // "stop" and "ponder-hit" without a search
+      "stop" | "ponder-hit" | "" => (),
+      _ => writeln!(output, "error message=\"unknown command {}\"", command)?,
+    }
+    output.flush()?;
+  }
+  Ok(())
+}
+
+impl <A : GameAgent<Checkers>> HubEngine<A> {
+  /// Answers "hub" with the engine's name and parameters,
+  /// including the agent's parameters
+  fn identify<W : Write>(&self, out : &mut W) -> io::Result<()> {
+    writeln!(out, "id name=\"Workshop {}\" version={} author=\"the Workshop authors\"",
+      self.agent.name(), env!("CARGO_PKG_VERSION"))?;
+    let mut variants = vec![];
+    for &name in VARIANTS {
+      variants.push(name.to_string());
+      variants.push(if name == "normal" { "losing".to_string() } else { format!("{}-losing", name) });
+    }
+    writeln!(out, "param name=variant value={} type=enum values=\"{}\"",
+      variant_name(self.rules), variants.join(" "))?;
+    for (name, value) in self.agent.parameters() {
+      match option_type(&value) {
+        OptionType::Check => writeln!(out, "param name={} value={} type=bool", name, value)?,
+        OptionType::Spin => writeln!(out, "param name={} value={} type=int min=-1000000 max=1000000", name, value)?,
+        OptionType::String => writeln!(out, "param name={} value={} type=string", name, value)?,
+      }
+    }
+    writeln!(out, "wait")
+  }
+
+  /// Handles the position and moves of "pos" and "new-game". Without a
+  /// position, the game starts from the beginning. An invalid position
+  /// or move is reported, and the last position is kept.
+  fn set_position<W : Write>(&mut self, position : Option<&str>, moves : Option<&str>, out : &mut W)
+    -> io::Result<()>
+  {
+    let start = match position {
+      Some(text) => read_position(self.rules, text),
+      None => Ok(Checkers::new(self.rules)),
+    };
+    let mut game = match start {
+      Ok(game) => game,
+      Err(e) => return writeln!(out, "error message=\"{}\"", e),
+    };
+    for m in moves.unwrap_or("").split_whitespace() {
+      match parse_hub_move(&game, m) {
+        Ok(chain) => for a in chain {
+          game.apply_action(&a);
+        },
+        Err(e) => return writeln!(out, "error message=\"{}\"", e),
+      }
+    }
+    self.game = game;
+    Ok(())
+  }
+
+  /// Handles "level", which can limit the depth, nodes or time of each
+  /// move with "depth", "nodes" and "move-time", or set a time control
+  /// with "time" for the game or each control of "moves" moves, and
+  /// "inc". "infinite" takes away every limit.
+  fn set_level<W : Write>(&mut self, args : &[(String, String)], out : &mut W) -> io::Result<()> {
+    self.depth = None;
+    self.nodes = None;
+    self.move_time = None;
+    self.infinite = false;
+    self.moves_per_control = 0;
+    self.base = None;
+    self.increment = 0;
+    for (name, value) in args {
+      let seconds = value.parse::<f64>().ok().filter(|&s| s >= 0.0).map(|s| (s * 1000.0) as i64);
+      let read = match name.as_str() {
+        "depth" => value.parse().map(|d| self.depth = Some(d)).is_ok(),
+        "nodes" => value.parse().map(|n| self.nodes = Some(n)).is_ok(),
+        "move-time" => seconds.map(|t| self.move_time = Some(Duration::from_millis(t.max(1) as u64))).is_some(),
+        "moves" => value.parse().map(|m| self.moves_per_control = m).is_ok(),
+        "time" => seconds.map(|t| self.base = Some(t)).is_some(),
+        "inc" => seconds.map(|t| self.increment = t).is_some(),
+        "infinite" => {
+          self.infinite = true;
+          true
+        }
+        _ => false,
+      };
+      if !read {
+        writeln!(out, "error message=\"bad level {}={}\"", name, value)?;
+      }
+    }
+    self.clock = self.base.unwrap_or(0);
+    Ok(())
+  }
+
+  /// The limits for the engine's next move, from "level"
+  fn limits(&self, stop : &Arc<AtomicBool>) -> SearchLimits {
+    let think = match (self.move_time, self.base) {
+      _ if self.infinite => None,
+      (Some(t), _) => Some(t),
+      (None, Some(_)) => {
+        let moves_to_go = if self.moves_per_control > 0 {
+          Some(self.moves_per_control - self.moves_made % self.moves_per_control)
+        }
+        else {
+          None
+        };
+        Some(Duration::from_millis(think_time(self.clock, self.increment, moves_to_go) as u64))
+      }
+      (None, None) => None,
+    };
+    SearchLimits {
+      depth: self.depth,
+      nodes: if self.infinite { None } else { self.nodes },
+      deadline: think.map(|t| Instant::now() + t),
+      stop: stop.clone(),
+    }
+  }
+
+  /// Handles "go think", searching within the limits from "level", and
+  /// "go ponder" and "go analyze", which search until "stop" or
+  /// "ponder-hit". After "ponder-hit" the engine thinks again within its
+  /// limits. Returns false if the interface said to quit.
+  fn go<W : Write>(
+    &mut self, open_ended : bool, stop : &Arc<AtomicBool>, lines : &Commands, out : &mut W)
+    -> io::Result<bool>
+  {
+    if !open_ended {
+      let start = Instant::now();
+      let limits = self.limits(stop);
+      self.think(&limits, out)?;
+      // Keep the clock going between "time" commands
+      self.moves_made += 1;
+      self.clock += self.increment - start.elapsed().as_millis() as i64;
+      if self.moves_per_control > 0 && self.moves_made % self.moves_per_control == 0 {
+        self.clock += self.base.unwrap_or(0);
+      }
+      return Ok(true);
+    }
+
+    let limits = SearchLimits { stop: stop.clone(), ..SearchLimits::default() };
+    let (chain, line) = self.search(&limits, out)?;
+    // Wait for the command that ends the search, in case it ended by itself
+    loop {
+      match lines.recv() {
+        Ok((line, _)) if line.trim() == "ping" => {
+          writeln!(out, "pong")?;
+          out.flush()?;
+        }
+        Ok((line, stop)) => match line.split_whitespace().next() {
+          Some("stop") => break,
+          Some("ponder-hit") => return self.go(false, &stop, lines, out),
+          Some("quit") => return Ok(false),
+          _ => (),
+        },
+        Err(_) => return Ok(false),
+      }
+    }
+    self.write_done(&chain, &line, out)?;
+    Ok(true)
+  }
+
+  /// Chooses the engine's move, and writes it as "done"
+  fn think<W : Write>(&mut self, limits : &SearchLimits, out : &mut W) -> io::Result<()> {
+    let (chain, line) = self.search(limits, out)?;
+    self.write_done(&chain, &line, out)
+  }
+
+  /// Searches for a complete move, writing an "info" line for each
+  /// report, and returns the move with the last line that was reported.
+  /// The agent chooses one jump at a time, so the rest of a chain of
+  /// captures follows that line while it can, or asks the agent again.
+  fn search<W : Write>(&mut self, limits : &SearchLimits, out : &mut W)
+    -> io::Result<(Vec<Action>, Vec<Action>)>
+  {
+    let start = Instant::now();
+    let HubEngine { agent, game, rng, .. } = self;
+    let mut line = vec![];
+    let mut written = Ok(());
+    let mut next = agent.choose_action_limited(game, rng, limits, &mut |info| {
+      line = info.line.clone();
+      if written.is_ok() {
+        written = write_info(out, game, info, start.elapsed());
+      }
+    });
+    written?;
+
+    let mut chain = vec![];
+    let mut position = game.clone();
+    while let Some(a) = next {
+      position.apply_action(&a);
+      chain.push(a);
+      if !is_mid_chain(&position) {
+        break;
+      }
+      let mut actions = vec![];
+      position.possible_actions(&mut actions);
+      let planned = line.get(chain.len()).filter(|p| line.starts_with(&chain) && actions.contains(p));
+      next = match planned {
+        Some(&p) => Some(p),
+        None if actions.len() == 1 => actions.pop(),
+        None => agent.choose_action_limited(&position, rng, limits, &mut |_| ()),
+      };
+    }
+    Ok((chain, line))
+  }
+
+  /// Writes "done move=<move>", with the opponent's reply from the
+  /// reported line as "ponder=<move>" if there is one. A bare "done"
+  /// means that the game is over.
+  fn write_done<W : Write>(&self, chain : &[Action], line : &[Action], out : &mut W) -> io::Result<()> {
+    if chain.is_empty() {
+      writeln!(out, "done")?;
+      return out.flush();
+    }
+    write!(out, "done move={}", hub_move(&self.game, chain))?;
+    if line.starts_with(chain) {
+      let mut position = self.game.clone();
+      for a in chain {
+        position.apply_action(a);
+      }
+      if let Some(reply) = complete_moves(&position, &line[chain.len()..]).first() {
+        write!(out, " ponder={}", hub_move(&position, reply))?;
+      }
+    }
+    writeln!(out)?;
+    out.flush()
+  }
+}
+
+/// Splits a Hub command into its name and its arguments. Each argument is
+/// a name, with a value after '=' that is quoted if it contains spaces.
+fn parse_command(line : &str) -> (&str, Vec<(String, String)>) {
+  let line = line.trim();
+  let (command, rest) = match line.find(char::is_whitespace) {
+    Some(i) => (&line[..i], &line[i..]),
+    None => (line, ""),
+  };
+  let mut args = vec![];
+  let mut chars = rest.chars().peekable();
+  loop {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+      chars.next();
+    }
+    let mut name = String::new();
+    while let Some(&c) = chars.peek() {
+      if c.is_whitespace() || c == '=' {
+        break;
+      }
+      name.push(c);
+      chars.next();
+    }
+    if name.is_empty() {
+      break;
+    }
+    let mut value = String::new();
+    if chars.peek() == Some(&'=') {
+      chars.next();
+      if chars.peek() == Some(&'"') {
+        chars.next();
+        value.extend(chars.by_ref().take_while(|&c| c != '"'));
+      }
+      else {
+        value.extend(chars.by_ref().take_while(|c| !c.is_whitespace()));
+      }
+    }
+    args.push((name, value));
+  }
+  (command, args)
+}
+
+/// The rules for a value of the "variant" parameter
+fn variant_rules(name : &str) -> Option<CheckersRules> {
+  let giveaway = name == "losing" || name.ends_with("-losing");
+  let preset = match name.trim_end_matches("-losing") {
+    "losing" => "normal",
+    preset => preset,
+  };
+  let rules = match preset {
+    "normal" => CheckersRules::international(),
+    _ => CheckersRules::from_name(preset).filter(|_| VARIANTS.contains(&preset))?,
+  };
+  Some(if giveaway { rules.giveaway() } else { rules })
+}
+
+/// The value of the "variant" parameter for `rules`, or "custom"
+/// if they don't match a preset
+fn variant_name(rules : CheckersRules) -> String {
+  match (rules.name(), rules.giveaway) {
+    (Some("international"), false) => "normal".into(),
+    (Some("international"), true) => "losing".into(),
+    (Some(name), false) => name.into(),
+    (Some(name), true) => format!("{}-losing", name),
+    (None, _) => "custom".into(),
+  }
+}
+
+/// Reads a position in the Hub format: W or B for the player to move,
+/// and then each dark tile in number order, as w or b for a man, W or
+/// B for a king, or e if it's empty
+fn read_position(rules : CheckersRules, text : &str) -> Result<Checkers, String> {
+  let mut game = Checkers::new(rules);
+  let squares = (rules.board_size * rules.board_size / 2) as usize;
+  let mut chars = text.chars();
+  game.active_player = match chars.next() {
+    Some('W') => Player::White,
+    Some('B') => Player::Black,
+    _ => return Err(format!("the position {} should start with W or B", text)),
+  };
+  if text.len() != squares + 1 {
+    return Err(format!("the position {} should have {} squares", text, squares));
+  }
+  for (i, c) in chars.enumerate() {
+    let tile = match c {
+      'w' => Tile::Occupied(Player::White, Piece::Pawn),
+      'b' => Tile::Occupied(Player::Black, Piece::Pawn),
+      'W' => Tile::Occupied(Player::White, Piece::King),
+      'B' => Tile::Occupied(Player::Black, Piece::King),
+      'e' => Tile::Empty,
+      _ => return Err(format!("'{}' in the position {} isn't a piece", c, text)),
+    };
+    let p = game.square_at(i as i32 + 1).unwrap();
+    game.tiles.set(p, tile);
+  }
+  // Go through a FEN to check for the end of the game
+  Checkers::from_fen(rules, &game.to_fen()).map_err(|e| e.to_string())
+}
+
+fn is_mid_chain(game : &Checkers) -> bool {
+  matches!(game.mode, Mode::ChainCapture(_))
+}
+
+/// Splits a line of actions into complete moves, leaving out
+/// a chain of captures that the line doesn't finish
+fn complete_moves(game : &Checkers, line : &[Action]) -> Vec<Vec<Action>> {
+  let mut position = game.clone();
+  let mut moves = vec![];
+  let mut chain = vec![];
+  for a in line {
+    position.apply_action(a);
+    chain.push(*a);
+    if !is_mid_chain(&position) {
+      moves.push(chain);
+      chain = vec![];
+    }
+  }
+  moves
+}
+
+/// Writes a complete move as the Hub protocol does: "32-28" for a step,
+/// or the start and end of a chain of jumps followed by every piece that
+/// it captures, in order of their numbers, e.g. "28x17x22x23"
+fn hub_move(game : &Checkers, chain : &[Action]) -> String {
+  let mut captured = vec![];
+  let mut ends = vec![];
+  for a in chain {
+    match *a {
+      Action::Step { from, to } => ends.extend(&[from, to]),
+      Action::Jump { from, capture, to } => {
+        ends.extend(&[from, to]);
+        captured.push(game.square_number(capture));
+      }
+    }
+  }
+  let from = game.square_number(ends[0]);
+  let to = game.square_number(*ends.last().unwrap());
+  if captured.is_empty() {
+    return format!("{}-{}", from, to);
+  }
+  captured.sort();
+  let captured : Vec<String> = captured.iter().map(|n| n.to_string()).collect();
+  format!("{}x{}x{}", from, to, captured.join("x"))
+}
+
+/// Finds the complete move written as `hub_move` writes it. The captured
+/// pieces can be left out if only one chain of jumps joins the squares.
+fn parse_hub_move(game : &Checkers, text : &str) -> Result<Vec<Action>, MoveError> {
+  let jump = text.contains('x');
+  let mut squares = text.split(if jump { 'x' } else { '-' })
+    .map(|n| n.parse::<i32>())
+    .collect::<Result<Vec<i32>, _>>()
+    .map_err(|_| MoveError::Malformed(text.into()))?;
+  if squares.len() < 2 || (!jump && squares.len() > 2) {
+    return Err(MoveError::Malformed(text.into()));
+  }
+  let mut captured = squares.split_off(2);
+  captured.sort();
+  let matches : Vec<Vec<Action>> = game.legal_moves().into_iter()
+    .filter(|chain| {
+      let written = hub_move(game, chain);
+      let mut numbers = written.split(['x', '-']).map(|n| n.parse::<i32>().unwrap());
+      let (from, to) = (numbers.next().unwrap(), numbers.next().unwrap());
+      let is_jump = written.contains('x');
+      from == squares[0] && to == squares[1] && is_jump == jump
+        && (captured.is_empty() || numbers.eq(captured.iter().cloned()))
+    })
+    .collect();
+  match matches.len() {
+    0 => Err(MoveError::Illegal(text.into())),
+    1 => Ok(matches.into_iter().next().unwrap()),
+    _ => Err(MoveError::Ambiguous(text.into())),
+  }
+}
+
+/// Writes a search report as an "info" line, with the score in men
+/// for the engine and the time in seconds
+fn write_info<W : Write>(out : &mut W, game : &Checkers, info : &SearchInfo<Action>, elapsed : Duration)
+  -> io::Result<()>
+{
+  let mut position = game.clone();
+  let pv : Vec<String> = complete_moves(game, &info.line).iter().map(|m| {
+    let written = hub_move(&position, m);
+    for a in m {
+      position.apply_action(a);
+    }
+    written
+  }).collect();
+  let seconds = elapsed.as_secs_f64();
+  write!(out, "info depth={} score={:.2} nodes={} time={:.2} nps={:.0}", info.depth, info.score,
+    info.nodes, seconds, info.nodes as f64 / seconds.max(0.001))?;
+  // A line that ends part way through a chain of captures has no complete move to show
+  if !pv.is_empty() {
+    write!(out, " pv=\"{}\"", pv.join(" "))?;
+  }
+  writeln!(out)?;
+  out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  /// Runs the engine on `input` through in-memory pipes, and returns
+  /// every line that it wrote
+  fn run<A : GameAgent<Checkers>>(agent : A, rules : CheckersRules, input : &str) -> Vec<String> {
+    let mut output = vec![];
+    run_hub(agent, rules, Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(String::from).collect()
+  }
+
+  /// Plays the first legal action
+  #[derive(Default)]
+  struct FirstMove {
+    depth : i64,
+  }
+
+  impl GameAgent<Checkers> for FirstMove {
+    fn choose_action(&mut self, game : &Checkers, _rng : &mut StdRng) -> Option<Action> {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      actions.into_iter().next()
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+      vec![("depth", self.depth.to_string())]
+    }
+
+    fn set_parameter(&mut self, name : &str, value : &str) -> bool {
+      name == "depth" && value.parse().map(|v| self.depth = v).is_ok()
+    }
+  }
+
+  /// The man on 28 can take all four pieces, going round either way
+  const CIRCLE : &str = "W:W28:B22,23,12,13";
+
+  #[test]
+  fn commands_are_split_into_arguments() {
+    let (command, args) = parse_command("  pos pos=Wbbe moves=\"32-28 19-23\" flag ");
+    assert_eq!(command, "pos");
+    assert_eq!(args, [("pos".to_string(), "Wbbe".to_string()),
+      ("moves".to_string(), "32-28 19-23".to_string()), ("flag".to_string(), String::new())]);
+    assert_eq!(parse_command("ping"), ("ping", vec![]));
+  }
+
+  #[test]
+  fn moves_list_the_captured_pieces() {
+    let game = Checkers::new(CheckersRules::international());
+    let step = parse_hub_move(&game, "32-28").unwrap();
+    assert_eq!(hub_move(&game, &step), "32-28");
+    assert_eq!(parse_hub_move(&game, "32-29"), Err(MoveError::Illegal("32-29".into())));
+    assert_eq!(parse_hub_move(&game, "32-28-23"), Err(MoveError::Malformed("32-28-23".into())));
+
+    let game = Checkers::from_fen(CheckersRules::international(), "W:W28:B22,12").unwrap();
+    let chain = parse_hub_move(&game, "28x8x12x22").unwrap();
+    let square = |n| game.square_at(n).unwrap();
+    assert_eq!(chain, [Action::Jump { from: square(28), capture: square(22), to: square(17) },
+      Action::Jump { from: square(17), capture: square(12), to: square(8) }]);
+    // The captured pieces can be in any order, or left out
+    assert_eq!(parse_hub_move(&game, "28x8x22x12"), Ok(chain.clone()));
+    assert_eq!(parse_hub_move(&game, "28x8"), Ok(chain));
+    assert_eq!(parse_hub_move(&game, "28x8x13"), Err(MoveError::Illegal("28x8x13".into())));
+
+    // The man can go round either way, and the move is written the same
+    let game = Checkers::from_fen(CheckersRules::international(), CIRCLE).unwrap();
+    assert_eq!(hub_move(&game, &game.legal_moves()[0]), "28x28x12x13x22x23");
+    assert_eq!(parse_hub_move(&game, "28x28x12x13x22x23"), Err(MoveError::Ambiguous("28x28x12x13x22x23".into())));
+    assert_eq!(parse_hub_move(&game, "28x28"), Err(MoveError::Ambiguous("28x28".into())));
+  }
+
+  #[test]
+  fn variants_name_the_rules() {
+    assert_eq!(variant_rules("normal"), Some(CheckersRules::international()));
+    assert_eq!(variant_rules("losing"), Some(CheckersRules::international().giveaway()));
+    assert_eq!(variant_rules("russian-losing"), Some(CheckersRules::russian().giveaway()));
+    assert_eq!(variant_rules("english"), None);
+    assert_eq!(variant_rules("shogi"), None);
+    for &name in VARIANTS {
+      for name in [name.to_string(), format!("{}-losing", name)].iter() {
+        let name = if name == "normal-losing" { "losing" } else { name.as_str() };
+        assert_eq!(variant_name(variant_rules(name).unwrap()), name);
+      }
+    }
+  }
+
+  #[test]
+  fn positions_with_a_side_out_of_pieces_are_over() {
+    let rules = CheckersRules::international();
+    let empty = "e".repeat(49);
+    assert_eq!(read_position(rules, &format!("W{}w", empty)).unwrap().winner(), Some(0));
+    assert_eq!(read_position(rules, &format!("B{}w", empty)).unwrap().winner(), Some(0));
+    assert_eq!(read_position(rules.giveaway(), &format!("W{}w", empty)).unwrap().winner(), Some(1));
+    assert!(read_position(rules, "W").is_err());
+    assert!(read_position(rules, &format!("W{}x", empty)).is_err());
+  }
+
+  #[test]
+  fn new_game_and_go_answer_with_done() {
+    let input = "hub\ninit\nset-param name=depth value=3\nnew-game\ngo think\nping\n\
+      pos pos=W".to_string() + &"e".repeat(49) + "w\ngo think\nquit\n";
+    let output = run(FirstMove::default(), CheckersRules::international(), &input);
+    assert!(output[0].starts_with("id name=\"Workshop "));
+    assert!(output.contains(&"param name=depth value=0 type=int min=-1000000 max=1000000".to_string()));
+    let rest = &output[output.iter().position(|l| l == "wait").unwrap() + 1..];
+    assert_eq!(rest, ["ready", "done move=31-26", "pong", "done"]);
+  }
+
+  #[test]
+  fn bad_commands_are_reported() {
+    let input = "set-param name=variant value=shogi\nset-param name=width value=3\nset-param\n\
+      pos pos=X\npos moves=32-29\nlevel depth=deep\nfly\n";
+    let output = run(FirstMove::default(), CheckersRules::international(), input);
+    assert_eq!(output[..3], ["error message=\"unknown variant shogi\"", "error message=\"can't set width to 3\"",
+      "error message=\"set-param needs a name and a value\""]);
+    assert_eq!(output[3..].iter().filter(|l| l.starts_with("error message=")).count(), 4);
+    assert_eq!(output.last().unwrap(), "error message=\"unknown command fly\"");
+  }
+}
@@ -0,0 +1,176 @@
+use rand::{Rng, rngs::StdRng};
+
+use crate::agents::{Game, Stochastic, HiddenInformation};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+/// The three cards in the deck, in order of strength
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Card {
+  Jack,
+  Queen,
+  King,
+}
+
+use Card::*;
+
+const DECK : [Card ; 3] = [Jack, Queen, King];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+  /// Deal a card to each player (a chance outcome)
+  Deal([Card ; 2]),
+  /// Check, or fold when facing a bet
+  Pass,
+  /// Bet one chip, or call a bet
+  Bet,
+}
+
+use Action::*;
+
+/// Kuhn poker, a minimal poker game for two players.
+///
+/// Each player antes one chip and is dealt one card from a deck of
+/// three. Player 0 may check or bet one chip. If they check, player 1
+/// may check (going to showdown) or bet. Facing a bet, a player either
+/// calls (going to showdown) or folds. The higher card wins the pot.
+#[derive(Clone)]
+pub struct KuhnPoker {
+  /// None until the cards have been dealt
+  cards : Option<[Card ; 2]>,
+  /// The betting decisions so far
+  history : Vec<Action>,
+}
+
+/// What one player can see: their own card and the betting so far
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Observation {
+  pub player : i64,
+  pub card : Option<Card>,
+  pub history : Vec<Action>,
+}
+
+impl KuhnPoker {
+
+  pub fn new() -> KuhnPoker {
+    KuhnPoker { cards: None, history: vec![] }
+  }
+
+  fn is_finished(&self) -> bool {
+    matches!(self.history.as_slice(), [Pass, Pass] | [Bet, _] | [Pass, Bet, _])
+  }
+
+  /// The chips won by player 0 (and lost by player 1), once the game is over
+  fn result(&self) -> Option<i32> {
+    if !self.is_finished() {
+      return None;
+    }
+    let cards = self.cards?;
+    let showdown = |stake : i32| if cards[0] as i32 > cards[1] as i32 { stake } else { -stake };
+    match self.history.as_slice() {
+      [Pass, Pass] => Some(showdown(1)),
+      [Bet, Bet] | [Pass, Bet, Bet] => Some(showdown(2)),
+      [Bet, Pass] => Some(1),
+      [Pass, Bet, Pass] => Some(-1),
+      _ => None,
+    }
+  }
+}
+
+impl Game for KuhnPoker {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.cards.is_some() && !self.is_finished() {
+      actions.push(Pass);
+      actions.push(Bet);
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    (self.history.len() % 2) as i64
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    match *a {
+      Deal(cards) => self.cards = Some(cards),
+      bet => self.history.push(bet),
+    }
+  }
+
+  /// The chips won or lost by the player
+  fn player_score(&self, player : i64) -> f64 {
+    let won = self.result().unwrap_or(0) as f64;
+    match player {
+      0 => won,
+      1 => -won,
+      _ => panic!("kuhn poker is a two-player game"),
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    self.result().map(|r| if r > 0 { 0 } else { 1 })
+  }
+
+  fn payoffs(&self) -> Vec<f64> {
+    vec![self.player_score(0), self.player_score(1)]
+  }
+}
+
+impl Stochastic for KuhnPoker {
+  fn is_chance_node(&self) -> bool {
+    self.cards.is_none()
+  }
+
+  fn chance_outcomes(&self, outcomes : &mut Vec<(Action, f64)>) {
+    if self.cards.is_none() {
+      for &a in DECK.iter() {
+        for &b in DECK.iter() {
+          if a != b {
+            outcomes.push((Deal([a, b]), 1.0 / 6.0));
+          }
+        }
+      }
+    }
+  }
+}
+
+/// The deal is a chance event, so it is part of the record
+impl Recordable for KuhnPoker {
+  const GAME_TYPE : &'static str = "kuhn";
+
+  fn config(&self) -> String {
+    String::new()
+  }
+
+  fn from_config(_config : &str) -> Option<KuhnPoker> {
+    Some(KuhnPoker::new())
+  }
+}
+
+/// Actions are written as "Pass" and "Bet"
+impl Notation for KuhnPoker {}
+
+impl HiddenInformation for KuhnPoker {
+  type Observation = Observation;
+
+  fn observe(&self, player : i64) -> Observation {
+    Observation {
+      player,
+      card: self.cards.map(|c| c[player as usize]),
+      history: self.history.clone(),
+    }
+  }
+
+  fn determinize(observation : &Observation, rng : &mut StdRng) -> KuhnPoker {
+    let card = match observation.card {
+      Some(c) => c,
+      None => return KuhnPoker::new(),
+    };
+    let others : Vec<Card> = DECK.iter().cloned().filter(|&c| c != card).collect();
+    let other = others[rng.gen_range(0, others.len())];
+    let mut cards = [other, other];
+    cards[observation.player as usize] = card;
+    KuhnPoker { cards: Some(cards), history: observation.history.clone() }
+  }
+}
@@ -0,0 +1,299 @@
+use rand::{Rng, rngs::StdRng};
+
+use crate::agents::{Game, Stochastic, HiddenInformation};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+/// The three ranks in the deck, in order of strength.
+/// The deck holds two cards of each rank.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Card {
+  Jack,
+  Queen,
+  King,
+}
+
+use Card::*;
+
+const RANKS : [Card ; 3] = [Jack, Queen, King];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+  /// Deal a private card to each player (a chance outcome)
+  DealPrivate([Card ; 2]),
+  /// Deal the public card after the first round (a chance outcome)
+  DealPublic(Card),
+  Fold,
+  /// Check, or call a bet
+  Call,
+  /// Bet, or raise a bet
+  Raise,
+}
+
+use Action::*;
+
+/// The most bets and raises allowed in one betting round
+const MAX_RAISES : i32 = 2;
+
+/// Leduc hold'em, a small poker game for two players.
+///
+/// Each player antes one chip and is dealt a private card from a deck of
+/// six. After a round of betting a public card is dealt, followed by a
+/// second round. Bets are two chips in the first round and four in the
+/// second, with at most one bet and one raise per round. At showdown a
+/// player whose card pairs the public card wins, and otherwise the
+/// higher card wins.
+#[derive(Clone)]
+pub struct LeducPoker {
+  /// None until the private cards have been dealt
+  private : Option<[Card ; 2]>,
+  public : Option<Card>,
+  /// The public actions so far: betting, and the public card
+  history : Vec<Action>,
+  /// The chips that each player has put in the pot
+  bets : [i32 ; 2],
+  /// The number of bets and raises in the current round
+  raises : i32,
+  /// The number of betting actions in the current round
+  round_actions : i32,
+  active_player : usize,
+  folded : Option<usize>,
+  /// Both betting rounds are over, and the hands are compared
+  showdown : bool,
+}
+
+/// What one player can see: their own card, the public card,
+/// and the betting so far
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Observation {
+  pub player : i64,
+  pub card : Option<Card>,
+  pub history : Vec<Action>,
+}
+
+impl LeducPoker {
+
+  pub fn new() -> LeducPoker {
+    LeducPoker {
+      private: None, public: None, history: vec![], bets: [1, 1],
+      raises: 0, round_actions: 0, active_player: 0, folded: None, showdown: false,
+    }
+  }
+
+  fn bet_size(&self) -> i32 {
+    if self.public.is_none() { 2 } else { 4 }
+  }
+
+  /// The number of cards of each rank left in the deck
+  fn remaining(&self) -> [i32 ; 3] {
+    let mut counts = [2, 2, 2];
+    for c in self.private.iter().flat_map(|p| p.iter()).chain(self.public.iter()) {
+      counts[*c as usize] -= 1;
+    }
+    counts
+  }
+
+  /// After the first round, this leaves a chance node for the public card
+  fn end_round(&mut self) {
+    if self.public.is_none() {
+      self.raises = 0;
+      self.round_actions = 0;
+    }
+    else {
+      self.showdown = true;
+    }
+  }
+
+  fn waiting_for_public_card(&self) -> bool {
+    self.private.is_some() && self.public.is_none() && self.folded.is_none()
+      && !self.history.is_empty() && self.round_actions == 0
+  }
+
+  fn hand_strength(&self, player : usize) -> i32 {
+    let card = self.private.unwrap()[player];
+    if Some(card) == self.public { 10 } else { card as i32 }
+  }
+
+  /// The chips won by player 0 (and lost by player 1), once the game is over
+  fn result(&self) -> Option<i32> {
+    if let Some(p) = self.folded {
+      return Some(if p == 0 { -self.bets[0] } else { self.bets[1] });
+    }
+    if !self.showdown {
+      return None;
+    }
+    let (a, b) = (self.hand_strength(0), self.hand_strength(1));
+    Some(if a > b { self.bets[1] } else if a < b { -self.bets[0] } else { 0 })
+  }
+}
+
+impl Game for LeducPoker {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.is_chance_node() || self.result().is_some() {
+      return;
+    }
+    if self.bets[0] != self.bets[1] {
+      actions.push(Fold);
+    }
+    actions.push(Call);
+    if self.raises < MAX_RAISES {
+      actions.push(Raise);
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player as i64
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    let p = self.active_player;
+    let other = 1 - p;
+    match *a {
+      DealPrivate(cards) => {
+        self.private = Some(cards);
+        return;
+      }
+      DealPublic(card) => {
+        self.public = Some(card);
+        self.history.push(*a);
+        return;
+      }
+      Fold => {
+        self.folded = Some(p);
+      }
+      Call => {
+        self.bets[p] = self.bets[other];
+        self.round_actions += 1;
+        // A call, or a second check, ends the round
+        if self.round_actions >= 2 {
+          self.end_round();
+        }
+      }
+      Raise => {
+        self.bets[p] = self.bets[other] + self.bet_size();
+        self.raises += 1;
+        self.round_actions += 1;
+      }
+    }
+    self.history.push(*a);
+    self.active_player = other;
+    if self.waiting_for_public_card() {
+      self.active_player = 0;
+    }
+  }
+
+  /// The chips won or lost by the player
+  fn player_score(&self, player : i64) -> f64 {
+    let won = self.result().unwrap_or(0) as f64;
+    match player {
+      0 => won,
+      1 => -won,
+      _ => panic!("leduc poker is a two-player game"),
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    match self.result() {
+      Some(r) if r > 0 => Some(0),
+      Some(r) if r < 0 => Some(1),
+      _ => None,
+    }
+  }
+
+  fn payoffs(&self) -> Vec<f64> {
+    vec![self.player_score(0), self.player_score(1)]
+  }
+
+  /// Both hands had the same rank at showdown, so the pot was split
+  fn is_draw(&self) -> bool {
+    self.result() == Some(0)
+  }
+}
+
+impl Stochastic for LeducPoker {
+  fn is_chance_node(&self) -> bool {
+    self.private.is_none() || self.waiting_for_public_card()
+  }
+
+  fn chance_outcomes(&self, outcomes : &mut Vec<(Action, f64)>) {
+    if self.private.is_none() {
+      // Two cards drawn from six, of which two have each rank
+      for &a in RANKS.iter() {
+        for &b in RANKS.iter() {
+          let p = if a == b { (2.0 / 6.0) * (1.0 / 5.0) } else { (2.0 / 6.0) * (2.0 / 5.0) };
+          outcomes.push((DealPrivate([a, b]), p));
+        }
+      }
+    }
+    else if self.waiting_for_public_card() {
+      let remaining = self.remaining();
+      for &c in RANKS.iter() {
+        let count = remaining[c as usize];
+        if count > 0 {
+          outcomes.push((DealPublic(c), count as f64 / 4.0));
+        }
+      }
+    }
+  }
+}
+
+/// The deals are chance events, so they are part of the record
+impl Recordable for LeducPoker {
+  const GAME_TYPE : &'static str = "leduc";
+
+  fn config(&self) -> String {
+    String::new()
+  }
+
+  fn from_config(_config : &str) -> Option<LeducPoker> {
+    Some(LeducPoker::new())
+  }
+}
+
+impl Notation for LeducPoker {}
+
+impl HiddenInformation for LeducPoker {
+  type Observation = Observation;
+
+  fn observe(&self, player : i64) -> Observation {
+    Observation {
+      player,
+      card: self.private.map(|c| c[player as usize]),
+      history: self.history.clone(),
+    }
+  }
+
+  fn determinize(observation : &Observation, rng : &mut StdRng) -> LeducPoker {
+    let mut game = LeducPoker::new();
+    let card = match observation.card {
+      Some(c) => c,
+      None => return game,
+    };
+    // Draw the opponent's card from the cards this player can't see
+    let mut counts = [2, 2, 2];
+    counts[card as usize] -= 1;
+    for a in observation.history.iter() {
+      if let DealPublic(c) = a {
+        counts[*c as usize] -= 1;
+      }
+    }
+    let mut r = rng.gen_range(0, counts.iter().sum::<i32>());
+    let mut other = Jack;
+    for &c in RANKS.iter() {
+      if r < counts[c as usize] {
+        other = c;
+        break;
+      }
+      r -= counts[c as usize];
+    }
+    let mut cards = [other, other];
+    cards[observation.player as usize] = card;
+    game.apply_action(&DealPrivate(cards));
+    for a in observation.history.iter() {
+      game.apply_action(a);
+    }
+    game
+  }
+}
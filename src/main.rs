@@ -2,14 +2,41 @@
 mod utils;
 mod checkers;
 mod chess;
+mod pgn;
+mod pdn;
 mod tic_tac_toe;
+mod ultimate_tic_tac_toe;
+mod breakthrough;
+mod amazons;
+mod pig;
+mod chinese_checkers;
+mod kuhn_poker;
+mod leduc_poker;
+mod cfr;
 mod agents;
 mod contest;
+mod record;
+mod notation;
+mod uci;
+mod xboard;
+mod hub;
 mod exercise;
 
 use std::env;
-use agents::{RandomAgent, RolloutAgent};
-use checkers::Checkers;
+use agents::{RandomAgent, RolloutAgent, ExpectimaxAgent, DeterminizedRolloutAgent, Observer};
+use checkers::{Checkers, CheckersRules};
+use chess::{Chess, Variant};
+use tic_tac_toe::{TicTacToe, MnkRules};
+use ultimate_tic_tac_toe::UltimateTicTacToe;
+use breakthrough::Breakthrough;
+use amazons::Amazons;
+use pig::Pig;
+use chinese_checkers::ChineseCheckers;
+use kuhn_poker::KuhnPoker;
+use leduc_poker::LeducPoker;
+use contest::ContestAgent;
+use record::{Record, Recordable, Replay};
+use notation::Notation;
 
 fn main() {
   let random_agent = RandomAgent{};
@@ -17,22 +44,440 @@ fn main() {
   let rollout_deep = RolloutAgent{ iterations: 300, depth: 20 };
   let rollout_weak = RolloutAgent{ iterations: 1, depth: 1 };
   let rollout_strong = RolloutAgent{ iterations: 600, depth: 20 };
+  let expectimax = ExpectimaxAgent{ depth: 4, min_score: -1.0, max_score: 1.0, star2: true };
+  // Chess scores are in pawns, or 1000 for a win
+  let chess_expectimax = ExpectimaxAgent{ depth: 3, min_score: -1000.0, max_score: 1000.0, star2: false };
+  // As are draughts scores, in men
+  let draughts_expectimax = ExpectimaxAgent{ depth: 4, min_score: -1000.0, max_score: 1000.0, star2: false };
 
   if let Some(arg) = env::args().nth(1) {
     match arg.as_str() {
       "contest" => {
-        contest::run_contest(Checkers::new(), rollout_broad, rollout_deep);
+        // The games are saved if a .pdn file is named
+        let args : Vec<String> = env::args().skip(2).filter(|a| !a.ends_with(".jsonl")).collect();
+        let pdn_path = args.iter().find(|a| a.ends_with(".pdn")).cloned();
+        let rules = checkers_rules(args.into_iter().filter(|a| !a.ends_with(".pdn")));
+        let mut pdn = String::new();
+        let mut records = vec![];
+        contest::run_contest_recorded(|_| Checkers::new(rules), &[&rollout_broad, &rollout_deep], |record| {
+          let tags = [("Event", "Workshop checkers contest".to_string()),
+            ("Round", (record.index + 1).to_string()),
+            ("White", record.agents[0].clone()), ("Black", record.agents[1].clone()),
+            ("Seed", record.seed.to_string())];
+          pdn.push_str(&pdn::write_pdn(&tags, &record.start, &record.actions));
+          records.push(Record::from_game(&record));
+        });
+        save_records(&records);
+        if let Some(path) = pdn_path {
+          match std::fs::write(&path, pdn) {
+            Ok(()) => println!("Saved the games to {}", path),
+            Err(e) => println!("Couldn't save the games: {}", e),
+          }
+        }
+      }
+      "pig" => {
+        run_contest(Pig::new(100), &[&rollout_broad, &random_agent]);
+      }
+      "pig-expectimax" => {
+        println!("Expectimax (P1) vs rollout (P2)");
+        run_contest(Pig::new(100), &[&expectimax, &rollout_broad]);
+        println!("Rollout (P1) vs expectimax (P2)");
+        run_contest(Pig::new(100), &[&rollout_broad, &expectimax]);
+      }
+      "halma" => {
+        let rollout_quick = RolloutAgent{ iterations: 10, depth: 10 };
+        run_contest(ChineseCheckers::new(3),
+          &[&rollout_quick, &rollout_weak, &random_agent]);
+      }
+      "kuhn" => {
+        let determinized = Observer(DeterminizedRolloutAgent{ determinizations: 20, iterations: 10, depth: 10 });
+        println!("Determinized rollout (P1) vs random (P2)");
+        run_contest(KuhnPoker::new(), &[&determinized, &random_agent]);
+        println!("Random (P1) vs determinized rollout (P2)");
+        run_contest(KuhnPoker::new(), &[&random_agent, &determinized]);
+      }
+      "cfr" => {
+        let iterations = env::args().nth(3).and_then(|s| s.parse().ok()).unwrap_or(1000);
+        match env::args().nth(2).as_deref() {
+          Some("leduc") => solve_poker(LeducPoker::new(), iterations),
+          _ => solve_poker(KuhnPoker::new(), iterations),
+        }
       }
       "tictactoe" => {
-        tic_tac_toe::play_game(rollout_broad, rollout_weak);
+        tic_tac_toe::play_game(mnk_rules(env::args().skip(2)), rollout_broad, rollout_weak);
+      }
+      "ultimate" => {
+        ultimate_tic_tac_toe::play_ultimate_tic_tac_toe(random_agent, rollout_broad);
+      }
+      "ultimate-contest" => {
+        run_contest(UltimateTicTacToe::new(), &[&rollout_broad, &rollout_deep]);
       }
       "checkers" => {
-        checkers::play_checkers(random_agent, rollout_weak);
+        let rules = checkers_rules(env::args().skip(2));
+        checkers::play_checkers(rules, random_agent, rollout_weak);
+      }
+      "chess" => {
+        let variant = chess_variant(env::args().nth(2));
+        chess::play_chess(Chess::new().with_variant(variant), random_agent, rollout_weak);
+      }
+      "chess960" => {
+        // A position number from 0 to 959, the back row pieces
+        // (e.g. RNBQKBNR), or a random position
+        let arg = env::args().nth(2).unwrap_or_default();
+        let game = match arg.parse::<u32>() {
+          Ok(index) if index < 960 => Chess::chess960(index),
+          _ => Chess::from_back_row(&arg).unwrap_or_else(|| {
+            let index = rand::random::<u32>() % 960;
+            println!("Chess960 position {}", index);
+            Chess::chess960(index)
+          }),
+        };
+        let variant = chess_variant(env::args().nth(3));
+        chess::play_chess(game.with_variant(variant), random_agent, rollout_weak);
+      }
+      "fen" => {
+        // A position in Forsyth-Edwards Notation, e.g.
+        // "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"
+        let fen = env::args().nth(2).unwrap_or_default();
+        let variant = chess_variant(env::args().nth(3));
+        match Chess::from_fen(variant, &fen) {
+          Ok(game) => chess::play_chess(game, random_agent, rollout_weak),
+          Err(e) => println!("Invalid FEN: {}", e),
+        }
+      }
+      "chess-contest" => {
+        // Each game starts from a different Chess960 position. The
+        // games are saved if a .pgn file is named, after the variant.
+        let rollout_quick = RolloutAgent{ iterations: 20, depth: 10 };
+        let args : Vec<String> = env::args().skip(2).filter(|a| !a.ends_with(".jsonl")).collect();
+        let pgn_path = args.iter().find(|a| a.ends_with(".pgn")).cloned();
+        let variant = chess_variant(args.into_iter().find(|a| !a.ends_with(".pgn")));
+        let mut pgn = String::new();
+        let mut records = vec![];
+        contest::run_contest_recorded(|i| Chess::chess960_from_seed(i as u64).with_variant(variant),
+          &[&rollout_quick, &rollout_weak], |record| {
+            let tags = [("Event", "Workshop chess contest".to_string()),
+              ("Round", (record.index + 1).to_string()),
+              ("White", record.agents[0].clone()), ("Black", record.agents[1].clone()),
+              ("Seed", record.seed.to_string())];
+            pgn.push_str(&pgn::write_pgn(&tags, &record.start, &record.actions));
+            records.push(Record::from_game(&record));
+          });
+        save_records(&records);
+        if let Some(path) = pgn_path {
+          match std::fs::write(&path, pgn) {
+            Ok(()) => println!("Saved the games to {}", path),
+            Err(e) => println!("Couldn't save the games: {}", e),
+          }
+        }
+      }
+      "pgn" => {
+        // Replays each game in a PGN file, and checks its result
+        let path = env::args().nth(2).unwrap_or_default();
+        let games = std::fs::read_to_string(&path).map_err(|e| e.to_string())
+          .and_then(|text| pgn::read_pgn(&text).map_err(|e| e.to_string()));
+        match games {
+          Ok(games) => for (i, game) in games.iter().enumerate() {
+            let end = game.replay();
+            let result = pgn::result(&end);
+            println!("Game {}: {} vs {}, {} moves, {}", i + 1,
+              game.header("White").unwrap_or("?"), game.header("Black").unwrap_or("?"),
+              game.actions.len(), result);
+            if game.header("Result").is_some_and(|r| r != result && result != "*") {
+              println!("  The Result tag says {}", game.header("Result").unwrap());
+            }
+            println!("  Final position: {}", end.to_fen());
+          },
+          Err(e) => println!("Couldn't read {}: {}", path, e),
+        }
+      }
+      "breakthrough" => {
+        breakthrough::play_breakthrough(random_agent, rollout_broad);
+      }
+      "amazons" => {
+        amazons::play_amazons(random_agent, rollout_weak);
+      }
+      "perft" => {
+        let depth = env::args().nth(3).and_then(|s| s.parse().ok()).unwrap_or(3);
+        match env::args().nth(2).as_deref() {
+          Some("checkers") => run_perft(&Checkers::new(CheckersRules::american()), depth),
+          Some("draughts") => run_perft(&Checkers::new(CheckersRules::international()), depth),
+          Some("chess") => run_perft(&Chess::new(), depth),
+          Some("tictactoe") => run_perft(&TicTacToe::new(MnkRules::tic_tac_toe()), depth),
+          Some("ultimate") => run_perft(&UltimateTicTacToe::new(), depth),
+          Some("breakthrough") => run_perft(&Breakthrough::new(), depth),
+          Some("amazons") => run_perft(&Amazons::new(), depth),
+          _ => println!("Usage: perft checkers|draughts|chess|tictactoe|ultimate|breakthrough|amazons [depth]"),
+        }
+      }
+      "uci" => {
+        // Plays chess for a GUI, e.g. cutechess-cli, over standard input and output
+        let input = std::io::BufReader::new(std::io::stdin());
+        let output = std::io::stdout();
+        let result = match env::args().nth(2).as_deref() {
+          Some("rollout") => uci::run_uci(rollout_broad, input, output),
+          Some("random") => uci::run_uci(random_agent, input, output),
+          _ => uci::run_uci(chess_expectimax, input, output),
+        };
+        if let Err(e) = result {
+          eprintln!("UCI error: {}", e);
+        }
+      }
+      "xboard" => {
+        // Plays chess for XBoard or WinBoard over standard input and output
+        let input = std::io::BufReader::new(std::io::stdin());
+        let output = std::io::stdout();
+        let result = match env::args().nth(2).as_deref() {
+          Some("rollout") => xboard::run_xboard(rollout_broad, input, output),
+          Some("random") => xboard::run_xboard(random_agent, input, output),
+          _ => xboard::run_xboard(chess_expectimax, input, output),
+        };
+        if let Err(e) = result {
+          eprintln!("CECP error: {}", e);
+        }
+      }
+      "hub" => {
+        // Plays draughts for Hub interfaces, e.g. Scan's, over standard input
+        // and output. The rules default to international draughts.
+        let (agent, rules) : (Vec<String>, Vec<String>) =
+          env::args().skip(2).partition(|a| a == "rollout" || a == "random");
+        let rules = if rules.is_empty() { CheckersRules::international() } else { checkers_rules(rules.into_iter()) };
+        let input = std::io::BufReader::new(std::io::stdin());
+        let output = std::io::stdout();
+        let result = match agent.first().map(String::as_str) {
+          Some("rollout") => hub::run_hub(rollout_broad, rules, input, output),
+          Some("random") => hub::run_hub(random_agent, rules, input, output),
+          _ => hub::run_hub(draughts_expectimax, rules, input, output),
+        };
+        if let Err(e) = result {
+          eprintln!("Hub error: {}", e);
+        }
+      }
+      "board" => {
+        // Reads a position drawn as text from standard input, and
+        // prints it back with the moves that are available
+        let mut text = String::new();
+        if let Err(e) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut text) {
+          println!("Couldn't read the position: {}", e);
+        }
+        match env::args().nth(2).as_deref() {
+          Some("checkers") => show_board(Checkers::from_ascii(checkers_rules(env::args().skip(3)), &text)),
+          Some("chess") => show_board(Chess::from_ascii(&text)),
+          Some("tictactoe") => show_board(TicTacToe::from_ascii(mnk_rules(env::args().skip(3)), &text)),
+          _ => println!("Usage: board checkers|chess|tictactoe [rules] < position"),
+        }
+      }
+      "pdn" => {
+        // Replays each game in a PDN file. Games without a GameType
+        // tag use the rules named after the file.
+        let path = env::args().nth(2).unwrap_or_default();
+        let rules = checkers_rules(env::args().skip(3));
+        let games = std::fs::read_to_string(&path).map_err(|e| e.to_string())
+          .and_then(|text| pdn::read_pdn(&text, rules).map_err(|e| e.to_string()));
+        match games {
+          Ok(games) => for (i, game) in games.iter().enumerate() {
+            let end = game.replay();
+            let result = pdn::result(&end);
+            println!("Game {}: {} vs {}, {}", i + 1,
+              game.header("White").unwrap_or("?"), game.header("Black").unwrap_or("?"), result);
+            if game.header("Result").is_some_and(|r| r != result && result != "*") {
+              println!("  The Result tag says {}", game.header("Result").unwrap());
+            }
+            println!("  Final position: {}", end.to_fen());
+          },
+          Err(e) => println!("Couldn't read {}: {}", path, e),
+        }
+      }
+      "replay" => {
+        // Replays each game in a file of records from a contest, and
+        // lists the moves of the game numbered after the file
+        let path = env::args().nth(2).unwrap_or_default();
+        let listed = env::args().nth(3).and_then(|n| n.parse::<usize>().ok());
+        let records = std::fs::read_to_string(&path).map_err(|e| e.to_string())
+          .and_then(|text| record::read_records(&text).map_err(|e| e.to_string()));
+        match records {
+          Ok(records) => for (i, r) in records.iter().enumerate() {
+            let list_moves = listed == Some(i + 1);
+            let replayed = match r.game_type.as_str() {
+              "checkers" => replay::<Checkers>(r, list_moves),
+              "chess" => replay::<Chess>(r, list_moves),
+              "pig" => replay::<Pig>(r, list_moves),
+              "chinese-checkers" => replay::<ChineseCheckers>(r, list_moves),
+              "kuhn" => replay::<KuhnPoker>(r, list_moves),
+              "leduc" => replay::<LeducPoker>(r, list_moves),
+              "tictactoe" => replay::<TicTacToe>(r, list_moves),
+              "ultimate" => replay::<UltimateTicTacToe>(r, list_moves),
+              "breakthrough" => replay::<Breakthrough>(r, list_moves),
+              "amazons" => replay::<Amazons>(r, list_moves),
+              other => Err(format!("unknown game \"{}\"", other)),
+            };
+            match replayed {
+              Ok(summary) => println!("Game {}: {}, {}", i + 1, r.agents.join(" vs "), summary),
+              Err(e) => println!("Game {}: {}", i + 1, e),
+            }
+          },
+          Err(e) => println!("Couldn't read {}: {}", path, e),
+        }
+      }
+      "draughts" => {
+        checkers::play_checkers(CheckersRules::international(), random_agent, rollout_weak);
       }
       s => println!("Argument not recognised: {}", s),
     }
   }
   else {
-    checkers::play_checkers(rollout_strong, rollout_weak);
+    checkers::play_checkers(CheckersRules::american(), rollout_strong, rollout_weak);
+  }
+}
+
+/// Looks up the checkers rules named on the command line, defaulting
+/// to American checkers. "giveaway" selects the antidraughts version.
+fn checkers_rules(args : impl Iterator<Item=String>) -> CheckersRules {
+  let mut rules = CheckersRules::american();
+  let mut giveaway = false;
+  for arg in args {
+    if arg == "giveaway" {
+      giveaway = true;
+    }
+    else if let Some(r) = CheckersRules::from_name(&arg) {
+      rules = r;
+    }
+    else {
+      println!("Unknown checkers rules: {}", arg);
+    }
+  }
+  if giveaway { rules.giveaway() } else { rules }
+}
+
+/// Reads the m,n,k game from the command line: either a preset
+/// name, or the width, height and line length, e.g. "7 6 4".
+/// Adding "swap2" starts with the Swap2 opening.
+fn mnk_rules(args : impl Iterator<Item=String>) -> MnkRules {
+  let mut args : Vec<String> = args.collect();
+  let swap2 = args.iter().any(|a| a == "swap2");
+  args.retain(|a| a != "swap2");
+  let mut rules = match args.as_slice() {
+    [m, n, k] => match (m.parse(), n.parse(), k.parse()) {
+      (Ok(m), Ok(n), Ok(k)) => Some(MnkRules::new(m, n, k)),
+      _ => None,
+    },
+    [name] => MnkRules::from_name(name),
+    [] => Some(MnkRules::tic_tac_toe()),
+    _ => None,
+  }.unwrap_or_else(|| {
+    println!("Unknown tic tac toe rules: {}, using tictactoe", args.join(" "));
+    MnkRules::tic_tac_toe()
+  });
+  rules.swap2 = swap2;
+  rules
+}
+
+/// Looks up the chess variant named on the command line
+fn chess_variant(name : Option<String>) -> Variant {
+  match name {
+    Some(name) => Variant::from_name(&name).unwrap_or_else(|| {
+      println!("Unknown chess variant: {}, using standard", name);
+      Variant::Standard
+    }),
+    None => Variant::Standard,
+  }
+}
+
+/// The .jsonl file named on the command line to save contest records in
+fn records_path() -> Option<String> {
+  env::args().skip(2).find(|a| a.ends_with(".jsonl"))
+}
+
+/// Adds the records to the .jsonl file named on the command line, if there is one
+fn save_records(records : &[Record]) {
+  if let Some(path) = records_path() {
+    match record::append_records(&path, records) {
+      Ok(()) => println!("Saved the records to {}", path),
+      Err(e) => println!("Couldn't save the records: {}", e),
+    }
+  }
+}
+
+/// Plays a contest, and saves the records of the games
+/// if a .jsonl file is named on the command line
+fn run_contest<G>(game : G, agents : &[&dyn ContestAgent<G>])
+  where G : Recordable + Send + Sync, G::Action : Send
+{
+  match records_path() {
+    Some(path) => match contest::run_contest_saved(|_| game.clone(), agents, &path) {
+      Ok(()) => println!("Saved the records to {}", path),
+      Err(e) => println!("Couldn't save the records: {}", e),
+    },
+    None => contest::run_contest(game, agents),
+  }
+}
+
+/// Steps through a record to the end, then back to the start, and
+/// describes the game, checking that it reaches the recorded result
+/// and that the notation of each move reads back as the same move
+fn replay<G : Recordable + Notation>(record : &Record, list_moves : bool) -> Result<String, String> {
+  let mut replay = Replay::<G>::new(record).map_err(|e| e.to_string())?;
+  let mut moves = vec![];
+  while !replay.is_at_end() {
+    let before = replay.game().clone();
+    if let Some(a) = replay.forward() {
+      let notation = before.format_action(a);
+      if before.parse_action(&notation).as_ref() != Ok(a) {
+        return Err(format!("the move {} doesn't read back as the same move", notation));
+      }
+      moves.push(notation);
+    }
+  }
+  let (length, payoffs) = (replay.position(), replay.game().payoffs());
+  while replay.back() {}
+  if payoffs != record.result {
+    return Err(format!("the game ends with payoffs {:?}, not {:?}", payoffs, record.result));
+  }
+  let mut summary = format!("{} actions, payoffs {:?}", length, payoffs);
+  if list_moves {
+    summary.push_str(&format!("\n  {}", moves.join(" ")));
+  }
+  Ok(summary)
+}
+
+/// Prints a position read by `board`, and the moves that are available
+fn show_board<G : Notation + std::fmt::Display>(game : Result<G, utils::AsciiError>) {
+  match game {
+    Ok(game) => {
+      print!("{}", game);
+      let moves : Vec<String> = agents::available_actions(&game).iter()
+        .map(|a| game.format_action(a)).collect();
+      println!("Moves: {}", if moves.is_empty() { "none".into() } else { moves.join(" ") });
+    }
+    Err(e) => println!("Couldn't read the position: {}", e),
+  }
+}
+
+/// Prints the perft count at each depth up to `depth`
+fn run_perft<G : agents::Stochastic>(game : &G, depth : i64) {
+  for d in 1..=depth {
+    println!("Depth {}: {}", d, agents::perft(game, d));
+  }
+}
+
+/// Solves a poker game with CFR+, and then plays the solution
+/// against a determinized rollout agent
+fn solve_poker<G>(game : G, iterations : i64)
+  where G : agents::HiddenInformation + Recordable + Send + Sync + 'static,
+    G::Action : Send, G::Observation : std::hash::Hash + Eq + Send + Sync
+{
+  let mut solver = cfr::CfrSolver::new(game.clone(), true);
+  let mut checkpoint = 1;
+  while solver.iterations() < iterations {
+    let next = (checkpoint * 10).min(iterations);
+    solver.train(next - solver.iterations());
+    println!("Iterations: {}, exploitability: {:.5}", solver.iterations(), solver.exploitability());
+    checkpoint = next;
   }
+  let solved = cfr::CfrAgent{ strategy: solver.strategy() };
+  let determinized = Observer(DeterminizedRolloutAgent{ determinizations: 20, iterations: 10, depth: 10 });
+  println!("CFR (P1) vs determinized rollout (P2)");
+  run_contest(game.clone(), &[&solved, &determinized]);
+  println!("Determinized rollout (P1) vs CFR (P2)");
+  run_contest(game, &[&determinized, &solved]);
 }
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use crate::agents::{Stochastic, available_actions};
+
+/// The reasons that `Notation::parse_action` can reject some text
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotationError {
+  /// The text isn't written in the game's notation
+  Malformed(String),
+  /// No available action matches the text
+  Illegal(String),
+  /// More than one available action matches the text
+  Ambiguous(String),
+}
+
+impl fmt::Display for NotationError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      NotationError::Malformed(text) => write!(f, "can't read the move '{}'", text),
+      NotationError::Illegal(text) => write!(f, "'{}' isn't a legal move", text),
+      NotationError::Ambiguous(text) => write!(f, "'{}' could be more than one move", text),
+    }
+  }
+}
+
+impl std::error::Error for NotationError {}
+
+/// Writes and reads actions in a game's usual notation, like "Nf3" in chess
+pub trait Notation : Stochastic {
+  /// Writes an action that is available in this position. By default
+  /// this uses the action's `Debug` format.
+  fn format_action(&self, action : &Self::Action) -> String {
+    format!("{:?}", action)
+  }
+
+  /// Finds the available action written as `text`. By default this
+  /// looks for the action that `format_action` writes the same way.
+  fn parse_action(&self, text : &str) -> Result<Self::Action, NotationError> {
+    let text = text.trim();
+    find_action(self, text, |written| written == text)
+  }
+}
+
+/// Finds the one available action, or chance outcome, whose notation
+/// satisfies `matches`. `text` is only used in errors.
+pub fn find_action<G, F>(game : &G, text : &str, matches : F) -> Result<G::Action, NotationError>
+  where G : Notation, F : Fn(&str) -> bool
+{
+  let mut found = available_actions(game).into_iter().filter(|a| matches(&game.format_action(a)));
+  match (found.next(), found.next()) {
+    (Some(a), None) => Ok(a),
+    (Some(_), Some(_)) => Err(NotationError::Ambiguous(text.into())),
+    (None, _) => Err(NotationError::Illegal(text.into())),
+  }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::agents::Game;
+use crate::checkers::{Checkers, CheckersRules, Action, Mode, FenError, MoveError};
+use crate::pgn::{PgnError, split_games, format_game};
+
+/// A draughts game read from a PDN file
+#[derive(Clone)]
+pub struct PdnGame {
+  /// The tag pairs, like ("White", "Random"), in the order they appeared
+  pub headers : Vec<(String, String)>,
+  /// The position before the first move, which is set by
+  /// the GameType and FEN tags if they are present
+  pub start : Checkers,
+  /// Each jump in a chain of captures is a separate action
+  pub actions : Vec<Action>,
+}
+
+impl PdnGame {
+  pub fn header(&self, name : &str) -> Option<&str> {
+    self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+  }
+
+  /// Plays the moves from the start, and returns the final position
+  pub fn replay(&self) -> Checkers {
+    let mut game = self.start.clone();
+    for a in &self.actions {
+      game.apply_action(a);
+    }
+    game
+  }
+}
+
+/// The reasons that `read_pdn` can reject a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdnError {
+  /// The tags, comments or variations are malformed
+  Syntax(PgnError),
+  BadFen(FenError),
+  /// A GameType tag for rules that aren't supported
+  UnknownGameType(String),
+  /// A move that can't be played, in the numbered game
+  BadMove { game : usize, move_number : i32, error : MoveError },
+}
+
+impl fmt::Display for PdnError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PdnError::Syntax(e) => write!(f, "{}", e),
+      PdnError::BadFen(e) => write!(f, "bad FEN tag: {}", e),
+      PdnError::UnknownGameType(t) => write!(f, "unsupported game type \"{}\"", t),
+      PdnError::BadMove { game, move_number, error } =>
+        write!(f, "game {}, move {}: {}", game, move_number, error),
+    }
+  }
+}
+
+impl std::error::Error for PdnError {}
+
+/// The number of the rules in a PDN GameType tag. Giveaway
+/// games use the same numbers, with a Variant tag.
+fn game_type(rules : CheckersRules) -> Option<i32> {
+  let rules = CheckersRules { giveaway: false, ..rules };
+  [(20, CheckersRules::international()), (21, CheckersRules::american()),
+    (22, CheckersRules::italian()), (23, CheckersRules::pool()),
+    (25, CheckersRules::russian()), (26, CheckersRules::brazilian())]
+    .iter().find(|(_, r)| *r == rules).map(|(n, _)| *n)
+}
+
+/// Reads a GameType tag, which can have more fields after the number
+fn parse_game_type(tag : &str) -> Option<CheckersRules> {
+  match tag.split(',').next()?.trim() {
+    "20" => Some(CheckersRules::international()),
+    "21" => Some(CheckersRules::american()),
+    "22" => Some(CheckersRules::italian()),
+    "23" => Some(CheckersRules::pool()),
+    "25" => Some(CheckersRules::russian()),
+    "26" => Some(CheckersRules::brazilian()),
+    _ => None,
+  }
+}
+
+/// The result of a game: "1-0" if white won, "0-1" if black won,
+/// "1/2-1/2" for a draw, or "*" if it isn't over
+pub fn result(game : &Checkers) -> &'static str {
+  match game.winner() {
+    Some(0) => "1-0",
+    Some(_) => "0-1",
+    None if game.is_draw() => "1/2-1/2",
+    None => "*",
+  }
+}
+
+/// Writes a game in PDN, with each chain of jumps written as one move.
+/// The Result tag always comes from the final position, and the
+/// GameType and FEN tags from `start`.
+pub fn write_pdn(tags : &[(&str, String)], start : &Checkers, actions : &[Action]) -> String {
+  let mut game = start.clone();
+  let mut tokens = vec![];
+  let mut move_number = 1;
+  let mut i = 0;
+  while i < actions.len() {
+    // Collect the rest of the chain of jumps
+    let before = game.clone();
+    let mut end = i;
+    loop {
+      game.apply_action(&actions[end]);
+      end += 1;
+      match game.mode {
+        Mode::ChainCapture(_) if end < actions.len() => (),
+        _ => break,
+      }
+    }
+    let notation = before.move_notation(&actions[i..end]);
+    // Moves are numbered in pairs, starting with the first player
+    if before.active_player == start.rules.first_player {
+      tokens.push(format!("{}. {}", move_number, notation));
+    }
+    else {
+      if i == 0 {
+        tokens.push(format!("{}... {}", move_number, notation));
+      }
+      else {
+        tokens.push(notation);
+      }
+      move_number += 1;
+    }
+    i = end;
+  }
+  let mut tags = tags.to_vec();
+  if let Some(n) = game_type(start.rules) {
+    tags.push(("GameType", n.to_string()));
+  }
+  if start.rules.giveaway {
+    tags.push(("Variant", "Giveaway".into()));
+  }
+  let fen = start.to_fen();
+  if fen != Checkers::new(start.rules).to_fen() {
+    tags.push(("FEN", fen));
+  }
+  format_game(&tags, result(&game), tokens)
+}
+
+/// Reads every game in a PDN file. Games without a GameType tag are
+/// played with `default_rules`. Games are numbered from 1 in errors.
+pub fn read_pdn(text : &str, default_rules : CheckersRules) -> Result<Vec<PdnGame>, PdnError> {
+  let games = split_games(text).map_err(PdnError::Syntax)?;
+  let mut pdn_games = vec![];
+  for (i, text) in games.into_iter().enumerate() {
+    let tag = |name : &str| text.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    let mut rules = match tag("GameType") {
+      Some(t) => parse_game_type(t).ok_or_else(|| PdnError::UnknownGameType(t.into()))?,
+      None => default_rules,
+    };
+    if tag("Variant").is_some_and(|v| v.eq_ignore_ascii_case("giveaway")) {
+      rules = rules.giveaway();
+    }
+    let start = match tag("FEN") {
+      Some(fen) => Checkers::from_fen(rules, fen).map_err(PdnError::BadFen)?,
+      None => Checkers::new(rules),
+    };
+    let mut game = start.clone();
+    let mut actions = vec![];
+    let mut move_number = 1;
+    for m in &text.moves {
+      let chain = game.parse_move(m).map_err(|error| {
+        PdnError::BadMove { game: i + 1, move_number, error }
+      })?;
+      if game.active_player != rules.first_player {
+        move_number += 1;
+      }
+      for a in chain {
+        game.apply_action(&a);
+        actions.push(a);
+      }
+    }
+    pdn_games.push(PdnGame { headers: text.tags, start, actions });
+  }
+  Ok(pdn_games)
+}
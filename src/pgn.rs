@@ -0,0 +1,332 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::agents::Game;
+use crate::chess::{Chess, Action, Variant, FenError, SanError};
+
+/// A chess game read from a PGN file
+#[derive(Clone)]
+pub struct PgnGame {
+  /// The tag pairs, like ("White", "Random"), in the order they appeared
+  pub headers : Vec<(String, String)>,
+  /// The position before the first move, which is set by the FEN
+  /// and Variant tags if they are present
+  pub start : Chess,
+  pub actions : Vec<Action>,
+}
+
+impl PgnGame {
+  pub fn header(&self, name : &str) -> Option<&str> {
+    self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+  }
+
+  /// Plays the moves from the start, and returns the final position
+  pub fn replay(&self) -> Chess {
+    let mut game = self.start.clone();
+    for a in &self.actions {
+      game.apply_action(a);
+    }
+    game
+  }
+}
+
+/// The reasons that `read_pgn` can reject a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgnError {
+  /// A tag pair that isn't of the form `[Name "value"]`
+  BadTag(String),
+  BadFen(FenError),
+  UnknownVariant(String),
+  /// A move that can't be played, in the numbered game
+  BadMove { game : usize, move_number : i32, error : SanError },
+  /// A comment, tag or variation that is never closed
+  Unterminated(char),
+}
+
+impl fmt::Display for PgnError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PgnError::BadTag(tag) => write!(f, "can't read the tag [{}]", tag),
+      PgnError::BadFen(e) => write!(f, "bad FEN tag: {}", e),
+      PgnError::UnknownVariant(v) => write!(f, "unknown variant \"{}\"", v),
+      PgnError::BadMove { game, move_number, error } =>
+        write!(f, "game {}, move {}: {}", game, move_number, error),
+      PgnError::Unterminated(c) => write!(f, "a '{}' is never closed", c),
+    }
+  }
+}
+
+impl std::error::Error for PgnError {}
+
+/// The standard name of a variant in a PGN Variant tag
+fn variant_name(variant : Variant) -> &'static str {
+  match variant {
+    Variant::Standard => "Standard",
+    Variant::Crazyhouse => "Crazyhouse",
+    Variant::Atomic => "Atomic",
+    Variant::ThreeCheck => "Three-check",
+    Variant::KingOfTheHill => "King of the Hill",
+  }
+}
+
+/// Reads a Variant tag, ignoring case, spaces and hyphens
+fn parse_variant(name : &str) -> Option<Variant> {
+  let name : String = name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+  match name.as_str() {
+    // Chess960 games give their starting position in a FEN tag
+    "chess960" | "fischerandom" | "normal" => Some(Variant::Standard),
+    _ => Variant::from_name(&name),
+  }
+}
+
+/// The result of a game in PGN: "1-0", "0-1", "1/2-1/2", or "*" if
+/// it isn't over
+pub fn result(game : &Chess) -> &'static str {
+  match game.winner() {
+    Some(0) => "1-0",
+    Some(_) => "0-1",
+    None if game.is_draw() => "1/2-1/2",
+    None => "*",
+  }
+}
+
+fn escape(value : &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes a game in PGN, with the moves in Standard Algebraic Notation.
+/// The Result tag always comes from the final position, and the FEN
+/// and Variant tags from `start`.
+pub fn write_pgn(tags : &[(&str, String)], start : &Chess, actions : &[Action]) -> String {
+  let mut game = start.clone();
+  let mut tokens = vec![];
+  for (i, a) in actions.iter().enumerate() {
+    // Keep each move number with its move
+    let san = game.san(a);
+    if game.active_player() == 0 {
+      tokens.push(format!("{}. {}", game.fullmove_number(), san));
+    }
+    else if i == 0 {
+      tokens.push(format!("{}... {}", game.fullmove_number(), san));
+    }
+    else {
+      tokens.push(san);
+    }
+    game.apply_action(a);
+  }
+  let mut tags = tags.to_vec();
+  if start.variant() != Variant::Standard {
+    tags.push(("Variant", variant_name(start.variant()).into()));
+  }
+  let fen = start.to_fen();
+  if fen != Chess::new().to_fen() {
+    tags.push(("SetUp", "1".into()));
+    tags.push(("FEN", fen));
+  }
+  format_game(&tags, result(&game), tokens)
+}
+
+/// Lays out a game in PGN or PDN. The Seven Tag Roster comes first, with
+/// "?" for any tags that aren't given, followed by the other tags, and
+/// then the moves and the result, wrapped to fit in 80 columns.
+pub fn format_game(tags : &[(&str, String)], result : &str, mut tokens : Vec<String>) -> String {
+  let roster = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+  let mut text = String::new();
+  for &name in roster.iter() {
+    let value = match tags.iter().find(|(n, _)| *n == name) {
+      _ if name == "Result" => result.to_string(),
+      Some((_, v)) => v.clone(),
+      None if name == "Date" => "????.??.??".into(),
+      None => "?".into(),
+    };
+    text.push_str(&format!("[{} \"{}\"]\n", name, escape(&value)));
+  }
+  for (name, value) in tags.iter().filter(|(n, _)| !roster.contains(n)) {
+    text.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+  }
+  text.push('\n');
+
+  tokens.push(result.to_string());
+  let mut line_length = 0;
+  for t in tokens {
+    if line_length > 0 && line_length + 1 + t.len() >= 80 {
+      text.push('\n');
+      line_length = 0;
+    }
+    else if line_length > 0 {
+      text.push(' ');
+      line_length += 1;
+    }
+    line_length += t.len();
+    text.push_str(&t);
+  }
+  text.push_str("\n\n");
+  text
+}
+
+/// Adds a game to the end of a PGN file, creating it if needed
+pub fn append_pgn(path : &str, pgn : &str) -> io::Result<()> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  file.write_all(pgn.as_bytes())
+}
+
+/// Skips past the character `end`, returning an error if it never comes
+fn skip_to(chars : &mut Peekable<Chars>, end : char) -> Result<(), PgnError> {
+  for c in chars {
+    if c == end {
+      return Ok(());
+    }
+  }
+  Err(PgnError::Unterminated(end))
+}
+
+/// Reads a tag pair, after its opening '['
+fn read_tag(chars : &mut Peekable<Chars>) -> Result<(String, String), PgnError> {
+  let mut tag = String::new();
+  let mut in_quotes = false;
+  loop {
+    match chars.next() {
+      Some('\\') if in_quotes => {
+        tag.push('\\');
+        tag.extend(chars.next());
+      }
+      Some('"') => {
+        in_quotes = !in_quotes;
+        tag.push('"');
+      }
+      Some(']') if !in_quotes => break,
+      Some(c) => tag.push(c),
+      None => return Err(PgnError::Unterminated('[')),
+    }
+  }
+  let bad_tag = || PgnError::BadTag(tag.clone());
+  let trimmed = tag.trim();
+  let name_end = trimmed.find(char::is_whitespace).ok_or_else(bad_tag)?;
+  let name = &trimmed[..name_end];
+  let quoted = trimmed[name_end..].trim();
+  if quoted.len() < 2 || !quoted.starts_with('"') || !quoted.ends_with('"') {
+    return Err(bad_tag());
+  }
+  let mut value = String::new();
+  let mut escaped = quoted[1..quoted.len() - 1].chars();
+  while let Some(c) = escaped.next() {
+    value.push(if c == '\\' { escaped.next().ok_or_else(bad_tag)? } else { c });
+  }
+  Ok((name.to_string(), value))
+}
+
+/// Sets up the starting position from the tags, and plays the moves
+fn make_game(index : usize, headers : Vec<(String, String)>, moves : &[String]) -> Result<PgnGame, PgnError> {
+  let tag = |name : &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+  let variant = match tag("Variant") {
+    Some(name) => parse_variant(name).ok_or_else(|| PgnError::UnknownVariant(name.into()))?,
+    None => Variant::Standard,
+  };
+  let start = match tag("FEN") {
+    Some(fen) => Chess::from_fen(variant, fen).map_err(PgnError::BadFen)?,
+    None => Chess::new().with_variant(variant),
+  };
+  let mut game = start.clone();
+  let mut actions = vec![];
+  for san in moves {
+    let a = game.parse_san(san).map_err(|error| {
+      PgnError::BadMove { game: index, move_number: game.fullmove_number(), error }
+    })?;
+    game.apply_action(&a);
+    actions.push(a);
+  }
+  Ok(PgnGame { headers, start, actions })
+}
+
+/// The tags and moves of one game in a file, before the moves are
+/// played. PDN files for draughts use the same layout as PGN.
+pub struct GameText {
+  pub tags : Vec<(String, String)>,
+  pub moves : Vec<String>,
+}
+
+/// Splits a PGN or PDN file into games. Comments, variations, numeric
+/// annotation glyphs like "$1", and move numbers are skipped.
+pub fn split_games(text : &str) -> Result<Vec<GameText>, PgnError> {
+  let mut games = vec![];
+  let mut tags = vec![];
+  let mut moves : Vec<String> = vec![];
+  let mut chars = text.chars().peekable();
+  let mut line_start = true;
+  while let Some(c) = chars.next() {
+    match c {
+      // A line starting with '%' is ignored
+      '%' if line_start => {
+        while chars.peek().is_some_and(|&c| c != '\n') {
+          chars.next();
+        }
+      }
+      ';' => {
+        while chars.peek().is_some_and(|&c| c != '\n') {
+          chars.next();
+        }
+      }
+      '{' => skip_to(&mut chars, '}')?,
+      '[' => tags.push(read_tag(&mut chars)?),
+      // Variations can nest, and contain comments
+      '(' => {
+        let mut depth = 1;
+        while depth > 0 {
+          match chars.next() {
+            Some('(') => depth += 1,
+            Some(')') => depth -= 1,
+            Some('{') => skip_to(&mut chars, '}')?,
+            Some(_) => (),
+            None => return Err(PgnError::Unterminated('(')),
+          }
+        }
+      }
+      c if c.is_whitespace() => (),
+      c => {
+        let mut token = c.to_string();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || "{}();[$".contains(c) {
+            break;
+          }
+          token.push(c);
+          chars.next();
+        }
+        match token.as_str() {
+          // Draughts results count two points for a win
+          "1-0" | "0-1" | "1/2-1/2" | "2-0" | "0-2" | "1-1" | "*" => {
+            games.push(GameText { tags, moves });
+            tags = vec![];
+            moves = vec![];
+          }
+          // A numeric annotation glyph
+          t if t.starts_with('$') => (),
+          t => {
+            // Move numbers like "12." or "12..." can be attached to the move
+            let rest = t.trim_start_matches(|c : char| c.is_ascii_digit());
+            let m = if rest.starts_with('.') { rest.trim_start_matches('.') } else { t };
+            if !m.is_empty() {
+              moves.push(m.to_string());
+            }
+          }
+        }
+      }
+    }
+    line_start = c == '\n';
+  }
+  // The last game might be missing its result
+  if !tags.is_empty() || !moves.is_empty() {
+    games.push(GameText { tags, moves });
+  }
+  Ok(games)
+}
+
+/// Reads every game in a PGN file. Move suffixes like "!?" are
+/// skipped. Games are numbered from 1 in errors.
+pub fn read_pgn(text : &str) -> Result<Vec<PgnGame>, PgnError> {
+  split_games(text)?.into_iter().enumerate()
+    .map(|(i, game)| make_game(i + 1, game.tags, &game.moves))
+    .collect()
+}
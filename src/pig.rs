@@ -0,0 +1,141 @@
+use crate::agents::{Game, Stochastic};
+use crate::record::Recordable;
+use crate::notation::Notation;
+
+/// The dice game Pig.
+///
+/// On their turn a player repeatedly rolls a die, adding each roll
+/// to a turn total. They can hold at any point to bank the turn total,
+/// but rolling a one loses it and ends the turn. The first player to
+/// bank `target` points wins.
+#[derive(Clone)]
+pub struct Pig {
+  scores : [i32 ; 2],
+  turn_total : i32,
+  active_player : usize,
+  /// The active player chose to roll, and the die hasn't landed yet
+  rolling : bool,
+  target : i32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+  /// Roll the die again
+  Roll,
+  /// Bank the turn total and pass the die
+  Hold,
+  /// The die landed on this number (a chance outcome)
+  Die(i32),
+}
+
+impl Pig {
+
+  pub fn new(target : i32) -> Pig {
+    Pig { scores: [0, 0], turn_total: 0, active_player: 0, rolling: false, target }
+  }
+
+  fn end_turn(&mut self) {
+    self.turn_total = 0;
+    self.active_player = 1 - self.active_player;
+  }
+}
+
+impl Game for Pig {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    if self.rolling || self.winner().is_some() {
+      return;
+    }
+    actions.push(Action::Roll);
+    // You have to roll at least once before holding
+    if self.turn_total > 0 {
+      actions.push(Action::Hold);
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player as i64
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    match *a {
+      Action::Roll => {
+        self.rolling = true;
+      }
+      Action::Hold => {
+        self.scores[self.active_player] += self.turn_total;
+        // The winner keeps the turn, so that the game ends here
+        if self.winner().is_none() {
+          self.end_turn();
+        }
+      }
+      Action::Die(n) => {
+        self.rolling = false;
+        if n == 1 {
+          self.end_turn();
+        }
+        else {
+          self.turn_total += n;
+        }
+      }
+    }
+  }
+
+  /// Banked points relative to the opponent, scaled to lie
+  /// between -1 and 1. A win scores 1 and a loss scores -1.
+  fn player_score(&self, player : i64) -> f64 {
+    let (mine, theirs) = match player {
+      0 => (self.scores[0], self.scores[1]),
+      1 => (self.scores[1], self.scores[0]),
+      _ => panic!("pig is a two-player game"),
+    };
+    match self.winner() {
+      Some(w) if w == player => 1.0,
+      Some(_) => -1.0,
+      None => (mine - theirs) as f64 / self.target as f64,
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    if self.scores[0] >= self.target {
+      Some(0)
+    }
+    else if self.scores[1] >= self.target {
+      Some(1)
+    }
+    else {
+      None
+    }
+  }
+}
+
+impl Stochastic for Pig {
+  fn is_chance_node(&self) -> bool {
+    self.rolling
+  }
+
+  fn chance_outcomes(&self, outcomes : &mut Vec<(Action, f64)>) {
+    if self.rolling {
+      for n in 1..=6 {
+        outcomes.push((Action::Die(n), 1.0 / 6.0));
+      }
+    }
+  }
+}
+
+impl Recordable for Pig {
+  const GAME_TYPE : &'static str = "pig";
+
+  /// The target score, e.g. "100"
+  fn config(&self) -> String {
+    self.target.to_string()
+  }
+
+  fn from_config(config : &str) -> Option<Pig> {
+    config.parse().ok().map(Pig::new)
+  }
+}
+
+/// Actions are written as "Roll", "Hold" and "Die(3)"
+impl Notation for Pig {}
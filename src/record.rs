@@ -0,0 +1,467 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::agents::{Game, Stochastic, available_actions};
+use crate::contest::GameRecord;
+
+/// A game that can be set up again from a short description, so that
+/// records of it can be replayed without knowing its type in advance
+pub trait Recordable : Stochastic {
+  /// The name of the game in records, e.g. "checkers"
+  const GAME_TYPE : &'static str;
+
+  /// The settings and starting position, e.g. the rules and the FEN
+  fn config(&self) -> String;
+
+  /// Sets up the game described by `config`, or returns None if it
+  /// can't be read. Games whose config is only their settings can
+  /// only be recorded from the position that `new` starts them in.
+  fn from_config(config : &str) -> Option<Self>;
+}
+
+/// The position of `action` among the available actions at this point of the game.
+/// Records store actions this way, so that any game can be recorded.
+pub fn action_index<G : Stochastic>(game : &G, action : &G::Action) -> Option<usize> {
+  available_actions(game).iter().position(|a| a == action)
+}
+
+/// The action with this index among the available actions
+pub fn action_at<G : Stochastic>(game : &G, index : usize) -> Option<G::Action> {
+  available_actions(game).into_iter().nth(index)
+}
+
+/// The reasons that a record can't be read or replayed
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+  /// The text isn't valid JSON, or isn't a record
+  Syntax(String),
+  MissingField(&'static str),
+  /// The record is of a different game to the one replaying it
+  WrongGame { expected : &'static str, found : String },
+  BadConfig(String),
+  /// An action index that is out of range, at the numbered step
+  BadAction { step : usize, index : usize },
+}
+
+impl fmt::Display for RecordError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RecordError::Syntax(e) => write!(f, "invalid record: {}", e),
+      RecordError::MissingField(name) => write!(f, "the record has no \"{}\" field", name),
+      RecordError::WrongGame { expected, found } =>
+        write!(f, "expected a record of {}, but found {}", expected, found),
+      RecordError::BadConfig(config) => write!(f, "can't set up the game \"{}\"", config),
+      RecordError::BadAction { step, index } =>
+        write!(f, "step {}: there is no action {}", step, index),
+    }
+  }
+}
+
+impl std::error::Error for RecordError {}
+
+/// A game-agnostic record of one game, which is stored as a line of JSON:
+///
+/// `{"game":"pig","config":"100","agents":["Rollout (600 iterations, depth 10)","Random"],
+///   "seed":1234,"actions":[0,3,1,...],"result":[1,0]}`
+///
+/// Each action is stored as its index in `possible_actions`, or in
+/// `chance_outcomes` at a chance node, so records depend on the order
+/// that a game lists its actions in.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Record {
+  #[cfg_attr(feature = "serde", serde(rename = "game"))]
+  pub game_type : String,
+  pub config : String,
+  /// The agents' names, in player order
+  pub agents : Vec<String>,
+  /// The seed of the random number generator that the agents shared
+  pub seed : u64,
+  pub actions : Vec<usize>,
+  /// The payoff of each player at the end
+  pub result : Vec<f64>,
+}
+
+impl Record {
+
+  /// Records a game from a contest
+  pub fn from_game<G : Recordable>(record : &GameRecord<G>) -> Record {
+    let mut game = record.start.clone();
+    let actions = record.actions.iter().map(|a| {
+      let index = action_index(&game, a).expect("recorded action wasn't possible");
+      game.apply_action(a);
+      index
+    }).collect();
+    Record {
+      game_type: G::GAME_TYPE.into(),
+      config: record.start.config(),
+      agents: record.agents.clone(),
+      seed: record.seed,
+      actions,
+      result: record.end.payoffs(),
+    }
+  }
+
+  /// Writes the record as one line of JSON, without the newline
+  pub fn to_json(&self) -> String {
+    let agents : Vec<String> = self.agents.iter().map(|a| quote(a)).collect();
+    let actions : Vec<String> = self.actions.iter().map(|a| a.to_string()).collect();
+    let result : Vec<String> = self.result.iter().map(|r| r.to_string()).collect();
+    format!("{{\"game\":{},\"config\":{},\"agents\":[{}],\"seed\":{},\"actions\":[{}],\"result\":[{}]}}",
+      quote(&self.game_type), quote(&self.config), agents.join(","), self.seed,
+      actions.join(","), result.join(","))
+  }
+
+  /// Reads a record written by `to_json`. Other fields are ignored.
+  pub fn from_json(text : &str) -> Result<Record, RecordError> {
+    let mut chars = text.chars().peekable();
+    let json = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+      return Err(RecordError::Syntax("text after the end of the record".into()));
+    }
+    let fields = match json {
+      Json::Object(fields) => fields,
+      _ => return Err(RecordError::Syntax("a record should be an object".into())),
+    };
+    let field = |name : &'static str| {
+      fields.iter().find(|(n, _)| n == name).map(|(_, v)| v).ok_or(RecordError::MissingField(name))
+    };
+    let wrong_type = |name : &str| RecordError::Syntax(format!("the \"{}\" field has the wrong type", name));
+    let string = |name : &'static str| match field(name)? {
+      Json::String(s) => Ok(s.clone()),
+      _ => Err(wrong_type(name)),
+    };
+    let numbers = |name : &'static str| match field(name)? {
+      Json::Array(items) => items.iter().map(|item| match item {
+        Json::Number(n) => Ok(n.as_str()),
+        _ => Err(wrong_type(name)),
+      }).collect::<Result<Vec<&str>, _>>(),
+      _ => Err(wrong_type(name)),
+    };
+    let agents = match field("agents")? {
+      Json::Array(items) => items.iter().map(|item| match item {
+        Json::String(s) => Ok(s.clone()),
+        _ => Err(wrong_type("agents")),
+      }).collect::<Result<Vec<String>, _>>()?,
+      _ => return Err(wrong_type("agents")),
+    };
+    let seed = match field("seed")? {
+      Json::Number(n) => n.parse().map_err(|_| wrong_type("seed"))?,
+      _ => return Err(wrong_type("seed")),
+    };
+    Ok(Record {
+      game_type: string("game")?,
+      config: string("config")?,
+      agents,
+      seed,
+      actions: numbers("actions")?.into_iter()
+        .map(|n| n.parse().map_err(|_| wrong_type("actions")))
+        .collect::<Result<_, _>>()?,
+      result: numbers("result")?.into_iter()
+        .map(|n| n.parse().map_err(|_| wrong_type("result")))
+        .collect::<Result<_, _>>()?,
+    })
+  }
+}
+
+/// Reads a file of records, one per line. Blank lines are skipped.
+pub fn read_records(text : &str) -> Result<Vec<Record>, RecordError> {
+  text.lines().filter(|l| !l.trim().is_empty()).map(Record::from_json).collect()
+}
+
+/// Adds records to the end of a file, creating it if needed
+pub fn append_records(path : &str, records : &[Record]) -> io::Result<()> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  for r in records {
+    writeln!(file, "{}", r.to_json())?;
+  }
+  Ok(())
+}
+
+/// Steps through a recorded game. Each step forwards applies the next
+/// action, and each step back plays the game again from the start.
+pub struct Replay<G : Game> {
+  start : G,
+  actions : Vec<G::Action>,
+  game : G,
+  position : usize,
+}
+
+impl <G : Recordable> Replay<G> {
+
+  /// Sets up the record's game, and checks that every action in it is possible
+  pub fn new(record : &Record) -> Result<Replay<G>, RecordError> {
+    if record.game_type != G::GAME_TYPE {
+      return Err(RecordError::WrongGame { expected: G::GAME_TYPE, found: record.game_type.clone() });
+    }
+    let start = G::from_config(&record.config)
+      .ok_or_else(|| RecordError::BadConfig(record.config.clone()))?;
+    let mut game = start.clone();
+    let mut actions = vec![];
+    for (step, &index) in record.actions.iter().enumerate() {
+      let a = action_at(&game, index).ok_or(RecordError::BadAction { step: step + 1, index })?;
+      game.apply_action(&a);
+      actions.push(a);
+    }
+    Ok(Replay { game: start.clone(), start, actions, position: 0 })
+  }
+}
+
+impl <G : Game> Replay<G> {
+
+  /// The position after the actions played so far
+  pub fn game(&self) -> &G {
+    &self.game
+  }
+
+  /// The number of actions played so far
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  pub fn is_at_end(&self) -> bool {
+    self.position == self.actions.len()
+  }
+
+  /// Plays the next action, and returns it, or None at the end
+  pub fn forward(&mut self) -> Option<&G::Action> {
+    let a = self.actions.get(self.position)?;
+    self.game.apply_action(a);
+    self.position += 1;
+    Some(a)
+  }
+
+  /// Takes back the last action. Returns false at the start.
+  pub fn back(&mut self) -> bool {
+    if self.position == 0 {
+      return false;
+    }
+    self.seek(self.position - 1);
+    true
+  }
+
+  /// Moves to the position after `position` actions, or the end
+  pub fn seek(&mut self, position : usize) {
+    let position = position.min(self.actions.len());
+    if position < self.position {
+      self.game = self.start.clone();
+      self.position = 0;
+    }
+    while self.position < position {
+      self.forward();
+    }
+  }
+}
+
+/// Writes a JSON string, with quotes
+fn quote(s : &str) -> String {
+  let mut quoted = String::from("\"");
+  for c in s.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\t' => quoted.push_str("\\t"),
+      c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// A JSON value. Numbers keep their text, so that large seeds
+/// aren't rounded.
+enum Json {
+  /// null, true or false, which records don't use
+  Keyword,
+  Number(String),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+fn skip_whitespace(chars : &mut Peekable<Chars>) {
+  while chars.peek().is_some_and(|c| c.is_whitespace()) {
+    chars.next();
+  }
+}
+
+/// Reads the characters of a keyword like "true", after its first letter
+fn expect_word(chars : &mut Peekable<Chars>, rest : &str) -> Result<(), RecordError> {
+  for expected in rest.chars() {
+    if chars.next() != Some(expected) {
+      return Err(RecordError::Syntax(format!("expected '{}'", expected)));
+    }
+  }
+  Ok(())
+}
+
+fn parse_value(chars : &mut Peekable<Chars>) -> Result<Json, RecordError> {
+  skip_whitespace(chars);
+  match chars.next() {
+    Some('n') => expect_word(chars, "ull").map(|_| Json::Keyword),
+    Some('t') => expect_word(chars, "rue").map(|_| Json::Keyword),
+    Some('f') => expect_word(chars, "alse").map(|_| Json::Keyword),
+    Some('"') => parse_string(chars).map(Json::String),
+    Some('[') => {
+      let mut items = vec![];
+      skip_whitespace(chars);
+      if chars.peek() == Some(&']') {
+        chars.next();
+        return Ok(Json::Array(items));
+      }
+      loop {
+        items.push(parse_value(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+          Some(',') => (),
+          Some(']') => return Ok(Json::Array(items)),
+          _ => return Err(RecordError::Syntax("expected ',' or ']'".into())),
+        }
+      }
+    }
+    Some('{') => {
+      let mut fields = vec![];
+      skip_whitespace(chars);
+      if chars.peek() == Some(&'}') {
+        chars.next();
+        return Ok(Json::Object(fields));
+      }
+      loop {
+        skip_whitespace(chars);
+        if chars.next() != Some('"') {
+          return Err(RecordError::Syntax("expected a field name".into()));
+        }
+        let name = parse_string(chars)?;
+        skip_whitespace(chars);
+        if chars.next() != Some(':') {
+          return Err(RecordError::Syntax("expected ':'".into()));
+        }
+        fields.push((name, parse_value(chars)?));
+        skip_whitespace(chars);
+        match chars.next() {
+          Some(',') => (),
+          Some('}') => return Ok(Json::Object(fields)),
+          _ => return Err(RecordError::Syntax("expected ',' or '}'".into())),
+        }
+      }
+    }
+    Some(c) if c == '-' || c.is_ascii_digit() => {
+      let mut number = c.to_string();
+      while let Some(&c) = chars.peek() {
+        if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+          break;
+        }
+        number.push(c);
+        chars.next();
+      }
+      Ok(Json::Number(number))
+    }
+    Some(c) => Err(RecordError::Syntax(format!("unexpected '{}'", c))),
+    None => Err(RecordError::Syntax("unexpected end of the record".into())),
+  }
+}
+
+/// Reads a JSON string, after its opening quote
+fn parse_string(chars : &mut Peekable<Chars>) -> Result<String, RecordError> {
+  let mut s = String::new();
+  loop {
+    match chars.next() {
+      Some('"') => return Ok(s),
+      Some('\\') => match chars.next() {
+        Some('n') => s.push('\n'),
+        Some('t') => s.push('\t'),
+        Some('r') => s.push('\r'),
+        Some('b') => s.push('\u{8}'),
+        Some('f') => s.push('\u{c}'),
+        Some('u') => {
+          let hex : String = chars.by_ref().take(4).collect();
+          let c = u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32)
+            .ok_or_else(|| RecordError::Syntax(format!("bad escape \\u{}", hex)))?;
+          s.push(c);
+        }
+        Some(c) => s.push(c),
+        None => break,
+      },
+      Some(c) => s.push(c),
+      None => break,
+    }
+  }
+  Err(RecordError::Syntax("a string is never closed".into()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tic_tac_toe::TicTacToe;
+
+  fn tic_tac_toe_record(actions : Vec<usize>) -> Record {
+    Record {
+      game_type: "tictactoe".into(), config: "tictactoe".into(),
+      agents: vec!["Random".into(), "Rollout".into()], seed: 1, actions, result: vec![1.0, -1.0],
+    }
+  }
+
+  #[test]
+  fn json_round_trip() {
+    let record = Record {
+      game_type: "chess".into(),
+      config: "standard rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".into(),
+      agents: vec!["Quoted \"agent\" \\ here".into(), "Tab\tnewline\ncontrol\u{1}".into()],
+      // Above 2^53, so it would be rounded as a float
+      seed: u64::MAX - 1,
+      actions: vec![0, 12, 3],
+      result: vec![0.5, -0.5],
+    };
+    let json = record.to_json();
+    assert!(!json.contains('\n'));
+    assert!(json.contains("\"seed\":18446744073709551614"));
+    assert_eq!(Record::from_json(&json), Ok(record));
+  }
+
+  #[test]
+  fn json_escapes_and_other_fields() {
+    let json = r#" { "game" : "tictactoe", "config": "tictactoe", "extra": [null, true, {"a": false}],
+      "agents": ["A\/\"", "b"], "seed": 7, "actions": [], "result": [1, -1] } "#;
+    let record = Record::from_json(json).unwrap();
+    assert_eq!(record.agents, ["A/\"", "b"]);
+    assert_eq!(record.seed, 7);
+    assert!(Record::from_json(r#"{"game": "tictactoe""#).is_err());
+    assert_eq!(Record::from_json(r#"{"game": "tictactoe"}"#).err(), Some(RecordError::MissingField("agents")));
+  }
+
+  #[test]
+  fn replay_checks_the_game_and_actions() {
+    let replay = Replay::<TicTacToe>::new(&tic_tac_toe_record(vec![0, 0, 0])).unwrap();
+    assert_eq!(replay.actions.len(), 3);
+    let chess = Record { game_type: "chess".into(), ..tic_tac_toe_record(vec![]) };
+    assert_eq!(Replay::<TicTacToe>::new(&chess).err(),
+      Some(RecordError::WrongGame { expected: "tictactoe", found: "chess".into() }));
+    // There are 9 places for the first mark, and then 8
+    assert_eq!(Replay::<TicTacToe>::new(&tic_tac_toe_record(vec![8, 8])).err(),
+      Some(RecordError::BadAction { step: 2, index: 8 }));
+    let bad_config = Record { config: "nonsense".into(), ..tic_tac_toe_record(vec![]) };
+    assert_eq!(Replay::<TicTacToe>::new(&bad_config).err(), Some(RecordError::BadConfig("nonsense".into())));
+  }
+
+  #[test]
+  fn replay_steps_back_and_forth() {
+    let mut replay = Replay::<TicTacToe>::new(&tic_tac_toe_record(vec![0, 0, 0])).unwrap();
+    while replay.forward().is_some() {}
+    assert!(replay.is_at_end());
+    assert_eq!(replay.position(), 3);
+    assert!(replay.back());
+    assert_eq!(replay.position(), 2);
+    while replay.back() {}
+    assert_eq!(replay.position(), 0);
+    assert!(!replay.back());
+  }
+}
@@ -1,50 +1,387 @@
-
+use std::fmt;
 use piston_window::*;
 use rand::{SeedableRng, rngs::StdRng};
 
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
 use crate::utils::*;
 use crate::agents::*;
+use crate::record::Recordable;
+use crate::notation::{Notation, NotationError, find_action};
 
 /// The contents of a tile
-type Tile = Option<Player>;
+type Tile = Option<Mark>;
 
-/// The two competing players
+/// The two kinds of mark. Normally the first player places crosses
+/// and the second noughts, but some variants let either player
+/// place either mark.
 #[derive(Copy, Clone, PartialEq, Debug)]
-enum Player {
-  WhitePlayer,
-  BlackPlayer,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Mark {
+  Cross,
+  Nought,
 }
 
-use Player::*;
+use Mark::*;
 
 type Board = crate::utils::Board<Tile>;
 
-#[derive(Debug, Copy, Clone)]
-pub struct Action {
-  pos : Pos,
-  player : Player,
+/// Changes to the goal, or to which marks the players may place
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+  /// The first to make a line of k wins
+  Standard,
+  /// The first to make a line of k loses
+  Misere,
+  /// Either player may place either mark, and whoever
+  /// completes a line of k (of either mark) wins
+  Wild,
+  /// Either player may place either mark. The first player (Order) wins
+  /// if a line of k of either mark appears, and the second player (Chaos)
+  /// wins if the board fills up without one.
+  OrderAndChaos,
+}
+
+/// The rules of an m,n,k game: players take turns placing marks
+/// on a `width` by `height` board, trying to make `k` in a row
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MnkRules {
+  pub width : i32,
+  pub height : i32,
+  pub k : i32,
+  /// Lines longer than k don't count (the Gomoku "exactly five" rule)
+  pub exact : bool,
+  pub variant : Variant,
+  /// Black (crosses) may not make an overline, two fours or two open threes
+  /// at once, unless the move also makes exactly k. White (noughts) wins
+  /// with k or more.
+  pub renju : bool,
+  /// Start with the Swap2 opening, where the players only pick
+  /// their colours after the first few stones are down
+  pub swap2 : bool,
+}
+
+impl MnkRules {
+  pub fn new(width : i32, height : i32, k : i32) -> MnkRules {
+    MnkRules {
+      width, height, k, exact: false, variant: Variant::Standard, renju: false, swap2: false,
+    }
+  }
+
+  pub fn tic_tac_toe() -> MnkRules {
+    MnkRules::new(3, 3, 3)
+  }
+
+  /// Five in a row on a 15x15 board, where six or more doesn't count
+  pub fn gomoku() -> MnkRules {
+    MnkRules { exact: true, ..MnkRules::new(15, 15, 5) }
+  }
+
+  /// Gomoku on a 15x15 board with the Renju restrictions on black
+  pub fn renju() -> MnkRules {
+    MnkRules { renju: true, ..MnkRules::new(15, 15, 5) }
+  }
+
+  pub fn misere() -> MnkRules {
+    MnkRules { variant: Variant::Misere, ..MnkRules::tic_tac_toe() }
+  }
+
+  pub fn wild() -> MnkRules {
+    MnkRules { variant: Variant::Wild, ..MnkRules::tic_tac_toe() }
+  }
+
+  /// Five in a row on a 6x6 board
+  pub fn order_and_chaos() -> MnkRules {
+    MnkRules { variant: Variant::OrderAndChaos, ..MnkRules::new(6, 6, 5) }
+  }
+
+  /// Looks up a preset by name, e.g. "gomoku"
+  pub fn from_name(name : &str) -> Option<MnkRules> {
+    match name {
+      "tictactoe" => Some(MnkRules::tic_tac_toe()),
+      "gomoku" => Some(MnkRules::gomoku()),
+      "renju" => Some(MnkRules::renju()),
+      "misere" => Some(MnkRules::misere()),
+      "wild" => Some(MnkRules::wild()),
+      "orderchaos" => Some(MnkRules::order_and_chaos()),
+      _ => None,
+    }
+  }
+
+  /// The name of the preset that these rules match, ignoring swap2
+  pub fn name(&self) -> Option<&'static str> {
+    let rules = MnkRules { swap2: false, ..*self };
+    ["tictactoe", "gomoku", "renju", "misere", "wild", "orderchaos"].iter()
+      .find(|&&name| MnkRules::from_name(name) == Some(rules))
+      .cloned()
+  }
+
+  /// Returns true if the player may choose which mark to place
+  fn any_mark(&self) -> bool {
+    match self.variant {
+      Variant::Wild | Variant::OrderAndChaos => true,
+      Variant::Standard | Variant::Misere => false,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Action {
+  Place { pos : Pos, mark : Mark },
+  /// Swap2: the player takes this mark for the rest of
+  /// the game, and the opponent takes the other one
+  ChooseMark(Mark),
+  /// Swap2: rather than choosing a mark, place another cross and
+  /// nought and let the opponent choose
+  PlaceTwoMore,
+}
+
+/// The stages of the Swap2 opening
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Opening {
+  /// The first player places two crosses and a nought
+  PlaceThree,
+  /// The second player chooses a mark, or to place two more
+  Choose,
+  /// The second player places a cross and a nought
+  PlaceTwo,
+  /// The first player chooses a mark
+  FinalChoice,
+  /// The marks are chosen, and play continues as normal
+  Finished,
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TicTacToe {
   board : Board,
-  active_player : Player,
-  length_to_win : i32,
-  victory : Option<Player>,
+  rules : MnkRules,
+  active_player : i64,
+  victory : Option<i64>,
+  /// The mark that each player places
+  marks : [Mark ; 2],
+  opening : Opening,
 }
 
+/// The directions that a line can run in
+const DIRECTIONS : &[Pos] = &[ Pos { x: 1, y : 0}, Pos { x: 0, y : 1}, Pos { x: 1, y : 1}, Pos { x: 1, y : -1} ];
+
 impl TicTacToe {
 
-  pub fn new(size : i32, length_to_win : i32) -> TicTacToe {
-    let board = Board::new(None, size);
-    TicTacToe { board, active_player : WhitePlayer, length_to_win, victory: None }
+  pub fn new(rules : MnkRules) -> TicTacToe {
+    let board = Board::rectangle(None, rules.width, rules.height);
+    let opening = if rules.swap2 { Opening::PlaceThree } else { Opening::Finished };
+    TicTacToe { board, rules, active_player : 0, victory: None, marks: [Cross, Nought], opening }
+  }
+
+  /// Reads a position drawn by `Display`. A finished game needs
+  /// its result line, as the winner isn't worked out again.
+  pub fn from_ascii(rules : MnkRules, text : &str) -> Result<TicTacToe, AsciiError> {
+    let mut game = TicTacToe::new(rules);
+    let (board, lines) = read_ascii(text, rules.width, rules.height)?;
+    for y in 0..rules.height {
+      for x in 0..rules.width {
+        let tile = match board.get(Pos {x, y}) {
+          '.' | ' ' => None,
+          'X' => Some(Cross),
+          'O' => Some(Nought),
+          c => return Err(AsciiError::UnknownTile(c)),
+        };
+        game.board.set(Pos {x, y}, tile);
+      }
+    }
+    // The status line says if the Swap2 opening is still going on
+    game.opening = Opening::Finished;
+    let mut side = None;
+    for line in lines.iter() {
+      let bad_line = || AsciiError::BadLine(line.clone());
+      let mut parts = line.split(", ");
+      let mut words = parts.next().unwrap_or("").split(' ');
+      let player = match (words.next(), words.next().and_then(|n| n.parse::<i64>().ok())) {
+        (Some("Player"), Some(n)) if n == 1 || n == 2 => n - 1,
+        (Some("Drawn"), None) => continue,
+        _ => return Err(bad_line()),
+      };
+      match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("won"), None, _, _) => game.victory = Some(player),
+        (Some(mark), Some("to"), Some("move"), None) => {
+          let mark = match mark {
+            "(X)" => Cross,
+            "(O)" => Nought,
+            _ => return Err(bad_line()),
+          };
+          game.marks[player as usize] = mark;
+          game.marks[1 - player as usize] = if mark == Cross { Nought } else { Cross };
+          side = Some(player);
+        }
+        _ => return Err(bad_line()),
+      }
+      for part in parts {
+        game.opening = match part {
+          "Swap2: placing three" => Opening::PlaceThree,
+          "Swap2: choosing" => Opening::Choose,
+          "Swap2: placing two" => Opening::PlaceTwo,
+          "Swap2: final choice" => Opening::FinalChoice,
+          _ => return Err(bad_line()),
+        };
+      }
+    }
+    game.active_player = side.ok_or(AsciiError::NoSideToMove)?;
+    Ok(game)
+  }
+
+  /// The mark that the active player places, unless the variant lets them choose
+  fn own_mark(&self) -> Mark {
+    self.marks[self.active_player as usize]
+  }
+
+  fn count(&self, mark : Mark) -> usize {
+    self.board.iter().filter(|&&t| t == Some(mark)).count()
+  }
+
+  /// The player who places crosses moves next once the marks are chosen,
+  /// unless there are more crosses on the board
+  fn next_after_opening(&mut self) {
+    let next_mark = if self.count(Cross) > self.count(Nought) { Nought } else { Cross };
+    self.active_player = if self.marks[0] == next_mark { 0 } else { 1 };
+    self.opening = Opening::Finished;
+  }
+
+  /// The marks that can still be placed in the current stage of the opening
+  fn opening_marks(&self) -> Vec<Mark> {
+    let (crosses, noughts) = match self.opening {
+      Opening::PlaceThree => (2, 1),
+      Opening::PlaceTwo => (3, 2),
+      _ => return vec![],
+    };
+    let mut marks = vec![];
+    if self.count(Cross) < crosses {
+      marks.push(Cross);
+    }
+    if self.count(Nought) < noughts {
+      marks.push(Nought);
+    }
+    marks
+  }
+
+  /// The length of the line of `mark` through `pos` in direction `d`,
+  /// counting `pos` itself whatever it holds
+  fn run_length(&self, pos : Pos, d : Pos, mark : Mark) -> i32 {
+    let mut count = 1;
+    for &d in &[d, -d] {
+      let mut p = pos + d;
+      while self.board.try_get(p) == Some(Some(mark)) {
+        p += d;
+        count += 1;
+      }
+    }
+    count
+  }
+
+  /// The fours through the cross at `pos` in direction `d`. A four is a set of
+  /// four crosses that one more cross would turn into exactly five. Each is
+  /// returned with the number of tiles that complete it, so a straight four
+  /// (open at both ends) has two.
+  fn fours(&mut self, pos : Pos, d : Pos) -> Vec<(Vec<i32>, i32)> {
+    let mut fours : Vec<(Vec<i32>, i32)> = vec![];
+    for i in -4..=4 {
+      let q = Pos { x: pos.x + i * d.x, y: pos.y + i * d.y };
+      if i == 0 || self.board.try_get(q) != Some(None) {
+        continue;
+      }
+      self.board.set(q, Some(Cross));
+      if self.run_length(pos, d, Cross) == 5 {
+        // The offsets of the four crosses, which identify the four
+        let mut start = 0;
+        while self.board.try_get(Pos { x: pos.x + (start - 1) * d.x, y: pos.y + (start - 1) * d.y }) == Some(Some(Cross)) {
+          start -= 1;
+        }
+        let stones : Vec<i32> = (start..start + 5).filter(|&j| j != i).collect();
+        match fours.iter_mut().find(|f| f.0 == stones) {
+          Some(f) => f.1 += 1,
+          None => fours.push((stones, 1)),
+        }
+      }
+      self.board.set(q, None);
+    }
+    fours
+  }
+
+  /// Returns true if the cross at `pos` is part of an open three in direction
+  /// `d`: one more cross on the line would make a straight four. This doesn't
+  /// check whether that extra cross would itself be forbidden.
+  fn has_three(&mut self, pos : Pos, d : Pos) -> bool {
+    for i in -4..=4 {
+      let q = Pos { x: pos.x + i * d.x, y: pos.y + i * d.y };
+      if i == 0 || self.board.try_get(q) != Some(None) {
+        continue;
+      }
+      self.board.set(q, Some(Cross));
+      let straight_four = self.run_length(pos, d, Cross) < 6
+        && self.fours(pos, d).iter().any(|f| f.1 >= 2);
+      self.board.set(q, None);
+      if straight_four {
+        return true;
+      }
+    }
+    false
   }
 
-  fn active_player_swap(&mut self) {
-    match self.active_player {
-      Player::WhitePlayer => self.active_player = Player::BlackPlayer,
-      Player::BlackPlayer => self.active_player = Player::WhitePlayer,
+  /// Returns true if Renju forbids a cross at the empty tile `pos`
+  fn is_forbidden(&mut self, pos : Pos) -> bool {
+    self.board.set(pos, Some(Cross));
+    let runs : Vec<i32> = DIRECTIONS.iter().map(|&d| self.run_length(pos, d, Cross)).collect();
+    let forbidden = if runs.contains(&5) {
+      // Making five wins, whatever else the move does
+      false
     }
+    else if runs.iter().any(|&r| r > 5) {
+      true
+    }
+    else {
+      let mut fours = 0;
+      let mut threes = 0;
+      for &d in DIRECTIONS {
+        let n = self.fours(pos, d).len();
+        fours += n;
+        if n == 0 && self.has_three(pos, d) {
+          threes += 1;
+        }
+      }
+      fours >= 2 || threes >= 2
+    };
+    self.board.set(pos, None);
+    forbidden
+  }
+
+  pub fn is_full(&self) -> bool {
+    self.board.iter().all(|t| t.is_some())
+  }
+
+  pub fn mark_at(&self, pos : Pos) -> Option<Mark> {
+    self.board.get(pos)
+  }
+
+  /// Places a mark regardless of whose turn it is, for games built out of
+  /// tic tac toe boards. Returns true if it completes a line of k.
+  pub fn place(&mut self, pos : Pos, mark : Mark) -> bool {
+    self.board.set(pos, Some(mark));
+    self.makes_line(pos, mark)
+  }
+
+  /// Returns true if the mark placed at `pos` completes a line of k
+  fn makes_line(&self, pos : Pos, mark : Mark) -> bool {
+    let k = self.rules.k;
+    let exact = self.rules.exact || (self.rules.renju && mark == Cross);
+    DIRECTIONS.iter().any(|&d| {
+      let count = self.run_length(pos, d, mark);
+      count == k || (count > k && !exact)
+    })
   }
 }
 
@@ -55,134 +392,366 @@ impl Game for TicTacToe {
     if self.victory.is_some() {
       return;
     }
-    for y in 0..self.board.size {
-      for x in 0..self.board.size {
+    let marks = match self.opening {
+      Opening::Choose => {
+        actions.push(Action::ChooseMark(Cross));
+        actions.push(Action::ChooseMark(Nought));
+        actions.push(Action::PlaceTwoMore);
+        return;
+      }
+      Opening::FinalChoice => {
+        actions.push(Action::ChooseMark(Cross));
+        actions.push(Action::ChooseMark(Nought));
+        return;
+      }
+      Opening::PlaceThree | Opening::PlaceTwo => self.opening_marks(),
+      Opening::Finished if self.rules.any_mark() => vec![Cross, Nought],
+      Opening::Finished => vec![self.own_mark()],
+    };
+    // The Renju restrictions are only checked once the opening is over
+    let check_renju = self.rules.renju && self.opening == Opening::Finished;
+    let mut game = self.clone();
+    for y in 0..self.board.height {
+      for x in 0..self.board.width {
         let pos = Pos{x, y};
         if self.board.get(pos) == None {
-          actions.push(Action { pos, player: self.active_player });
+          for &mark in marks.iter() {
+            if mark == Cross && check_renju && game.is_forbidden(pos) {
+              continue;
+            }
+            actions.push(Action::Place { pos, mark });
+          }
         }
       }
     }
   }
 
   fn active_player(&self) -> i64 {
-    match self.active_player {
-      WhitePlayer => 0, BlackPlayer => 1
-    }
+    self.active_player
   }
 
   fn apply_action(&mut self, a : &Action) {
-    self.board.set(a.pos, Some(a.player));
-    // victory check
-    let dirs = &[ Pos { x: 1, y : 0}, Pos { x: 0, y : 1}, Pos { x: 1, y : 1}, Pos { x: 1, y : -1} ];
-    for &d in dirs {
-      let forwards_and_back = &[ d, -d ];
-      let mut count = 1;
-      for &d in forwards_and_back {
-        let mut p = a.pos + d;
-        while self.board.try_get(p) == Some(Some(a.player)) {
-          p += d;
-          count += 1;
-        }
+    let player = self.active_player;
+    let other = 1 - player;
+    let (pos, mark) = match *a {
+      Action::Place { pos, mark } => (pos, mark),
+      Action::ChooseMark(mark) => {
+        self.marks[player as usize] = mark;
+        self.marks[other as usize] = if mark == Cross { Nought } else { Cross };
+        self.next_after_opening();
+        return;
+      }
+      Action::PlaceTwoMore => {
+        self.opening = Opening::PlaceTwo;
+        return;
       }
-      if count >= self.length_to_win {
-        self.victory = Some(a.player);
-        break;
+    };
+    self.board.set(pos, Some(mark));
+    // The opening passes to the other player once all its stones are placed
+    if self.opening != Opening::Finished {
+      if self.opening_marks().is_empty() {
+        self.opening = match self.opening {
+          Opening::PlaceThree => Opening::Choose,
+          _ => Opening::FinalChoice,
+        };
+        self.active_player = other;
       }
+      return;
+    }
+    // victory check
+    if self.makes_line(pos, mark) {
+      self.victory = Some(match self.rules.variant {
+        Variant::Standard | Variant::Wild => player,
+        Variant::Misere => other,
+        Variant::OrderAndChaos => 0,
+      });
+    }
+    else if self.is_full() && self.rules.variant == Variant::OrderAndChaos {
+      self.victory = Some(1);
     }
     // Swap active player
-    self.active_player_swap();
+    self.active_player = other;
   }
 
   fn player_score(&self, player : i64) -> f64 {
-    match (self.victory, player) {
-      (Some(WhitePlayer), 0) => 0.0,
-      (Some(WhitePlayer), 1) => -1.0,
-      (Some(BlackPlayer), 1) => 0.0,
-      (Some(BlackPlayer), 0) => -1.0,
-      _ => 0.0,
+    match self.victory {
+      Some(p) if p == player => 0.0,
+      Some(_) => -1.0,
+      None => 0.0,
     }
   }
 
   fn winner(&self) -> Option<i64> {
-    self.victory.map(|p| match p { WhitePlayer => 0, BlackPlayer => 1 })
+    self.victory
+  }
+
+  fn is_draw(&self) -> bool {
+    self.victory.is_none() && self.is_full()
+  }
+}
+
+impl Stochastic for TicTacToe {}
+
+impl Recordable for TicTacToe {
+  const GAME_TYPE : &'static str = "tictactoe";
+
+  /// The preset, or the width, height and line length, followed by
+  /// "swap2" if the game starts with the Swap2 opening, e.g. "gomoku swap2"
+  /// or "7 6 4". Other rules can't be set up again.
+  fn config(&self) -> String {
+    let rules = self.rules;
+    let mut config = match rules.name() {
+      Some(name) => name.to_string(),
+      None if MnkRules { swap2: false, ..rules } == MnkRules::new(rules.width, rules.height, rules.k) => {
+        format!("{} {} {}", rules.width, rules.height, rules.k)
+      }
+      None => "custom".to_string(),
+    };
+    if rules.swap2 {
+      config.push_str(" swap2");
+    }
+    config
+  }
+
+  fn from_config(config : &str) -> Option<TicTacToe> {
+    let mut words : Vec<&str> = config.split_whitespace().collect();
+    let swap2 = words.last() == Some(&"swap2");
+    if swap2 {
+      words.pop();
+    }
+    let rules = match words[..] {
+      [name] => MnkRules::from_name(name)?,
+      [m, n, k] => MnkRules::new(m.parse().ok()?, n.parse().ok()?, k.parse().ok()?),
+      _ => return None,
+    };
+    Some(TicTacToe::new(MnkRules { swap2, ..rules }))
   }
 }
 
-fn draw_tic_tac_toe(game : &TicTacToe, context : &Context, graphics : &mut G2d) {
+/// Draws the board with the first row at the top, e.g.
+///
+/// ```text
+///  1 X . O
+///  2 . X .
+///  3 . . .
+///    a b c
+/// Player 2 (O) to move
+/// ```
+///
+/// A Swap2 opening adds its stage to the last line, and the result
+/// follows on its own line.
+impl fmt::Display for TicTacToe {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    let letter = |mark| if mark == Cross { 'X' } else { 'O' };
+    write!(f, "{}", draw_ascii(self.board.width, self.board.height, false, |p| {
+      self.board.get(p).map_or('.', letter)
+    }))?;
+    write!(f, "Player {} ({}) to move", self.active_player + 1, letter(self.own_mark()))?;
+    match self.opening {
+      Opening::PlaceThree => write!(f, ", Swap2: placing three")?,
+      Opening::Choose => write!(f, ", Swap2: choosing")?,
+      Opening::PlaceTwo => write!(f, ", Swap2: placing two")?,
+      Opening::FinalChoice => write!(f, ", Swap2: final choice")?,
+      Opening::Finished => (),
+    }
+    writeln!(f)?;
+    match self.victory {
+      Some(p) => writeln!(f, "Player {} won", p + 1),
+      None if self.is_draw() => writeln!(f, "Drawn"),
+      None => Ok(()),
+    }
+  }
+}
+
+/// A mark is placed by naming its tile, e.g. "b2", with the columns
+/// lettered from the left and the rows numbered from the top. When the
+/// player chooses which mark to place, the mark comes first, e.g. "Ob2".
+impl Notation for TicTacToe {
+  fn format_action(&self, action : &Action) -> String {
+    let letter = |mark| if mark == Cross { 'X' } else { 'O' };
+    match *action {
+      Action::Place { pos, mark } if self.rules.any_mark() || self.opening != Opening::Finished => {
+        format!("{}{}", letter(mark), pos.algebraic())
+      }
+      Action::Place { pos, .. } => pos.algebraic(),
+      Action::ChooseMark(mark) => format!("choose {}", letter(mark)),
+      Action::PlaceTwoMore => "place two".into(),
+    }
+  }
+
+  /// Ignores case and spaces
+  fn parse_action(&self, text : &str) -> Result<Action, NotationError> {
+    let simplify = |s : &str| s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    let simple = simplify(text);
+    find_action(self, text.trim(), |written| simplify(written) == simple)
+  }
+}
+
+fn draw_tic_tac_toe(game : &TicTacToe, tile_size : f64, context : &Context, graphics : &mut G2d) {
   clear([1.0; 4], graphics);
-  for y in 0..game.board.size {
-    for x in 0..game.board.size {
+  for y in 0..game.board.height {
+    for x in 0..game.board.width {
       let is_black = (x + (y % 2)) % 2 == 0;
       if is_black {
         rectangle(
           [0.0, 0.0, 0.0, 1.0], // black
-          [x as f64 * TILE_SIZE, y as f64 * TILE_SIZE, TILE_SIZE, TILE_SIZE],
+          [x as f64 * tile_size, y as f64 * tile_size, tile_size, tile_size],
           context.transform,
           graphics);
       }
       let tile = game.board.get(Pos{x, y});
       let colour = match tile {
-        Some(BlackPlayer) => Some([1.0, 0.0, 0.0, 1.0]),
-        Some(WhitePlayer) => Some([0.0, 1.0, 0.0, 1.0]),
+        Some(Nought) => Some([1.0, 0.0, 0.0, 1.0]),
+        Some(Cross) => Some([0.0, 1.0, 0.0, 1.0]),
         None => None,
       };
       if let Some(c) = colour {
+        let margin = tile_size / 16.0;
         ellipse(
-          c, [x as f64 * TILE_SIZE + 5.0, y as f64 * TILE_SIZE + 5.0, TILE_SIZE - 10.0, TILE_SIZE - 10.0],
+          c, [x as f64 * tile_size + margin, y as f64 * tile_size + margin,
+            tile_size - 2.0 * margin, tile_size - 2.0 * margin],
           context.transform, graphics);
       }
     }
   }
 }
 
+/// The largest tile size, for small boards
 static TILE_SIZE : f64 = 80.0;
 
-pub fn play_game<A, B>(mut agent_a : A, mut agent_b : B)
+/// The largest width or height of the window
+static MAX_BOARD_PIXELS : f64 = 640.0;
+
+/// Plays the user's action, if it's legal, and then lets the AI respond
+/// (as many times as needed to get through the opening)
+fn play_if_legal(
+  game : &mut TicTacToe, a : Action,
+  agents : &mut [&mut dyn GameAgent<TicTacToe>], rng : &mut StdRng)
+{
+  let mut actions = vec![];
+  game.possible_actions(&mut actions);
+  if !actions.contains(&a) {
+    return;
+  }
+  let player = game.active_player;
+  game.apply_action(&a);
+  while game.victory.is_none() && game.active_player != player {
+    if !agent_action(agents, game, rng) {
+      break;
+    }
+  }
+}
+
+pub fn play_game<A, B>(rules : MnkRules, mut agent_a : A, mut agent_b : B)
   where A : GameAgent<TicTacToe>, B : GameAgent<TicTacToe>
 {
-  let board_size = 3;
-  let length_to_win = 3;
-  let board_pixels = board_size as f64 * TILE_SIZE;
+  let longest_side = rules.width.max(rules.height) as f64;
+  let tile_size = TILE_SIZE.min(MAX_BOARD_PIXELS / longest_side);
+  let board_pixels = [rules.width as f64 * tile_size, rules.height as f64 * tile_size];
 
   println!("Tic tac toe!");
-  let mut game = TicTacToe::new(board_size, length_to_win);
+  if rules.swap2 {
+    println!("Swap2: press C or N to take crosses or noughts, or P to place two more");
+  }
+  let mut game = TicTacToe::new(rules);
   let mut rng = StdRng::from_entropy(); //StdRng::seed_from_u64(0);
 
+  let agents : &mut [&mut dyn GameAgent<TicTacToe>] = &mut [&mut agent_a, &mut agent_b];
+
   let mut window: PistonWindow =
-    WindowSettings::new("Tic Tac Toe", [board_pixels, board_pixels])
+    WindowSettings::new("Tic Tac Toe", board_pixels)
     .exit_on_esc(true).build().unwrap();
 
   let mut mouse_pos = [0.0, 0.0];
-  
+
   while let Some(event) = window.next() {
     if let Some(Button::Keyboard(key)) = event.press_args() {
       if key == Key::Space {
-        agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng);
+        agent_action(agents, &mut game, &mut rng);
       }
       if key == Key::Return {
-        game = TicTacToe::new(game.board.size, game.length_to_win);
+        game = TicTacToe::new(rules);
+      }
+      // Swap2 choices: C or N to take crosses or noughts, P to place two more
+      let choice = match key {
+        Key::C => Some(Action::ChooseMark(Cross)),
+        Key::N => Some(Action::ChooseMark(Nought)),
+        Key::P => Some(Action::PlaceTwoMore),
+        _ => None,
+      };
+      if let Some(a) = choice {
+        play_if_legal(&mut game, a, agents, &mut rng);
       }
     }
     if let Some(p) = event.mouse_cursor_args() {
       mouse_pos = p;
     }
-    // Handle mouse clicks
-    if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
-      let x = (mouse_pos[0] / TILE_SIZE) as i32;
-      let y = (mouse_pos[1] / TILE_SIZE) as i32;
+    // Handle mouse clicks. When either mark may be placed,
+    // the left button places a cross and the right a nought.
+    if let Some(Button::Mouse(button)) = event.press_args() {
+      let x = (mouse_pos[0] / tile_size) as i32;
+      let y = (mouse_pos[1] / tile_size) as i32;
       let pos = Pos{x, y};
-      if game.board.get(pos).is_none() {
-        let a = Action { pos, player: game.active_player };
-        game.apply_action(&a);
-        // AI response
-        agent_action(&mut agent_a, &mut agent_b, &mut game, &mut rng);
+      let choose_mark = rules.any_mark() || game.opening != Opening::Finished;
+      let mark = match button {
+        MouseButton::Left if choose_mark => Some(Cross),
+        MouseButton::Left => Some(game.own_mark()),
+        MouseButton::Right if choose_mark => Some(Nought),
+        _ => None,
+      };
+      if let Some(mark) = mark {
+        play_if_legal(&mut game, Action::Place { pos, mark }, agents, &mut rng);
       }
     }
     // Handle draw events
     window.draw_2d(&event, |context, graphics, _device| {
-      draw_tic_tac_toe(&game, &context, graphics)
+      draw_tic_tac_toe(&game, tile_size, &context, graphics)
     });
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The lines of a drawn board, without the indentation
+  fn lines(text : &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).collect()
+  }
+
+  #[test]
+  fn swap2_stage_reads_back_from_ascii() {
+    let text = "
+       1 X . . . . . .
+       2 . . . . X . .
+       3 . . . . . . .
+       4 . . . . . . .
+       5 . . . . O . .
+       6 . . . . . . .
+       7 . . . . . . .
+         a b c d e f g
+      Player 2 (O) to move, Swap2: choosing
+    ";
+    let rules = MnkRules { swap2: true, ..MnkRules::new(7, 7, 5) };
+    let game = TicTacToe::from_ascii(rules, text).unwrap();
+    let mut actions = vec![];
+    game.possible_actions(&mut actions);
+    assert_eq!(actions, [Action::ChooseMark(Cross), Action::ChooseMark(Nought), Action::PlaceTwoMore]);
+    assert_eq!(lines(&game.to_string()), lines(text));
+  }
+
+  #[test]
+  fn finished_game_reads_back_from_ascii() {
+    let text = "
+       1 X O .
+       2 O X .
+       3 . . X
+         a b c
+      Player 2 (O) to move
+      Player 1 won
+    ";
+    let game = TicTacToe::from_ascii(MnkRules::tic_tac_toe(), text).unwrap();
+    assert_eq!(game.winner(), Some(0));
+    assert_eq!(lines(&game.to_string()), lines(text));
+  }
+}
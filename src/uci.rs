@@ -0,0 +1,425 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::agents::{Game, GameAgent, SearchLimits, SearchInfo};
+use crate::chess::{Chess, Variant, Action};
+
+/// The variants that the UCI_Variant option offers, by the names
+/// that GUIs like cutechess use for them
+const VARIANTS : &[(&str, Variant)] = &[
+  ("chess", Variant::Standard), ("crazyhouse", Variant::Crazyhouse), ("atomic", Variant::Atomic),
+  ("3check", Variant::ThreeCheck), ("kingofthehill", Variant::KingOfTheHill)];
+
+/// `Chess::player_score` is at least this for a won game
+pub const WIN_SCORE : f64 = 1000.0;
+
+/// The time kept back from each move for the GUI, in milliseconds
+const MOVE_OVERHEAD : i64 = 50;
+
+/// The lines that `read_commands` has read, each with its stop flag
+pub type Commands = Receiver<(String, Arc<AtomicBool>)>;
+
+/// Reads lines from `input` on another thread, so that a search on this
+/// thread can be stopped by what comes next. Each line is sent with a flag
+/// that is set by the next line starting with one of `interrupts`, or when
+/// `input` ends.
+pub fn read_commands<R>(input : R, interrupts : &'static [&'static str]) -> Commands
+  where R : BufRead + Send + 'static
+{
+  let (sender, lines) = mpsc::channel();
+  thread::spawn(move || {
+    let mut stop = Arc::new(AtomicBool::new(false));
+    for line in input.lines() {
+      let line = match line {
+        Ok(line) => line,
+        Err(_) => break,
+      };
+      if line.split_whitespace().next().is_some_and(|w| interrupts.contains(&w)) {
+        stop.store(true, Ordering::Relaxed);
+        stop = Arc::new(AtomicBool::new(false));
+      }
+      if sender.send((line, stop.clone())).is_err() {
+        break;
+      }
+    }
+    stop.store(true, Ordering::Relaxed);
+  });
+  lines
+}
+
+/// What a UCI engine remembers between commands
+struct UciEngine<A> {
+  agent : A,
+  /// None after an invalid "position" command, until a valid one
+  game : Option<Chess>,
+  variant : Variant,
+  /// Castling is written as the king taking its own rook
+  chess960 : bool,
+  rng : StdRng,
+}
+
+/// Plays chess for a GUI or tournament manager with `agent`, by speaking
+/// the Universal Chess Interface over `input` and `output`, until the GUI
+/// sends "quit" or closes `input`. The GUI can change any of the agent's
+/// parameters with "setoption". Commands that arrive during a search are
+/// answered once it's over, apart from "stop" and "quit", which end it early,
+/// and "isready", which is answered straight away.
+pub fn run_uci<A, R, W>(agent : A, input : R, mut output : W) -> io::Result<()>
+  where A : GameAgent<Chess>, R : BufRead + Send + 'static, W : Write
+{
+  let lines = read_commands(input, &["stop", "quit"]);
+  let mut engine = UciEngine {
+    agent, game: Some(Chess::new()), variant: Variant::Standard, chess960: false, rng: StdRng::from_entropy(),
+  };
+  // Commands that arrived while an infinite search waited for "stop"
+  let mut queued = VecDeque::new();
+  while let Some((line, stop)) = queued.pop_front().or_else(|| lines.recv().ok()) {
+    let words : Vec<&str> = line.split_whitespace().collect();
+    match words.first() {
+      Some(&"uci") => engine.identify(&mut output)?,
+      Some(&"isready") => writeln!(output, "readyok")?,
+      Some(&"ucinewgame") => engine.game = Some(Chess::new().with_variant(engine.variant)),
+      Some(&"setoption") => engine.set_option(&words[1..], &mut output)?,
+      Some(&"position") => engine.set_position(&words[1..], &mut output)?,
+      // The GUI can say to quit while an infinite search waits for "stop"
+      Some(&"go") if !engine.go(&words[1..], &stop, &lines, &mut queued, &mut output)? => return Ok(()),
+      // Not part of UCI, but many engines draw the board for "d"
+      Some(&"d") => match &engine.game {
+        Some(game) => write!(output, "{}", game)?,
+        None => writeln!(output, "info string No valid position")?,
+      },
+      Some(&"quit") => return Ok(()),
+      // "stop" without a search, and commands for features we don't have
+      _ => (),
+    }
+    output.flush()?;
+  }
+  Ok(())
+}
+
+impl <A : GameAgent<Chess>> UciEngine<A> {
+  /// Answers "uci" with the engine's name and options
+  fn identify<W : Write>(&self, out : &mut W) -> io::Result<()> {
+    writeln!(out, "id name Workshop {}", self.agent.name())?;
+    writeln!(out, "id author the Workshop authors")?;
+    writeln!(out, "option name UCI_Chess960 type check default false")?;
+    let names : Vec<&str> = VARIANTS.iter().map(|(name, _)| *name).collect();
+    writeln!(out, "option name UCI_Variant type combo default chess var {}", names.join(" var "))?;
+    for (name, value) in self.agent.parameters() {
+      match option_type(&value) {
+        OptionType::Check => writeln!(out, "option name {} type check default {}", name, value)?,
+        OptionType::Spin => writeln!(out, "option name {} type spin default {} min -1000000 max 1000000", name, value)?,
+        OptionType::String => writeln!(out, "option name {} type string default {}", name, value)?,
+      }
+    }
+    writeln!(out, "uciok")
+  }
+
+  /// Handles "setoption name <name> value <value>"
+  fn set_option<W : Write>(&mut self, args : &[&str], out : &mut W) -> io::Result<()> {
+    let value_at = args.iter().position(|&w| w == "value").unwrap_or(args.len());
+    let name = args.get(1..value_at).unwrap_or(&[]).join(" ");
+    let value = args.get(value_at + 1..).unwrap_or(&[]).join(" ");
+    match name.as_str() {
+      "UCI_Chess960" => self.chess960 = value == "true",
+      "UCI_Variant" => match VARIANTS.iter().find(|(n, _)| *n == value) {
+        Some(&(_, variant)) => {
+          self.variant = variant;
+          self.game = Some(Chess::new().with_variant(variant));
+        }
+        None => writeln!(out, "info string Unknown variant {}", value)?,
+      },
+      _ => {
+        if !self.agent.set_parameter(&name, &value) {
+          writeln!(out, "info string Can't set {} to {}", name, value)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Handles "position startpos|fen <fen> [moves <move>...]". An invalid
+  /// position is reported, and there is no position to search from
+  /// until the next valid one.
+  fn set_position<W : Write>(&mut self, args : &[&str], out : &mut W) -> io::Result<()> {
+    self.game = None;
+    let moves_at = args.iter().position(|&w| w == "moves").unwrap_or(args.len());
+    let start = match args.first() {
+      Some(&"startpos") => Ok(Chess::new().with_variant(self.variant)),
+      Some(&"fen") => Chess::from_fen(self.variant, &args[1..moves_at].join(" "))
+        .map_err(|e| format!("Invalid FEN: {}", e)),
+      _ => Err("Expected startpos or fen".to_string()),
+    };
+    let mut game = match start {
+      Ok(game) => game,
+      Err(e) => return writeln!(out, "info string {}", e),
+    };
+    for m in args.iter().skip(moves_at + 1) {
+      match game.parse_long_algebraic(m, self.chess960) {
+        Ok(a) => game.apply_action(&a),
+        Err(e) => return writeln!(out, "info string {}", e),
+      }
+    }
+    self.game = Some(game);
+    Ok(())
+  }
+
+  /// Handles "go", searching within its limits and then writing the best
+  /// move. An infinite search waits for "stop" before it answers, and
+  /// adds the commands that come in meanwhile to `queued`.
+  /// Returns false if the GUI said to quit while it was waiting.
+  fn go<W : Write>(
+    &mut self, args : &[&str], stop : &Arc<AtomicBool>, lines : &Commands,
+    queued : &mut VecDeque<(String, Arc<AtomicBool>)>, out : &mut W)
+    -> io::Result<bool>
+  {
+    let start = Instant::now();
+    let UciEngine { agent, game, chess960, rng, .. } = self;
+    // Searching the last valid position would answer with a move from the wrong one
+    let game = match game {
+      Some(game) => &*game,
+      None => {
+        writeln!(out, "info string No valid position to search from")?;
+        writeln!(out, "bestmove 0000")?;
+        out.flush()?;
+        return Ok(true);
+      }
+    };
+    let value = |name| {
+      let i = args.iter().position(|&w| w == name)?;
+      args.get(i + 1)?.parse::<i64>().ok()
+    };
+    let (clock, increment) = if game.active_player() == 0 { ("wtime", "winc") } else { ("btime", "binc") };
+    let think_time = match (value("movetime"), value(clock)) {
+      (Some(t), _) => Some(t),
+      (None, Some(left)) => Some(think_time(left, value(increment).unwrap_or(0), value("movestogo"))),
+      (None, None) => None,
+    };
+    let limits = SearchLimits {
+      depth: value("depth"),
+      nodes: value("nodes").map(|n| n.max(1) as u64),
+      deadline: think_time.map(|t| start + Duration::from_millis(t.max(1) as u64)),
+      stop: stop.clone(),
+    };
+
+    let mut written = Ok(());
+    let action = agent.choose_action_limited(game, rng, &limits, &mut |info| {
+      if written.is_ok() {
+        written = write_info(out, game, *chess960, info, start.elapsed());
+      }
+    });
+    written?;
+
+    let mut quit = false;
+    if args.contains(&"infinite") || args.contains(&"ponder") {
+      while !stop.load(Ordering::Relaxed) {
+        match lines.recv() {
+          Ok((line, _)) if line.trim() == "isready" => {
+            writeln!(out, "readyok")?;
+            out.flush()?;
+          }
+          Ok((line, _)) if line.trim() == "quit" => quit = true,
+          Ok((line, _)) if line.trim() == "stop" => (),
+          Ok(command) => queued.push_back(command),
+          Err(_) => quit = true,
+        }
+      }
+    }
+    match action {
+      Some(a) => writeln!(out, "bestmove {}", game.long_algebraic(&a, *chess960))?,
+      None => writeln!(out, "bestmove 0000")?,
+    }
+    out.flush()?;
+    Ok(!quit)
+  }
+}
+
+/// How long to think about a move, in milliseconds, with `left` on the
+/// clock. The time left is shared between the moves until the next time
+/// control, or 30 moves if there isn't one, and most of the increment
+/// is used as well.
+pub fn think_time(left : i64, increment : i64, moves_to_go : Option<i64>) -> i64 {
+  let share = left / moves_to_go.unwrap_or(30).max(1) + increment * 3 / 4;
+  share.min(left - MOVE_OVERHEAD).max(1)
+}
+
+/// How a GUI should let the user set an agent's parameter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OptionType {
+  /// On or off
+  Check,
+  /// A whole number
+  Spin,
+  String,
+}
+
+/// Guesses the type of a parameter from its current value, as
+/// `GameAgent::parameters` only gives them as text
+pub fn option_type(value : &str) -> OptionType {
+  if value == "true" || value == "false" {
+    OptionType::Check
+  }
+  else if value.parse::<i64>().is_ok() {
+    OptionType::Spin
+  }
+  else {
+    OptionType::String
+  }
+}
+
+/// Writes a search report as an "info" line. The score is in centipawns
+/// for the engine, or the number of moves to mate if the game is won.
+fn write_info<W : Write>(out : &mut W, game : &Chess, chess960 : bool, info : &SearchInfo<Action>, elapsed : Duration)
+  -> io::Result<()>
+{
+  let mut position = game.clone();
+  let pv : Vec<String> = info.line.iter().map(|a| {
+    let m = position.long_algebraic(a, chess960);
+    position.apply_action(a);
+    m
+  }).collect();
+  let score = if info.score.abs() >= WIN_SCORE {
+    let moves = (info.line.len() as i64 + 1) / 2;
+    format!("mate {}", if info.score > 0.0 { moves } else { -moves })
+  }
+  else {
+    format!("cp {}", (info.score * 100.0).round() as i64)
+  };
+  let millis = elapsed.as_millis() as u64;
+  writeln!(out, "info depth {} score {} nodes {} nps {} time {} pv {}", info.depth, score,
+    info.nodes, info.nodes * 1000 / millis.max(1), millis, pv.join(" "))?;
+  out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{BufReader, Cursor, Read};
+  use std::sync::Mutex;
+
+  /// Runs the engine on `input` through in-memory pipes, and returns
+  /// every line that it wrote
+  fn run<A : GameAgent<Chess>>(agent : A, input : &str) -> Vec<String> {
+    let mut output = vec![];
+    run_uci(agent, Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(String::from).collect()
+  }
+
+  /// Plays the first legal move straight away
+  #[derive(Default)]
+  struct FirstMove {
+    depth : i64,
+  }
+
+  impl GameAgent<Chess> for FirstMove {
+    fn choose_action(&mut self, game : &Chess, _rng : &mut StdRng) -> Option<Action> {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      actions.into_iter().next()
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+      vec![("depth", self.depth.to_string())]
+    }
+
+    fn set_parameter(&mut self, name : &str, value : &str) -> bool {
+      name == "depth" && value.parse().map(|v| self.depth = v).is_ok()
+    }
+  }
+
+  /// Input that arrives a line at a time, as the test sends it
+  struct Pipe {
+    lines : Receiver<String>,
+    pending : Vec<u8>,
+  }
+
+  impl Read for Pipe {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+      if self.pending.is_empty() {
+        match self.lines.recv() {
+          Ok(line) => self.pending = format!("{}\n", line).into_bytes(),
+          Err(_) => return Ok(0),
+        }
+      }
+      let n = buf.len().min(self.pending.len());
+      buf[..n].copy_from_slice(&self.pending[..n]);
+      self.pending.drain(..n);
+      Ok(n)
+    }
+  }
+
+  /// Output that the test can read while the engine is still running
+  #[derive(Clone, Default)]
+  struct Shared(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for Shared {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl Shared {
+    fn lines(&self) -> Vec<String> {
+      String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(String::from).collect()
+    }
+  }
+
+  #[test]
+  fn invalid_position_has_no_best_move() {
+    let output = run(FirstMove::default(), "position fen nonsense\ngo depth 1\n");
+    assert!(output[0].starts_with("info string Invalid FEN"));
+    assert_eq!(output[1..], ["info string No valid position to search from", "bestmove 0000"]);
+    // Not the first move from the start, which was the last valid position
+    let output = run(FirstMove::default(), "position startpos moves e2e5\ngo depth 1\n");
+    assert_eq!(output[1..], ["info string No valid position to search from", "bestmove 0000"]);
+    let output = run(FirstMove::default(), "position startpos moves e2e5\nposition startpos moves e2e4\ngo\n");
+    assert_eq!(output[1..], ["bestmove a7a6"]);
+  }
+
+  #[test]
+  fn setoption_sets_agent_parameters() {
+    let input = "setoption name depth value 4\nsetoption name depth value deep\n\
+      setoption name width value 3\nsetoption name UCI_Variant value shogi\nuci\n";
+    let output = run(FirstMove::default(), input);
+    assert_eq!(output[..3], ["info string Can't set depth to deep", "info string Can't set width to 3",
+      "info string Unknown variant shogi"]);
+    assert!(output.contains(&"option name depth type spin default 4 min -1000000 max 1000000".to_string()));
+    assert_eq!(output.last().unwrap(), "uciok");
+  }
+
+  #[test]
+  fn go_infinite_waits_for_stop() {
+    let (sender, lines) = mpsc::channel();
+    let output = Shared::default();
+    let engine_output = output.clone();
+    let engine = thread::spawn(move || {
+      let input = BufReader::new(Pipe { lines, pending: vec![] });
+      run_uci(FirstMove::default(), input, engine_output).unwrap();
+    });
+    for line in ["position startpos", "go infinite", "position startpos moves e2e4", "isready"].iter() {
+      sender.send(line.to_string()).unwrap();
+    }
+    // "isready" is answered during the search, after the position was queued
+    while output.lines().is_empty() {
+      thread::yield_now();
+    }
+    assert_eq!(output.lines(), ["readyok"]);
+    for line in ["stop", "go depth 1"].iter() {
+      sender.send(line.to_string()).unwrap();
+    }
+    drop(sender);
+    engine.join().unwrap();
+    // The queued position is searched by the next "go"
+    assert_eq!(output.lines(), ["readyok", "bestmove b1c3", "bestmove a7a6"]);
+  }
+}
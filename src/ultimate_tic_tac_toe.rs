@@ -0,0 +1,304 @@
+use piston_window::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::utils::*;
+use crate::agents::*;
+use crate::record::Recordable;
+use crate::notation::Notation;
+use crate::tic_tac_toe::{TicTacToe, MnkRules, Mark};
+
+/// A move in the sub-board at `board`, on the tile `pos`.
+/// Both are in the range 0 to 2.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Action {
+  pub board : Pos,
+  pub pos : Pos,
+}
+
+/// Ultimate tic tac toe: nine tic tac toe boards in a 3x3 grid. Winning a
+/// sub-board claims that tile of the meta-board, and the first player to
+/// make a line on the meta-board wins. Each move sends the opponent to
+/// the sub-board matching the tile just played, unless that board is
+/// already decided, in which case they may play in any open board.
+#[derive(Clone)]
+pub struct UltimateTicTacToe {
+  boards : Vec<TicTacToe>,
+  /// Holds the mark of the player who won each sub-board
+  meta : TicTacToe,
+  active_player : i64,
+  /// The sub-board that the active player must play in, if any
+  next_board : Option<Pos>,
+  victory : Option<i64>,
+  /// All the sub-boards are decided, with no line on the meta-board
+  draw : bool,
+}
+
+fn board_index(p : Pos) -> usize {
+  (p.y * 3 + p.x) as usize
+}
+
+fn mark_of(player : i64) -> Mark {
+  if player == 0 { Mark::Cross } else { Mark::Nought }
+}
+
+impl UltimateTicTacToe {
+
+  pub fn new() -> UltimateTicTacToe {
+    UltimateTicTacToe {
+      boards: vec![TicTacToe::new(MnkRules::tic_tac_toe()) ; 9],
+      meta: TicTacToe::new(MnkRules::tic_tac_toe()),
+      active_player: 0,
+      next_board: None,
+      victory: None,
+      draw: false,
+    }
+  }
+
+  /// Returns true if the sub-board has been won or filled up
+  fn is_decided(&self, board : Pos) -> bool {
+    self.meta.mark_at(board).is_some() || self.boards[board_index(board)].is_full()
+  }
+
+  /// The sub-boards that the active player may play in
+  fn open_boards(&self) -> Vec<Pos> {
+    if self.victory.is_some() || self.draw {
+      return vec![];
+    }
+    match self.next_board {
+      Some(b) => vec![b],
+      None => {
+        let mut boards = vec![];
+        for y in 0..3 {
+          for x in 0..3 {
+            if !self.is_decided(Pos {x, y}) {
+              boards.push(Pos {x, y});
+            }
+          }
+        }
+        boards
+      }
+    }
+  }
+
+  /// The number of sub-boards won by each player
+  fn boards_won(&self) -> [i32 ; 2] {
+    let mut won = [0, 0];
+    for y in 0..3 {
+      for x in 0..3 {
+        match self.meta.mark_at(Pos {x, y}) {
+          Some(Mark::Cross) => won[0] += 1,
+          Some(Mark::Nought) => won[1] += 1,
+          None => (),
+        }
+      }
+    }
+    won
+  }
+}
+
+impl Game for UltimateTicTacToe {
+  type Action = Action;
+
+  fn possible_actions(&self, actions : &mut Vec<Action>) {
+    for board in self.open_boards() {
+      let sub_board = &self.boards[board_index(board)];
+      for y in 0..3 {
+        for x in 0..3 {
+          let pos = Pos {x, y};
+          if sub_board.mark_at(pos).is_none() {
+            actions.push(Action { board, pos });
+          }
+        }
+      }
+    }
+  }
+
+  fn active_player(&self) -> i64 {
+    self.active_player
+  }
+
+  fn apply_action(&mut self, a : &Action) {
+    let mark = mark_of(self.active_player);
+    // Winning a small board places a mark on the meta board
+    if self.boards[board_index(a.board)].place(a.pos, mark) && self.meta.place(a.board, mark) {
+      self.victory = Some(self.active_player);
+    }
+    let all_decided = (0..9).all(|i| self.is_decided(Pos {x: i % 3, y: i / 3}));
+    if self.victory.is_none() && all_decided {
+      self.draw = true;
+    }
+    // The opponent plays in the board matching the tile just played,
+    // unless it's already decided
+    self.next_board = if self.is_decided(a.pos) { None } else { Some(a.pos) };
+    self.active_player = 1 - self.active_player;
+  }
+
+  /// 1 for a win and -1 for a loss, and otherwise the
+  /// difference in sub-boards won, scaled to fit between
+  fn player_score(&self, player : i64) -> f64 {
+    match self.victory {
+      Some(w) if w == player => 1.0,
+      Some(_) => -1.0,
+      None => {
+        let won = self.boards_won();
+        let p = player as usize;
+        (won[p] - won[1 - p]) as f64 / 10.0
+      }
+    }
+  }
+
+  fn winner(&self) -> Option<i64> {
+    self.victory
+  }
+
+  fn is_draw(&self) -> bool {
+    self.draw
+  }
+}
+
+impl Stochastic for UltimateTicTacToe {}
+
+impl Recordable for UltimateTicTacToe {
+  const GAME_TYPE : &'static str = "ultimate";
+
+  fn config(&self) -> String {
+    String::new()
+  }
+
+  fn from_config(_config : &str) -> Option<UltimateTicTacToe> {
+    Some(UltimateTicTacToe::new())
+  }
+}
+
+/// A move names the sub-board and then the tile within it, e.g. "a1/b2"
+/// for the middle of the top left board. Both are written as in tic tac
+/// toe, with the columns lettered from the left and the rows numbered
+/// from the top.
+impl Notation for UltimateTicTacToe {
+  fn format_action(&self, action : &Action) -> String {
+    format!("{}/{}", action.board.algebraic(), action.pos.algebraic())
+  }
+}
+
+static TILE_SIZE : f64 = 60.0;
+
+/// The space between the sub-boards
+static GAP : f64 = 10.0;
+
+/// The top left corner of a tile in the window
+fn tile_corner(board : Pos, pos : Pos) -> (f64, f64) {
+  let x = board.x as f64 * (3.0 * TILE_SIZE + GAP) + pos.x as f64 * TILE_SIZE;
+  let y = board.y as f64 * (3.0 * TILE_SIZE + GAP) + pos.y as f64 * TILE_SIZE;
+  (x, y)
+}
+
+fn mark_colour(mark : Mark) -> [f32 ; 4] {
+  match mark {
+    Mark::Nought => [1.0, 0.0, 0.0, 1.0],
+    Mark::Cross => [0.0, 1.0, 0.0, 1.0],
+  }
+}
+
+fn draw_ultimate_tic_tac_toe(game : &UltimateTicTacToe, context : &Context, graphics : &mut G2d) {
+  clear([1.0; 4], graphics);
+  for by in 0..3 {
+    for bx in 0..3 {
+      let board = Pos {x: bx, y: by};
+      let sub_board = &game.boards[board_index(board)];
+      for y in 0..3 {
+        for x in 0..3 {
+          let (left, top) = tile_corner(board, Pos {x, y});
+          if (x + (y % 2)) % 2 == 0 {
+            rectangle(
+              [0.0, 0.0, 0.0, 1.0], // black
+              [left, top, TILE_SIZE, TILE_SIZE],
+              context.transform,
+              graphics);
+          }
+          if let Some(mark) = sub_board.mark_at(Pos {x, y}) {
+            ellipse(
+              mark_colour(mark), [left + 5.0, top + 5.0, TILE_SIZE - 10.0, TILE_SIZE - 10.0],
+              context.transform, graphics);
+          }
+        }
+      }
+      // Cover won boards in the winner's colour
+      if let Some(mark) = game.meta.mark_at(board) {
+        let (left, top) = tile_corner(board, Pos {x: 0, y: 0});
+        let mut colour = mark_colour(mark);
+        colour[3] = 0.5;
+        rectangle(colour, [left, top, 3.0 * TILE_SIZE, 3.0 * TILE_SIZE], context.transform, graphics);
+      }
+    }
+  }
+  // Highlight the boards that the active player can play in
+  for board in game.open_boards() {
+    let (left, top) = tile_corner(board, Pos {x: 0, y: 0});
+    Rectangle::new_border([0.0, 0.0, 1.0, 1.0], 3.0)
+      .draw([left, top, 3.0 * TILE_SIZE, 3.0 * TILE_SIZE],
+        &DrawState::default(), context.transform, graphics);
+  }
+}
+
+/// Finds the sub-board and tile under a point in the window
+fn tile_at(point : [f64 ; 2]) -> Option<Action> {
+  let board_pixels = 3.0 * TILE_SIZE + GAP;
+  let bx = (point[0] / board_pixels) as i32;
+  let by = (point[1] / board_pixels) as i32;
+  let x = ((point[0] - bx as f64 * board_pixels) / TILE_SIZE) as i32;
+  let y = ((point[1] - by as f64 * board_pixels) / TILE_SIZE) as i32;
+  if bx < 3 && by < 3 && x < 3 && y < 3 {
+    Some(Action { board: Pos {x: bx, y: by}, pos: Pos {x, y} })
+  }
+  else {
+    None
+  }
+}
+
+pub fn play_ultimate_tic_tac_toe<A, B>(mut agent_a : A, mut agent_b : B)
+  where A : GameAgent<UltimateTicTacToe>, B : GameAgent<UltimateTicTacToe>
+{
+  let board_pixels = 9.0 * TILE_SIZE + 2.0 * GAP;
+
+  println!("Ultimate tic tac toe!");
+  let mut game = UltimateTicTacToe::new();
+  let mut rng = StdRng::from_entropy();
+
+  let agents : &mut [&mut dyn GameAgent<UltimateTicTacToe>] = &mut [&mut agent_a, &mut agent_b];
+
+  let mut window: PistonWindow =
+    WindowSettings::new("Ultimate Tic Tac Toe", [board_pixels, board_pixels])
+    .exit_on_esc(true).build().unwrap();
+
+  let mut mouse_pos = [0.0, 0.0];
+
+  while let Some(event) = window.next() {
+    if let Some(Button::Keyboard(key)) = event.press_args() {
+      if key == Key::Space {
+        agent_action(agents, &mut game, &mut rng);
+      }
+      if key == Key::Return {
+        game = UltimateTicTacToe::new();
+      }
+    }
+    if let Some(p) = event.mouse_cursor_args() {
+      mouse_pos = p;
+    }
+    // Handle mouse clicks
+    if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+      if let Some(a) = tile_at(mouse_pos) {
+        let mut actions = vec![];
+        game.possible_actions(&mut actions);
+        if actions.contains(&a) {
+          game.apply_action(&a);
+          // AI response
+          agent_action(agents, &mut game, &mut rng);
+        }
+      }
+    }
+    // Handle draw events
+    window.draw_2d(&event, |context, graphics, _device| {
+      draw_ultimate_tic_tac_toe(&game, &context, graphics)
+    });
+  }
+}
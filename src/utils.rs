@@ -1,13 +1,25 @@
 
+use std::fmt;
 use std::ops::{Add, AddAssign, Neg};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 /// an integer (X, Y) coordinate
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Pos {
   pub x: i32,
   pub y: i32,
 }
 
+impl Pos {
+  /// The tile's name in algebraic notation, with the columns lettered
+  /// from 'a' and the rows numbered from 1, e.g. "e4"
+  pub fn algebraic(self) -> String {
+    format!("{}{}", (b'a' + self.x as u8) as char, self.y + 1)
+  }
+}
+
 impl Add for Pos {
   type Output = Pos;
   fn add(self, other: Pos) -> Pos {
@@ -28,16 +40,23 @@ impl AddAssign for Pos {
   }
 }
 
-/// Stores the tiles.
+/// Stores the tiles of a rectangular board.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Board<Tile : Clone> {
-  pub size : i32,
+  pub width : i32,
+  pub height : i32,
   tiles : Vec<Tile>,
 }
 
 impl <Tile : Clone> Board<Tile> {
+  /// A square board
   pub fn new(initial_val : Tile, size : i32) -> Board<Tile> {
-    Board { tiles : vec![initial_val ; (size * size) as usize], size }
+    Board::rectangle(initial_val, size, size)
+  }
+
+  pub fn rectangle(initial_val : Tile, width : i32, height : i32) -> Board<Tile> {
+    Board { tiles : vec![initial_val ; (width * height) as usize], width, height }
   }
 
   pub fn iter(&self) -> impl Iterator<Item=&Tile> {
@@ -45,11 +64,11 @@ impl <Tile : Clone> Board<Tile> {
   }
 
   pub fn index(&self, x : i32, y : i32) -> usize {
-    (y * self.size + x) as usize
+    (y * self.width + x) as usize
   }
 
   pub fn try_get(&self, p : Pos) -> Option<Tile> {
-    if p.x < 0 || p.x >= self.size || p.y < 0 || p.y >= self.size {
+    if p.x < 0 || p.x >= self.width || p.y < 0 || p.y >= self.height {
       None
     }
     else {
@@ -62,7 +81,89 @@ impl <Tile : Clone> Board<Tile> {
   }
 
   pub fn set(&mut self, p : Pos, t : Tile) {
-      let i = self.index(p.x, p.y);
+    let i = self.index(p.x, p.y);
     self.tiles[i] = t;
   }
 }
+
+/// The reasons that a board drawn in ASCII can't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiError {
+  /// A numbered row that is missing, repeated, too long or off the board
+  BadRow(i32),
+  UnknownTile(char),
+  /// A line other than the board that can't be read
+  BadLine(String),
+  /// The line saying whose turn it is is missing
+  NoSideToMove,
+  /// The position isn't valid, for the reason given
+  Invalid(String),
+}
+
+impl fmt::Display for AsciiError {
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AsciiError::BadRow(y) => write!(f, "row {} is missing, repeated or the wrong length", y),
+      AsciiError::UnknownTile(c) => write!(f, "'{}' isn't a tile", c),
+      AsciiError::BadLine(line) => write!(f, "can't read \"{}\"", line),
+      AsciiError::NoSideToMove => write!(f, "there is no line saying whose turn it is"),
+      AsciiError::Invalid(reason) => write!(f, "invalid position: {}", reason),
+    }
+  }
+}
+
+impl std::error::Error for AsciiError {}
+
+/// Draws a board in ASCII, one row per line starting with its number,
+/// with the columns lettered underneath. Rows are numbered from 1 for
+/// y = 0, which is drawn at the bottom if `y_up` is true.
+pub fn draw_ascii<F>(width : i32, height : i32, y_up : bool, tile : F) -> String
+  where F : Fn(Pos) -> char
+{
+  let mut text = String::new();
+  for i in 0..height {
+    let y = if y_up { height - 1 - i } else { i };
+    let row : Vec<String> = (0..width).map(|x| tile(Pos {x, y}).to_string()).collect();
+    text.push_str(format!("{:>2} {}", y + 1, row.join(" ")).trim_end());
+    text.push('\n');
+  }
+  let columns : Vec<String> = (0..width).map(|x| ((b'a' + x as u8) as char).to_string()).collect();
+  text.push_str(&format!("   {}\n", columns.join(" ")));
+  text
+}
+
+/// Reads a board drawn by `draw_ascii`, in either direction. Returns the
+/// character on each tile, with spaces for any missing from the end of
+/// a row, and the other lines of text, trimmed.
+pub fn read_ascii(text : &str, width : i32, height : i32) -> Result<(Board<char>, Vec<String>), AsciiError> {
+  let mut board = Board::rectangle(' ', width, height);
+  let mut seen = vec![false ; height as usize];
+  let mut lines = vec![];
+  for line in text.lines() {
+    let line = line.trim();
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+      // Skip the column letters
+      let is_columns = line.starts_with('a') && line.split(' ').all(|c| c.len() == 1);
+      if !line.is_empty() && !is_columns {
+        lines.push(line.to_string());
+      }
+      continue;
+    }
+    let number : i32 = line[..digits].parse().map_err(|_| AsciiError::BadLine(line.into()))?;
+    let y = number - 1;
+    let tiles : Vec<char> = line[digits..].chars().skip(1).step_by(2).collect();
+    if y < 0 || y >= height || seen[y as usize] || tiles.len() > width as usize
+      || !line[digits..].starts_with(' ') {
+      return Err(AsciiError::BadRow(number));
+    }
+    seen[y as usize] = true;
+    for (x, &c) in tiles.iter().enumerate() {
+      board.set(Pos {x: x as i32, y}, c);
+    }
+  }
+  if let Some(y) = seen.iter().position(|&s| !s) {
+    return Err(AsciiError::BadRow(y as i32 + 1));
+  }
+  Ok((board, lines))
+}
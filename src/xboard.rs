@@ -0,0 +1,524 @@
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::agents::{Game, GameAgent, SearchLimits};
+use crate::chess::{Chess, Variant, Action};
+use crate::uci::{read_commands, think_time, option_type, OptionType, WIN_SCORE};
+
+/// The variants that the engine offers, by their names in CECP
+const VARIANTS : &[(&str, Variant)] = &[
+  ("normal", Variant::Standard), ("crazyhouse", Variant::Crazyhouse), ("atomic", Variant::Atomic),
+  ("3check", Variant::ThreeCheck), ("kingofthehill", Variant::KingOfTheHill)];
+
+/// CECP scores a win in N moves as this plus N
+const MATE_SCORE : i64 = 100000;
+
+/// What a CECP engine remembers between commands
+struct XboardEngine<A> {
+  agent : A,
+  rng : StdRng,
+  /// Every position since the start of the game, for "undo"
+  history : Vec<Chess>,
+  variant : Variant,
+  /// Set by "variant fischerandom", which writes castling as O-O
+  chess960 : bool,
+  /// The player that the engine moves for, or None in force mode
+  side : Option<i64>,
+  /// Show the search's progress, after "post"
+  post : bool,
+  /// The depth limit from "sd"
+  depth : Option<i64>,
+  /// The exact time for each move, from "st"
+  move_time : Option<Duration>,
+  /// From "level": the moves in each time control, or 0 for the whole
+  /// game, and the time for each control and the increment, in milliseconds
+  moves_per_control : i64,
+  base : i64,
+  increment : i64,
+  /// The time on the engine's clock in milliseconds, from "time"
+  clock : i64,
+}
+
+/// Plays chess for XBoard, WinBoard and other CECP interfaces with `agent`,
+/// by speaking version 2 of the Chess Engine Communication Protocol over
+/// `input` and `output`, until the interface sends "quit" or closes `input`.
+/// "?" makes the engine move at once. Other commands that arrive while
+/// it's thinking are answered after it has moved.
+pub fn run_xboard<A, R, W>(agent : A, input : R, mut output : W) -> io::Result<()>
+  where A : GameAgent<Chess>, R : BufRead + Send + 'static, W : Write
+{
+  let lines = read_commands(input, &["?", "quit"]);
+  let mut engine = XboardEngine {
+    agent, rng: StdRng::from_entropy(), history: vec![Chess::new()], variant: Variant::Standard,
+    chess960: false, side: Some(1), post: false, depth: None, move_time: None,
+    moves_per_control: 40, base: 5 * 60 * 1000, increment: 0, clock: 5 * 60 * 1000,
+  };
+  while let Ok((line, stop)) = lines.recv() {
+    let line = line.trim();
+    let (command, args) = match line.find(' ') {
+      Some(i) => (&line[..i], line[i + 1..].trim()),
+      None => (line, ""),
+    };
+    match command {
+      "protover" => engine.features(&mut output)?,
+      "new" => engine.new_game(),
+      "variant" => match VARIANTS.iter().find(|(name, _)| *name == args) {
+        Some(&(_, variant)) => engine.set_variant(variant, false),
+        None if args == "fischerandom" => engine.set_variant(Variant::Standard, true),
+        None => writeln!(output, "Error (unsupported variant): {}", args)?,
+      },
+      "force" => engine.side = None,
+      "go" => {
+        engine.side = Some(engine.game().active_player());
+        engine.think(&stop, &mut output)?;
+      }
+      "playother" => engine.side = Some(1 - engine.game().active_player()),
+      "usermove" => {
+        if engine.user_move(args, &mut output)? && engine.side == Some(engine.game().active_player()) {
+          engine.think(&stop, &mut output)?;
+        }
+      }
+      "setboard" => match Chess::from_fen(engine.variant, args) {
+        Ok(game) => engine.history = vec![game],
+        Err(e) => writeln!(output, "tellusererror Illegal position: {}", e)?,
+      },
+      "undo" => engine.take_back(1),
+      "remove" => engine.take_back(2),
+      "level" => {
+        if !engine.set_level(args) {
+          writeln!(output, "Error (bad time control): {}", line)?;
+        }
+      }
+      "st" => match args.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 => engine.move_time = Some(Duration::from_millis((seconds * 1000.0) as u64)),
+        _ => writeln!(output, "Error (bad time): {}", line)?,
+      },
+      "sd" => match args.parse::<i64>() {
+        Ok(depth) if depth > 0 => engine.depth = Some(depth),
+        _ => writeln!(output, "Error (bad depth): {}", line)?,
+      },
+      // Centiseconds on the engine's clock
+      "time" => match args.parse::<i64>() {
+        Ok(time) => engine.clock = time * 10,
+        Err(_) => writeln!(output, "Error (bad time): {}", line)?,
+      },
+      "post" => engine.post = true,
+      "nopost" => engine.post = false,
+      "option" => engine.set_option(args, &mut output)?,
+      "ping" => writeln!(output, "pong {}", args)?,
+      // The game is over, by the interface's rules or the players' agreement
+      "result" => engine.side = None,
+      "quit" => return Ok(()),
+      // "?" without a search, and commands that don't need an answer
+      "?" | "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+        | "otim" | "name" | "rating" | "ics" | "white" | "black" | "draw" | "hint" | "bk" | "" => (),
+      _ => writeln!(output, "Error (unknown command): {}", command)?,
+    }
+    output.flush()?;
+  }
+  Ok(())
+}
+
+impl <A : GameAgent<Chess>> XboardEngine<A> {
+  fn game(&self) -> &Chess {
+    self.history.last().unwrap()
+  }
+
+  /// Answers "protover" with the features the engine supports,
+  /// including the agent's parameters as options
+  fn features<W : Write>(&self, out : &mut W) -> io::Result<()> {
+    let mut variants : Vec<&str> = VARIANTS.iter().map(|(name, _)| *name).collect();
+    variants.push("fischerandom");
+    write!(out, "feature myname=\"Workshop {}\" ping=1 setboard=1 usermove=1 time=1 draw=0 sigint=0 sigterm=0 \
+      reuse=1 analyze=0 colors=0 san=0 variants=\"{}\"", self.agent.name(), variants.join(","))?;
+    for (name, value) in self.agent.parameters() {
+      match option_type(&value) {
+        OptionType::Check => write!(out, " option=\"{} -check {}\"", name, if value == "true" { 1 } else { 0 })?,
+        OptionType::Spin => write!(out, " option=\"{} -spin {} -1000000 1000000\"", name, value)?,
+        OptionType::String => write!(out, " option=\"{} -string {}\"", name, value)?,
+      }
+    }
+    writeln!(out, " done=1")
+  }
+
+  /// Sets up the standard position with the engine playing black, and
+  /// forgets the variant, the depth limit and the time controls
+  fn new_game(&mut self) {
+    self.set_variant(Variant::Standard, false);
+    self.side = Some(1);
+    self.depth = None;
+    self.move_time = None;
+    self.clock = self.base;
+  }
+
+  fn set_variant(&mut self, variant : Variant, chess960 : bool) {
+    self.variant = variant;
+    self.chess960 = chess960;
+    self.history = vec![Chess::new().with_variant(variant)];
+  }
+
+  /// Reads "level <moves> <minutes>[:<seconds>] <increment>"
+  fn set_level(&mut self, args : &str) -> bool {
+    let fields : Vec<&str> = args.split_whitespace().collect();
+    if fields.len() != 3 {
+      return false;
+    }
+    let mut base = fields[1].split(':');
+    let minutes = base.next().and_then(|m| m.parse::<i64>().ok());
+    let seconds = base.next().map_or(Some(0), |s| s.parse::<i64>().ok());
+    match (fields[0].parse::<i64>(), minutes, seconds, fields[2].parse::<f64>()) {
+      (Ok(moves), Some(minutes), Some(seconds), Ok(increment)) if moves >= 0 => {
+        self.moves_per_control = moves;
+        self.base = (minutes * 60 + seconds) * 1000;
+        self.increment = (increment * 1000.0) as i64;
+        self.move_time = None;
+        self.clock = self.base;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Handles "option <name>=<value>". Check boxes are sent as 0 or 1.
+  fn set_option<W : Write>(&mut self, args : &str, out : &mut W) -> io::Result<()> {
+    let (name, value) = match args.find('=') {
+      Some(i) => (&args[..i], &args[i + 1..]),
+      None => (args, ""),
+    };
+    let is_check = self.agent.parameters().iter().any(|(n, v)| *n == name && (v == "true" || v == "false"));
+    let value = match value {
+      "1" if is_check => "true",
+      "0" if is_check => "false",
+      _ => value,
+    };
+    if !self.agent.set_parameter(name, value) {
+      writeln!(out, "Error (can't set option): {}", args)?;
+    }
+    Ok(())
+  }
+
+  /// Takes back `moves` moves, or as many as there are
+  fn take_back(&mut self, moves : usize) {
+    let keep = self.history.len().saturating_sub(moves).max(1);
+    self.history.truncate(keep);
+  }
+
+  /// Writes an action as CECP expects: in coordinates like "e2e4", but
+  /// with castling written as O-O in Chess960
+  fn format_move(&self, game : &Chess, a : &Action) -> String {
+    match *a {
+      Action::Castle { king, rook, .. } if self.chess960 => {
+        if rook.x > king.x { "O-O".into() } else { "O-O-O".into() }
+      }
+      _ => game.long_algebraic(a, false),
+    }
+  }
+
+  /// Plays the opponent's move, and returns true if it was legal
+  fn user_move<W : Write>(&mut self, text : &str, out : &mut W) -> io::Result<bool> {
+    let game = self.game().clone();
+    let parsed = if text.starts_with("O-O") || text.starts_with("0-0") {
+      game.parse_san(text)
+    }
+    else {
+      game.parse_long_algebraic(text, false)
+    };
+    match parsed {
+      Ok(a) => {
+        self.play(&a, out)?;
+        Ok(true)
+      }
+      Err(e) => {
+        writeln!(out, "Illegal move ({}): {}", e, text)?;
+        Ok(false)
+      }
+    }
+  }
+
+  /// Plays an action, and announces the result if that ends the game
+  fn play<W : Write>(&mut self, a : &Action, out : &mut W) -> io::Result<()> {
+    let mut game = self.game().clone();
+    game.apply_action(a);
+    match game.winner() {
+      Some(0) => writeln!(out, "1-0 {{White wins}}")?,
+      Some(_) => writeln!(out, "0-1 {{Black wins}}")?,
+      None if game.is_draw() => writeln!(out, "1/2-1/2 {{Draw}}")?,
+      None => (),
+    }
+    self.history.push(game);
+    Ok(())
+  }
+
+  /// The limits for the engine's next move: the time from "st", or a
+  /// share of its clock, and the depth from "sd"
+  fn limits(&self, stop : &Arc<AtomicBool>) -> SearchLimits {
+    let think = match self.move_time {
+      Some(t) => t,
+      None => {
+        let moves_to_go = if self.moves_per_control > 0 {
+          Some(self.moves_per_control - (self.game().fullmove_number() as i64 - 1) % self.moves_per_control)
+        }
+        else {
+          None
+        };
+        Duration::from_millis(think_time(self.clock, self.increment, moves_to_go) as u64)
+      }
+    };
+    SearchLimits { depth: self.depth, nodes: None, deadline: Some(Instant::now() + think), stop: stop.clone() }
+  }
+
+  /// Chooses and plays the engine's move, showing the search's
+  /// progress as "<depth> <score> <centiseconds> <nodes> <line>"
+  /// if "post" is on
+  fn think<W : Write>(&mut self, stop : &Arc<AtomicBool>, out : &mut W) -> io::Result<()> {
+    let game = self.game().clone();
+    let limits = self.limits(stop);
+    let start = Instant::now();
+    let post = self.post;
+    let mut written = Ok(());
+    let action = self.agent.choose_action_limited(&game, &mut self.rng, &limits, &mut |info| {
+      if !post || written.is_err() {
+        return;
+      }
+      let moves = (info.line.len() as i64 + 1) / 2;
+      let score = if info.score >= WIN_SCORE {
+        MATE_SCORE + moves
+      }
+      else if info.score <= -WIN_SCORE {
+        -MATE_SCORE - moves
+      }
+      else {
+        (info.score * 100.0).round() as i64
+      };
+      let mut position = game.clone();
+      let line : Vec<String> = info.line.iter().map(|a| {
+        let san = position.san(a);
+        position.apply_action(a);
+        san
+      }).collect();
+      written = writeln!(out, "{} {} {} {} {}", info.depth, score, start.elapsed().as_millis() / 10,
+        info.nodes, line.join(" ")).and_then(|_| out.flush());
+    });
+    written?;
+    if let Some(a) = action {
+      writeln!(out, "move {}", self.format_move(&game, &a))?;
+      self.play(&a, out)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use std::sync::Mutex;
+  use std::sync::atomic::Ordering;
+
+  /// Runs the engine on `input` through in-memory pipes, and returns
+  /// every line that it wrote
+  fn run<A : GameAgent<Chess>>(agent : A, input : &str) -> Vec<String> {
+    let mut output = vec![];
+    run_xboard(agent, Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(String::from).collect()
+  }
+
+  /// Plays the first legal move, and keeps the limits it was given
+  /// along with how long they left it to think
+  #[derive(Default)]
+  struct FirstMove {
+    limits : Arc<Mutex<Vec<SearchLimits>>>,
+    think_times : Arc<Mutex<Vec<Option<Duration>>>>,
+    depth : i64,
+  }
+
+  impl GameAgent<Chess> for FirstMove {
+    fn choose_action(&mut self, game : &Chess, _rng : &mut StdRng) -> Option<Action> {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      actions.into_iter().next()
+    }
+
+    fn choose_action_limited(
+      &mut self, game : &Chess, rng : &mut StdRng, limits : &SearchLimits,
+      _report : &mut dyn FnMut(&crate::agents::SearchInfo<Action>)) -> Option<Action>
+    {
+      let now = Instant::now();
+      self.think_times.lock().unwrap().push(limits.deadline.map(|d| d.saturating_duration_since(now)));
+      self.limits.lock().unwrap().push(limits.clone());
+      self.choose_action(game, rng)
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+      vec![("depth", self.depth.to_string())]
+    }
+
+    fn set_parameter(&mut self, name : &str, value : &str) -> bool {
+      name == "depth" && value.parse().map(|v| self.depth = v).is_ok()
+    }
+  }
+
+  /// Thinks until it is told to stop, then plays the first legal move
+  struct UntilStopped;
+
+  impl GameAgent<Chess> for UntilStopped {
+    fn choose_action(&mut self, game : &Chess, rng : &mut StdRng) -> Option<Action> {
+      FirstMove::default().choose_action(game, rng)
+    }
+
+    fn choose_action_limited(
+      &mut self, game : &Chess, rng : &mut StdRng, limits : &SearchLimits,
+      _report : &mut dyn FnMut(&crate::agents::SearchInfo<Action>)) -> Option<Action>
+    {
+      while !limits.stop.load(Ordering::Relaxed) {
+        std::thread::yield_now();
+      }
+      self.choose_action(game, rng)
+    }
+  }
+
+  /// Castles whenever it can
+  struct Castler;
+
+  impl GameAgent<Chess> for Castler {
+    fn choose_action(&mut self, game : &Chess, _rng : &mut StdRng) -> Option<Action> {
+      let mut actions = vec![];
+      game.possible_actions(&mut actions);
+      actions.into_iter().find(|a| matches!(a, Action::Castle { .. }))
+    }
+  }
+
+  fn moves(output : &[String]) -> Vec<&str> {
+    output.iter().filter(|l| l.starts_with("move ")).map(|l| &l[5..]).collect()
+  }
+
+  #[test]
+  fn handshake_lists_features_and_options() {
+    let output = run(FirstMove::default(), "xboard\nprotover 2\nping 7\n");
+    assert_eq!(output.len(), 2);
+    assert!(output[0].starts_with("feature myname=\"Workshop Agent\" ping=1 setboard=1 usermove=1"));
+    assert!(output[0].contains("variants=\"normal,crazyhouse,atomic,3check,kingofthehill,fischerandom\""));
+    assert!(output[0].contains(" option=\"depth -spin 0 -1000000 1000000\""));
+    assert!(output[0].ends_with(" done=1"));
+    assert_eq!(output[1], "pong 7");
+  }
+
+  #[test]
+  fn engine_answers_each_user_move() {
+    let output = run(FirstMove::default(), "new\nusermove e2e4\nusermove d2d4\nping 1\n");
+    // The first legal move for black is a pawn move on the a file
+    assert_eq!(moves(&output), ["a7a6", "a6a5"]);
+    assert_eq!(output.last().unwrap(), "pong 1");
+  }
+
+  #[test]
+  fn force_mode_only_records_moves_until_go() {
+    let output = run(FirstMove::default(), "new\nforce\nusermove e2e4\nusermove e7e5\nping 1\ngo\nusermove a7a6\n");
+    assert_eq!(output[0], "pong 1");
+    // After "go" the engine plays white, the side to move, whose first
+    // legal move is with the knight, and then with the rook behind it
+    assert_eq!(moves(&output), ["b1c3", "a1b1"]);
+  }
+
+  #[test]
+  fn playother_waits_for_the_user() {
+    let output = run(FirstMove::default(), "new\nforce\nusermove e2e4\nplayother\nping 1\nusermove e7e5\n");
+    assert_eq!(output[0], "pong 1");
+    assert_eq!(moves(&output), ["b1c3"]);
+  }
+
+  #[test]
+  fn illegal_and_malformed_moves_are_rejected() {
+    let output = run(FirstMove::default(), "new\nusermove e2e5\nusermove castle\nping 2\n");
+    assert_eq!(output, [
+      "Illegal move ('e2e5' isn't a legal move): e2e5",
+      "Illegal move ('castle' isn't a move in algebraic notation): castle",
+      "pong 2"]);
+  }
+
+  #[test]
+  fn underpromotions_are_accepted() {
+    // Only a knight can go on from e8 to f6
+    let input = "new\nforce\nsetboard 7k/4P3/8/8/8/8/8/K7 w - - 0 1\n\
+      usermove e7e8n\nusermove h8g8\nusermove e8f6\nping 1\n";
+    assert_eq!(run(FirstMove::default(), input), ["pong 1"]);
+  }
+
+  #[test]
+  fn undo_and_remove_take_back_moves() {
+    let input = "new\nforce\nusermove e2e4\nusermove e7e5\nusermove g1f3\nremove\nusermove e7e5\nundo\nundo\ngo\n";
+    let output = run(FirstMove::default(), input);
+    // Back at the start after taking back three moves, so the engine plays white
+    assert_eq!(moves(&output), ["b1c3"]);
+    // Taking back more moves than were played stops at the start
+    let output = run(FirstMove::default(), "new\nforce\nusermove e2e4\nremove\nremove\ngo\n");
+    assert_eq!(moves(&output), ["b1c3"]);
+  }
+
+  #[test]
+  fn setboard_and_castling() {
+    let input = "new\nforce\nsetboard r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\nusermove e1g1\nping 1\n\
+      setboard r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\nusermove O-O-O\nping 2\nsetboard nonsense\n";
+    let output = run(FirstMove::default(), input);
+    assert_eq!(output[..2], ["pong 1", "pong 2"]);
+    assert!(output[2].starts_with("tellusererror Illegal position"));
+  }
+
+  #[test]
+  fn chess960_castling_is_written_as_o_o() {
+    let position = "setboard 4k3/8/8/8/8/8/8/4K2R w K - 0 1\ngo\n";
+    let output = run(Castler, &format!("new\nforce\n{}", position));
+    assert_eq!(moves(&output), ["e1g1"]);
+    let output = run(Castler, &format!("new\nvariant fischerandom\nforce\n{}", position));
+    assert_eq!(moves(&output), ["O-O"]);
+    let output = run(FirstMove::default(), &format!("new\nvariant fischerandom\nforce\n{}", position)
+      .replace("go", "usermove O-O\nping 1"));
+    assert_eq!(output, ["pong 1"]);
+  }
+
+  #[test]
+  fn time_controls_set_the_limits() {
+    let agent = FirstMove::default();
+    let limits = agent.limits.clone();
+    let think_times = agent.think_times.clone();
+    let input = "new\nlevel 40 5 0\ntime 12000\notim 30000\nusermove e2e4\n\
+      sd 3\nst 2\nusermove d2d4\nlevel 0 2:30 12\nusermove c2c4\nlevel 40 bad 0\n";
+    let output = run(agent, input);
+    assert_eq!(output.last().unwrap(), "Error (bad time control): level 40 bad 0");
+    let seconds : Vec<u64> = think_times.lock().unwrap().iter().map(|t| {
+      (t.unwrap() + Duration::from_millis(500)).as_secs()
+    }).collect();
+    // 120 seconds left for 40 moves, then exactly 2 seconds, then
+    // 150 seconds for the rest of the game, plus most of the increment
+    assert_eq!(seconds, [3, 2, 5 + 9]);
+    assert_eq!(limits.lock().unwrap().iter().map(|l| l.depth).collect::<Vec<_>>(), [None, Some(3), Some(3)]);
+  }
+
+  #[test]
+  fn question_mark_makes_the_engine_move_now() {
+    let output = run(UntilStopped, "new\nusermove e2e4\n?\nping 1\n");
+    assert_eq!(output, ["move a7a6", "pong 1"]);
+  }
+
+  #[test]
+  fn game_end_is_announced_and_result_stops_the_engine() {
+    // Fool's mate, with black's mating move played by the user
+    let input = "new\nforce\nusermove f2f3\nusermove e7e5\nusermove g2g4\nusermove d8h4\nping 1\n";
+    let output = run(FirstMove::default(), input);
+    assert_eq!(output, ["0-1 {Black wins}", "pong 1"]);
+    let output = run(FirstMove::default(), "new\nresult 1/2-1/2 {Agreed}\nusermove e2e4\nping 1\n");
+    assert_eq!(output, ["pong 1"]);
+  }
+
+  #[test]
+  fn options_and_unknown_commands() {
+    let agent = FirstMove::default();
+    let output = run(agent, "option depth=4\noption depth=deep\noption width=3\nfly\nprotover 2\n");
+    assert_eq!(output[..3], ["Error (can't set option): depth=deep", "Error (can't set option): width=3",
+      "Error (unknown command): fly"]);
+    assert!(output[3].contains(" option=\"depth -spin 4 -1000000 1000000\""));
+  }
+}